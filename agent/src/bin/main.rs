@@ -24,10 +24,24 @@ fn load_plugins_metadata() -> Vec<PluginMetadata> {
     // plugins that work on every target
     let mut plugins = static_plugins![
         plugin_csv::CsvPlugin,
+        plugin_energy_attribution::EnergyAttributionPlugin,
         plugin_influxdb::InfluxDbPlugin,
         plugin_mongodb::MongoDbPlugin,
         plugin_relay::client::RelayClientPlugin,
         plugin_relay::server::RelayServerPlugin,
+        plugin_transforms::AggregationPlugin,
+        plugin_transforms::AlertingPlugin,
+        plugin_transforms::CarbonIntensityPlugin,
+        plugin_transforms::CounterDiffPlugin,
+        plugin_transforms::DerivedMetricsPlugin,
+        plugin_transforms::EnergyCostPlugin,
+        plugin_transforms::EnergyToPowerPlugin,
+        plugin_transforms::EnrichmentPlugin,
+        plugin_transforms::FilterPlugin,
+        plugin_transforms::RelabelPlugin,
+        plugin_transforms::ReorderPlugin,
+        plugin_transforms::StalenessPlugin,
+        plugin_transforms::TimeAlignmentPlugin,
     ];
 
     // plugins that only work on Linux
@@ -247,9 +261,15 @@ fn apply_pipeline_settings(args: &cli::Cli, config: &GeneralConfig, pipeline: &m
     if let Some(source_channel_size) = args.common.source_channel_size {
         *pipeline.source_channel_size() = source_channel_size;
     }
+    if let Some(cpus) = &args.common.normal_cpus {
+        pipeline.normal_cpu_affinity(cpus.clone());
+    }
+    if let Some(cpus) = &args.common.priority_cpus {
+        pipeline.high_priority_cpu_affinity(cpus.clone());
+    }
     if matches!(args.command, Some(cli::Command::Exec(_))) {
-        // the "exec" command requires event-based source trigger
-        pipeline.trigger_constraints_mut().allow_manual_trigger = true;
+        // the "exec" command requires the sources to be polled before and after the process
+        exec::prepare_pipeline(pipeline);
     }
 }
 
@@ -332,6 +352,7 @@ fn agent_version() -> String {
 /// To apply "advanced" tweaks, we combine the "derive" and "builder" APIs of clap.
 /// See https://docs.rs/clap/latest/clap/_derive/index.html#mixing-builder-and-derive-apis
 mod cli {
+    use alumet::pipeline::builder::CpuSet;
     use clap::{Args, Parser, Subcommand};
     use std::time::Duration;
 
@@ -472,6 +493,17 @@ mod cli {
         #[arg(long, env = "ALUMET_PRIORITY_THREADS")]
         pub priority_worker_threads: Option<usize>,
 
+        /// CPUs that the "normal" worker threads can run on, ex. `2-7` or `0,2,4`.
+        #[arg(long, env = "ALUMET_NORMAL_CPUS")]
+        pub normal_cpus: Option<CpuSet>,
+
+        /// CPUs that the "high-priority" worker threads can run on, ex. `0-1`.
+        ///
+        /// Pinning the high-priority threads to dedicated CPUs can improve the accuracy
+        /// of the measurement timing.
+        #[arg(long, env = "ALUMET_PRIORITY_CPUS")]
+        pub priority_cpus: Option<CpuSet>,
+
         /// Path to the output file (CSV plugin).
         #[arg(long)]
         pub output_file: Option<String>,
@@ -5,13 +5,7 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Context;
-
-use crate::{
-    pipeline::{control::ControlMessage, naming::matching::SourceNamePattern, MeasurementPipeline},
-    plugin::event::StartConsumerMeasurement,
-    resources::ResourceConsumer,
-};
+use crate::{pipeline, plugin::event::StartConsumerMeasurement, resources::ResourceConsumer};
 
 use super::{builder::ShutdownError, RunningAgent};
 use thiserror::Error;
@@ -30,9 +24,22 @@ pub enum WatchError {
     Shutdown(#[source] ShutdownError),
 }
 
+/// Configures the measurement pipeline for [`watch_process`].
+///
+/// Every managed source will be polled once when the pipeline starts, that is, before the process spawns,
+/// and one last time when the pipeline shuts down, that is, after the process exits.
+/// The sources can also be triggered manually, for instance by other plugins.
+pub fn prepare_pipeline(pipeline: &mut pipeline::Builder) {
+    let constraints = pipeline.trigger_constraints_mut();
+    constraints.allow_manual_trigger = true;
+    constraints.poll_at_startup = true;
+    constraints.poll_at_shutdown = true;
+}
+
 /// Spawns a process that runs `program args` and stops the measurement agent when it exits.
 ///
-/// The measurement sources are triggered before the process spawns and after it exits.
+/// The pipeline should have been configured with [`prepare_pipeline`], so that the measurement sources
+/// are polled before the process spawns and after it exits.
 ///
 /// After the process exits, the pipeline must stop within `shutdown_timeout`, or an error is returned.
 pub fn watch_process(
@@ -41,21 +48,11 @@ pub fn watch_process(
     args: Vec<String>,
     shutdown_timeout: Duration,
 ) -> Result<(), WatchError> {
-    // At least one measurement.
-    if let Err(e) = trigger_measurement_now(&agent.pipeline) {
-        log::error!("Could not trigger a first measurement before the child spawn: {e}");
-    }
-
     // Spawn the process and wait for it to exit.
     let exit_status = exec_child(program, args)?;
     log::info!("Child process exited with status {exit_status}, Alumet will now stop.");
 
-    // One last measurement.
-    if let Err(e) = trigger_measurement_now(&agent.pipeline) {
-        log::error!("Could not trigger one last measurement after the child exit: {e}");
-    }
-
-    // Stop the pipeline
+    // Stop the pipeline. This polls the sources one last time (see prepare_pipeline).
     agent.pipeline.control_handle().shutdown();
     agent.wait_for_shutdown(shutdown_timeout).map_err(WatchError::Shutdown)
 }
@@ -78,19 +75,3 @@ fn exec_child(external_command: String, args: Vec<String>) -> Result<ExitStatus,
     let status = p.wait().map_err(|e| WatchError::ProcessWait(pid, e))?;
     Ok(status)
 }
-
-// Triggers one measurement (on all sources that support manual trigger).
-fn trigger_measurement_now(pipeline: &MeasurementPipeline) -> anyhow::Result<()> {
-    use crate::pipeline::elements::source;
-
-    let control_handle = pipeline.control_handle();
-    let send_task = control_handle.send(ControlMessage::Source(
-        source::control::ControlMessage::TriggerManually(source::control::TriggerMessage {
-            matcher: SourceNamePattern::wildcard().into(),
-        }),
-    ));
-    pipeline
-        .async_runtime()
-        .block_on(send_task)
-        .context("failed to send TriggerMessage")
-}
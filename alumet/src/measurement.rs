@@ -71,6 +71,13 @@ pub struct MeasurementPoint {
     attributes: SmallVec<[(Cow<'static, str>, AttributeValue); 4]>,
 }
 
+/// Key of the attribute that marks a point as a staleness marker.
+///
+/// A staleness marker signals that its series has stopped producing measurements.
+/// Its value is meaningless (`NaN` for floats, `0` for integers): outputs should serialize it as "missing",
+/// so that it cannot be confused with a measured zero.
+pub const STALE_MARKER_ATTRIBUTE: &str = "__stale";
+
 /// A measurement of a clock.
 ///
 /// This opaque type is currently a wrapper around [`SystemTime`],
@@ -136,6 +143,18 @@ impl MeasurementPoint {
         self.attributes.push((key.into(), value.into()));
     }
 
+    /// Returns `true` if this point is a staleness marker, see [`STALE_MARKER_ATTRIBUTE`].
+    pub fn is_stale_marker(&self) -> bool {
+        self.attributes()
+            .any(|(k, v)| k == STALE_MARKER_ATTRIBUTE && matches!(v, AttributeValue::Bool(true)))
+    }
+
+    /// Removes the attribute with the given key, and returns its value (if the point had this attribute).
+    pub fn remove_attr(&mut self, key: &str) -> Option<AttributeValue> {
+        let index = self.attributes.iter().position(|(k, _)| k == key)?;
+        Some(self.attributes.remove(index).1)
+    }
+
     /// Sets an attribute on this measurement point, and returns self to allow for method chaining.
     /// If an attribute with the same key already exists, its value is replaced.
     pub fn with_attr<K: Into<Cow<'static, str>>, V: Into<AttributeValue>>(mut self, key: K, value: V) -> Self {
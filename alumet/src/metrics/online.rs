@@ -1,13 +1,19 @@
 //! Online interaction with the metric registry.
+//!
+//! While the pipeline is running, the registry is never modified in place.
+//! Instead, each registration publishes a new immutable [`RegistrySnapshot`]
+//! with a greater version number. Readers obtain the latest snapshot without
+//! taking any lock, and keep using it for as long as they want.
 
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, ops::Deref, sync::Arc};
 
 use anyhow::Context;
+use arc_swap::ArcSwap;
 use tokio::{
     runtime,
     sync::{
         mpsc::{self, Receiver},
-        oneshot, RwLock, RwLockReadGuard,
+        oneshot,
     },
     task::JoinHandle,
 };
@@ -19,7 +25,7 @@ use super::{
     registry::MetricRegistry,
 };
 use crate::pipeline::naming::namespace::Namespace2;
-use listener::{ListenerName, MetricListener, MetricListenerBuilder};
+use listener::{ListenerName, MetricListener, MetricListenerBuilder, MetricUpdate};
 
 /// A message that can be sent to the task that controls the [`MetricRegistry`],
 /// for instance via [`MetricSender`].
@@ -47,16 +53,65 @@ pub enum DuplicateStrategy {
 
 /// Controls the central registry of metrics.
 pub(crate) struct MetricRegistryControl {
-    registry: Arc<RwLock<MetricRegistry>>,
+    registry: Arc<ArcSwap<VersionedRegistry>>,
     listeners: Vec<(ListenerName, Box<dyn MetricListener>)>,
 }
 
+/// A registry and its version number.
+struct VersionedRegistry {
+    version: u64,
+    registry: MetricRegistry,
+}
+
+/// An immutable, versioned snapshot of the [`MetricRegistry`].
+///
+/// A snapshot is cheap to clone and never changes: metrics that are registered
+/// after its creation are only visible in the snapshots with a greater [`version`](Self::version).
+/// Use [`MetricReader::snapshot`] to get the latest one.
+#[derive(Clone)]
+pub struct RegistrySnapshot(Arc<VersionedRegistry>);
+
+impl RegistrySnapshot {
+    /// Returns the version of the registry at the time of the snapshot.
+    ///
+    /// The version is incremented every time new metrics are published.
+    pub fn version(&self) -> u64 {
+        self.0.version
+    }
+}
+
+impl Deref for RegistrySnapshot {
+    type Target = MetricRegistry;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.registry
+    }
+}
+
 pub mod listener {
     use crate::metrics::def::{Metric, RawMetricId};
 
-    /// A callback that gets notified of new metrics.
-    pub trait MetricListener: FnMut(Vec<(RawMetricId, Metric)>) -> anyhow::Result<()> + Send {}
-    impl<F> MetricListener for F where F: FnMut(Vec<(RawMetricId, Metric)>) -> anyhow::Result<()> + Send {}
+    use super::RegistrySnapshot;
+
+    /// A new version of the metric registry, as seen by a [`MetricListener`].
+    #[derive(Clone)]
+    pub struct MetricUpdate {
+        /// The new version of the registry, which contains the new metrics.
+        pub snapshot: RegistrySnapshot,
+        /// The metrics that have been added in this version.
+        pub new_metrics: Vec<(RawMetricId, Metric)>,
+    }
+
+    impl MetricUpdate {
+        /// Returns the version number of the new registry.
+        pub fn version(&self) -> u64 {
+            self.snapshot.version()
+        }
+    }
+
+    /// A callback that gets notified of new versions of the metric registry.
+    pub trait MetricListener: FnMut(MetricUpdate) -> anyhow::Result<()> + Send {}
+    impl<F> MetricListener for F where F: FnMut(MetricUpdate) -> anyhow::Result<()> + Send {}
 
     pub(super) struct BuildContext<'a> {
         pub(super) rt: &'a tokio::runtime::Handle,
@@ -99,8 +154,9 @@ impl MetricRegistryControl {
     /// [`start`](Self::start) is called. They do _not_ get notified of the metrics
     /// that are initially present in the registry.
     pub fn new(registry: MetricRegistry) -> Self {
+        let initial = VersionedRegistry { version: 0, registry };
         Self {
-            registry: Arc::new(RwLock::new(registry)),
+            registry: Arc::new(ArcSwap::from_pointee(initial)),
             listeners: Vec::new(),
         }
     }
@@ -140,27 +196,31 @@ impl MetricRegistryControl {
     }
 
     async fn handle_message(&mut self, msg: ControlMessage) {
-        fn call_listener(name: &ListenerName, listener: &mut dyn MetricListener, metrics: Vec<(RawMetricId, Metric)>) {
-            let n = metrics.len();
-            if let Err(e) = listener(metrics) {
+        fn call_listener(name: &ListenerName, listener: &mut dyn MetricListener, update: MetricUpdate) {
+            let n = update.new_metrics.len();
+            let version = update.version();
+            if let Err(e) = listener(update) {
                 let plugin = &name.plugin;
                 let listener = &name.name;
-                log::error!("Error in metric listener {plugin}/{listener} (called on {n} metrics): {e}",);
+                log::error!(
+                    "Error in metric listener {plugin}/{listener} (called on {n} metrics, registry version {version}): {e}"
+                );
             }
         }
 
         match msg {
             ControlMessage::RegisterMetrics(metrics, dup, reply_to) => {
-                // Use an RCU (Read, Copy, Update) scheme to modify the registry with the minimal
-                // amount of blocking for readers and writers.
+                // Use an RCU (Read, Copy, Update) scheme to modify the registry without blocking the readers.
+                // The readers that hold a previous snapshot are unaffected by the update.
                 //
-                // NOTE: Since we don't take the write lock when copying, we must ensure that only one thread
+                // NOTE: Since we don't use a compare-and-swap loop, we must ensure that only one thread
                 // is performing the copy and the update (otherwise we would end up with multiple
                 // desynchronized copies). This is achieved by handling all the messages in one
                 // task, thus making their processing sequential.
 
                 // read and copy
-                let mut copy = (*self.registry.read().await).clone();
+                let current = self.registry.load_full();
+                let mut copy = current.registry.clone();
                 // modify the copy
                 let res = match dup {
                     DuplicateStrategy::Error => copy.extend(metrics.clone()),
@@ -170,22 +230,40 @@ impl MetricRegistryControl {
                         .map(|res| Ok(res))
                         .collect(),
                 };
-                // update
-                *self.registry.write().await = copy;
-
-                // call listeners
-                let mut registered_metrics = Vec::with_capacity(res.len());
-                for (metric, maybe_id) in metrics.into_iter().zip(res.iter()) {
-                    if let Ok(id) = maybe_id {
-                        registered_metrics.push((*id, metric));
-                    }
-                }
-                match &mut self.listeners[..] {
-                    [] => (),
-                    [(name, listener)] => call_listener(name, listener, registered_metrics),
-                    listeners => {
-                        for (name, listener) in listeners {
-                            call_listener(name, listener, registered_metrics.clone());
+                // Find the metrics that are really new. With DuplicateStrategy::Rename, some ids
+                // can refer to metrics that already existed, and some names can have been changed.
+                let new_metrics: Vec<(RawMetricId, Metric)> = res
+                    .iter()
+                    .filter_map(|maybe_id| maybe_id.as_ref().ok())
+                    .filter(|id| current.registry.by_id(*id).is_none())
+                    .map(|id| (*id, copy.by_id(id).unwrap().clone()))
+                    .collect();
+
+                // update, only if something has changed (the version must only change when the content changes)
+                if !new_metrics.is_empty() {
+                    let new_version = Arc::new(VersionedRegistry {
+                        version: current.version + 1,
+                        registry: copy,
+                    });
+                    self.registry.store(new_version.clone());
+                    log::trace!(
+                        "Published version {} of the metric registry ({} new metrics).",
+                        new_version.version,
+                        new_metrics.len()
+                    );
+
+                    // call listeners
+                    let update = MetricUpdate {
+                        snapshot: RegistrySnapshot(new_version),
+                        new_metrics,
+                    };
+                    match &mut self.listeners[..] {
+                        [] => (),
+                        [(name, listener)] => call_listener(name, listener, update),
+                        listeners => {
+                            for (name, listener) in listeners {
+                                call_listener(name, listener, update.clone());
+                            }
                         }
                     }
                 }
@@ -228,13 +306,15 @@ impl MetricRegistryControl {
     }
 }
 
-/// Read-write access to a [`MetricRegistry`] from multiple threads.
+/// Shared access to the latest version of the [`MetricRegistry`] from multiple threads.
 #[derive(Clone)]
 pub struct MetricAccess {
-    inner: Arc<RwLock<MetricRegistry>>,
+    inner: Arc<ArcSwap<VersionedRegistry>>,
 }
 
 /// Read-only access to a [`MetricRegistry`].
+///
+/// Reading never blocks and never prevents the registration of new metrics.
 #[derive(Clone)]
 pub struct MetricReader(MetricAccess);
 
@@ -286,14 +366,9 @@ impl Debug for SendError {
 }
 
 impl MetricAccess {
-    /// Provides shared read access to the metric registry.
-    pub async fn read(&self) -> RwLockReadGuard<MetricRegistry> {
-        self.inner.read().await
-    }
-
-    /// Provides exclusive write access to the metric registry.
-    pub async fn write(&self) -> tokio::sync::RwLockWriteGuard<MetricRegistry> {
-        self.inner.write().await
+    /// Returns a snapshot of the latest version of the metric registry.
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        RegistrySnapshot(self.inner.load_full())
     }
 
     pub fn into_read_only(self) -> MetricReader {
@@ -302,16 +377,18 @@ impl MetricAccess {
 }
 
 impl MetricReader {
-    /// Provides shared read access to the metric registry.
-    pub async fn read(&self) -> RwLockReadGuard<MetricRegistry> {
-        self.0.read().await
+    /// Returns a snapshot of the latest version of the metric registry.
+    ///
+    /// This is lock-free, and can be called from both async and blocking code.
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        self.0.snapshot()
     }
 
-    /// Provides shared read access to the metric registry, **in a blocking way**.
+    /// Returns the version of the latest metric registry.
     ///
-    /// Only use this _outside_ of an async runtime.
-    pub(crate) fn blocking_read(&self) -> RwLockReadGuard<MetricRegistry> {
-        self.0.inner.blocking_read()
+    /// Compare it with [`RegistrySnapshot::version`] to know whether a snapshot is outdated.
+    pub fn version(&self) -> u64 {
+        self.0.inner.load().version
     }
 }
 
@@ -382,3 +459,85 @@ impl MetricSender {
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        measurement::WrappedMeasurementType,
+        metrics::{def::Metric, registry::MetricRegistry},
+        pipeline::naming::namespace::Namespace2,
+        units::Unit,
+    };
+
+    use super::{
+        listener::{MetricListener, MetricListenerBuildContext, MetricListenerBuilder, MetricUpdate},
+        DuplicateStrategy, MetricRegistryControl,
+    };
+
+    fn metric(name: &str) -> Metric {
+        Metric {
+            name: name.to_owned(),
+            description: "".to_owned(),
+            value_type: WrappedMeasurementType::U64,
+            unit: Unit::Joule.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_versioned() {
+        let notified = Arc::new(Mutex::new(Vec::new()));
+        let notified_l = notified.clone();
+        let mut listeners: Namespace2<Box<dyn MetricListenerBuilder>> = Namespace2::new();
+        let builder: Box<dyn MetricListenerBuilder> = Box::new(move |_ctx: &mut dyn MetricListenerBuildContext| {
+            let listener = move |update: MetricUpdate| {
+                let names: Vec<String> = update.new_metrics.into_iter().map(|(_, m)| m.name).collect();
+                notified_l.lock().unwrap().push((update.snapshot.version(), names));
+                Ok(())
+            };
+            Ok(Box::new(listener) as Box<dyn MetricListener>)
+        });
+        listeners
+            .add("plugin".to_owned(), "listener".to_owned(), builder)
+            .unwrap();
+
+        let mut control = MetricRegistryControl::new(MetricRegistry::new());
+        let rt = tokio::runtime::Handle::current();
+        control.create_listeners(listeners, &rt).unwrap();
+        let shutdown = CancellationToken::new();
+        let (sender, access, task) = control.start(shutdown.clone(), &rt);
+        let reader = access.into_read_only();
+
+        let initial = reader.snapshot();
+        assert_eq!(initial.version(), 0);
+        assert!(initial.is_empty());
+
+        // new metric: new version
+        let res = sender
+            .create_metrics(vec![metric("a")], DuplicateStrategy::Error)
+            .await
+            .unwrap();
+        assert!(res[0].is_ok());
+        let v1 = reader.snapshot();
+        assert_eq!(v1.version(), 1);
+        assert_eq!(reader.version(), 1);
+        assert!(v1.by_name("a").is_some());
+        // the old snapshot is unchanged
+        assert!(initial.by_name("a").is_none());
+
+        // duplicate metric: no new version
+        let res = sender
+            .create_metrics(vec![metric("a")], DuplicateStrategy::Error)
+            .await
+            .unwrap();
+        assert!(res[0].is_err());
+        assert_eq!(reader.version(), 1);
+
+        shutdown.cancel();
+        task.await.unwrap();
+        assert_eq!(*notified.lock().unwrap(), vec![(1, vec![String::from("a")])]);
+    }
+}
@@ -1,4 +1,5 @@
 //! Construction of measurement pipelines.
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context};
@@ -19,12 +20,14 @@ use crate::pipeline::elements::output::OutputContext;
 use crate::pipeline::elements::source::control::SourceControl;
 use crate::pipeline::elements::transform::control::TransformControl;
 use crate::pipeline::util::channel;
+pub use crate::pipeline::util::threading::CpuSet;
 use crate::pipeline::Output;
 
+use super::elements::output::batch::BatchSettings;
 use super::elements::output::builder::OutputBuilder;
 use super::elements::source::builder::SourceBuilder;
 use super::elements::source::trigger::TriggerConstraints;
-use super::elements::transform::builder::TransformBuilder;
+use super::elements::transform::builder::AnyTransformBuilder;
 use super::error::PipelineError;
 use super::naming::{
     namespace::{DuplicateNameError, Namespace2},
@@ -53,9 +56,12 @@ pub struct MeasurementPipeline {
 pub struct Builder {
     // Pipeline elements, by plugin and name. The tuple (plugin, element name) is enforced to be unique.
     sources: Namespace2<SourceBuilder>,
-    transforms: Namespace2<Box<dyn TransformBuilder>>,
+    transforms: Namespace2<AnyTransformBuilder>,
     outputs: Namespace2<OutputBuilder>,
 
+    /// Batching settings, for the outputs that use batching.
+    output_batching: FxHashMap<OutputName, BatchSettings>,
+
     /// Order of the transforms, manually specified.
     transforms_order: Option<Vec<TransformName>>,
     /// Order in which the transforms have been added, to use if `transforms_order` is `None`.
@@ -74,6 +80,8 @@ pub struct Builder {
     // tokio::Runtime settings.
     threads_normal: Option<usize>,
     threads_high_priority: Option<usize>,
+    cpus_normal: Option<CpuSet>,
+    cpus_high_priority: Option<CpuSet>,
 }
 
 /// Allows to inspect the content of a pipeline builder.
@@ -89,6 +97,7 @@ impl Builder {
             sources: Namespace2::new(),
             transforms: Namespace2::new(),
             outputs: Namespace2::new(),
+            output_batching: FxHashMap::default(),
             transforms_order: None,
             default_transforms_order: Vec::new(),
             trigger_constraints: TriggerConstraints::default(),
@@ -97,6 +106,8 @@ impl Builder {
             metric_listeners: Namespace2::new(),
             threads_normal: None, // default to the number of cores
             threads_high_priority: None,
+            cpus_normal: None, // no restriction
+            cpus_high_priority: None,
         }
     }
 
@@ -140,11 +151,13 @@ impl Builder {
     }
 
     /// Adds a transform function to the pipeline, with a dedicated builder.
+    ///
+    /// The transform can be synchronous or asynchronous.
     pub fn add_transform_builder(
         &mut self,
         plugin: PluginName,
         name: &str,
-        builder: Box<dyn TransformBuilder>,
+        builder: AnyTransformBuilder,
     ) -> Result<TransformKey, DuplicateNameError> {
         match self.transforms.add(plugin.0.clone(), name.to_owned(), builder) {
             Ok(_) => {
@@ -169,6 +182,14 @@ impl Builder {
         }
     }
 
+    /// Enables the batching of measurements for the given output.
+    ///
+    /// The output will receive the measurements in batches, according to the `settings`.
+    /// If batching was already enabled for this output, the settings are replaced.
+    pub fn set_output_batching(&mut self, output: OutputName, settings: BatchSettings) {
+        self.output_batching.insert(output, settings);
+    }
+
     /// Sets the number of non-high-priority threads to use.
     ///
     /// # Default
@@ -185,6 +206,26 @@ impl Builder {
         self.threads_high_priority = Some(n);
     }
 
+    /// Restricts the non-high-priority threads to the given CPUs.
+    ///
+    /// If the number of threads is not set, one thread per CPU of the set is spawned.
+    ///
+    /// # Default
+    /// By default, the threads can run on any CPU.
+    pub fn normal_cpu_affinity(&mut self, cpus: CpuSet) {
+        self.cpus_normal = Some(cpus);
+    }
+
+    /// Restricts the high-priority threads to the given CPUs.
+    ///
+    /// If the number of threads is not set, one thread per CPU of the set is spawned.
+    ///
+    /// # Default
+    /// By default, the threads can run on any CPU.
+    pub fn high_priority_cpu_affinity(&mut self, cpus: CpuSet) {
+        self.cpus_high_priority = Some(cpus);
+    }
+
     /// Sets the execution order of the transforms.
     ///
     /// If this method is not called, the default order is the one
@@ -202,10 +243,7 @@ impl Builder {
     }
 
     /// Replaces each transform builder with the result of the closure `f`.
-    pub fn replace_transforms(
-        &mut self,
-        mut f: impl FnMut(TransformName, Box<dyn TransformBuilder>) -> Box<dyn TransformBuilder>,
-    ) {
+    pub fn replace_transforms(&mut self, mut f: impl FnMut(TransformName, AnyTransformBuilder) -> AnyTransformBuilder) {
         self.transforms.replace_each(|(plugin, transform), builder| {
             let name = TransformName::new(plugin.to_owned(), transform.to_owned());
             f(name, builder)
@@ -244,9 +282,9 @@ impl Builder {
 
         /// Take the builders out of `transforms` by following the given `order`.
         fn take_transforms_in_order(
-            mut transforms: Namespace2<Box<dyn TransformBuilder>>,
+            mut transforms: Namespace2<AnyTransformBuilder>,
             order: Vec<TransformName>,
-        ) -> anyhow::Result<Vec<(TransformName, Box<dyn TransformBuilder>)>> {
+        ) -> anyhow::Result<Vec<(TransformName, AnyTransformBuilder)>> {
             let res = order
                 .into_iter()
                 .map(|name| {
@@ -268,23 +306,39 @@ impl Builder {
             Ok(res)
         }
 
+        // Check the CPU affinity before starting any thread.
+        if let Some(cpus) = &self.cpus_normal {
+            cpus.check_online()
+                .context("invalid CPU affinity for the normal threads")?;
+        }
+        if let Some(cpus) = &self.cpus_high_priority {
+            cpus.check_online()
+                .context("invalid CPU affinity for the high-priority threads")?;
+        }
+
         // Tokio runtime backed by "real-time" high priority threads.
         let rt_priority: Option<Runtime> = if self.threads_high_priority == Some(0) {
             None
         } else {
-            util::threading::build_priority_runtime(self.threads_high_priority).ok()
+            let n_threads = self
+                .threads_high_priority
+                .or_else(|| self.cpus_high_priority.as_ref().map(|cpus| cpus.len()));
+            util::threading::build_priority_runtime(n_threads, self.cpus_high_priority.clone()).ok()
         };
 
         // Tokio runtime backed by usual threads (default priority).
         let rt_normal: Runtime = {
             let n_threads = if let Some(n) = self.threads_normal {
                 Some(n)
+            } else if let Some(cpus) = &self.cpus_normal {
+                Some(cpus.len())
             } else if rt_priority.is_some() && self.threads_high_priority.is_none() {
                 Some(2)
             } else {
                 None
             };
-            util::threading::build_normal_runtime(n_threads).context("could not build the multithreaded Runtime")?
+            util::threading::build_normal_runtime(n_threads, self.cpus_normal.clone())
+                .context("could not build the multithreaded Runtime")?
         };
         let rt_handle = rt_normal.handle();
 
@@ -295,7 +349,6 @@ impl Builder {
         let pipeline_shutdown_finalize = CancellationToken::new();
 
         // --- Metric registry (one for the entire pipeline) ---
-        // Note: We can modify it without sending a message thanks to MetricAccess::write().
         let mut registry_control = MetricRegistryControl::new(self.metrics);
 
         // Before it starts, register the initial listeners.
@@ -329,7 +382,12 @@ impl Builder {
 
             // Outputs
             let out_rx_provider = channel::ReceiverProvider::from(in_rx);
-            output_control = OutputControl::new(out_rx_provider, rt_handle.clone(), metrics_r.clone());
+            output_control = OutputControl::new(
+                out_rx_provider,
+                rt_handle.clone(),
+                metrics_r.clone(),
+                self.output_batching,
+            );
             output_control
                 .blocking_create_outputs(self.outputs)
                 .context("output creation failed")?;
@@ -338,11 +396,16 @@ impl Builder {
             transform_control = TransformControl::empty();
         } else {
             // Broadcast queue: transforms -> outputs
-            let out_tx = broadcast::Sender::<MeasurementBuffer>::new(self.source_channel_size);
+            let out_tx = broadcast::Sender::<Arc<MeasurementBuffer>>::new(self.source_channel_size);
 
             // Outputs
             let out_rx_provider = channel::ReceiverProvider::from(out_tx.clone());
-            output_control = OutputControl::new(out_rx_provider, rt_handle.clone(), metrics_r.clone());
+            output_control = OutputControl::new(
+                out_rx_provider,
+                rt_handle.clone(),
+                metrics_r.clone(),
+                self.output_batching,
+            );
             output_control
                 .blocking_create_outputs(self.outputs)
                 .context("output creation failed")?;
@@ -350,8 +413,14 @@ impl Builder {
             // Transforms
             let order = self.transforms_order.unwrap_or(self.default_transforms_order);
             let transforms = take_transforms_in_order(self.transforms, order)?;
-            transform_control =
-                TransformControl::with_transforms(transforms, metrics_r.clone(), in_rx, out_tx, rt_handle)?;
+            transform_control = TransformControl::with_transforms(
+                transforms,
+                metrics_r.clone(),
+                in_rx,
+                out_tx,
+                self.source_channel_size,
+                rt_handle,
+            )?;
         };
 
         // Sources, last in order not to loose any measurement if they start measuring right away.
@@ -1,5 +1,7 @@
 //! Implementation and control of output tasks.
 
+/// Generic batching of the measurements sent to outputs.
+pub mod batch;
 /// Lazy creation of outputs.
 pub mod builder;
 pub mod control;
@@ -0,0 +1,281 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint};
+use crate::pipeline::util::channel::{MeasurementReceiver, RecvError, StreamRecvError};
+
+/// Settings of the batching layer of an output.
+///
+/// When batching is enabled for an output, the measurements are accumulated before being passed to the output,
+/// which receives one large buffer instead of many small ones.
+/// The batch is flushed as soon as one of the limits is reached, and when the pipeline shuts down.
+///
+/// A limit set to `None` is not checked. If all the limits are `None`, the measurements are only
+/// flushed on shutdown.
+///
+/// The settings can be read from the configuration of a plugin, with `max_delay` as a human-readable
+/// duration such as `"1s"`.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use alumet::pipeline::elements::output::batch::BatchSettings;
+///
+/// let settings = BatchSettings::default()
+///     .max_points(10_000)
+///     .max_delay(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchSettings {
+    /// Maximum number of measurement points in a batch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_points: Option<usize>,
+    /// Maximum (approximate) size of a batch in memory, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<usize>,
+    /// Maximum amount of time between the reception of the first measurement of a batch and its flush.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub max_delay: Option<Duration>,
+}
+
+impl BatchSettings {
+    /// Sets the maximum number of measurement points in a batch.
+    pub fn max_points(mut self, n: usize) -> Self {
+        self.max_points = Some(n);
+        self
+    }
+
+    /// Sets the maximum (approximate) size of a batch in memory, in bytes.
+    pub fn max_bytes(mut self, n: usize) -> Self {
+        self.max_bytes = Some(n);
+        self
+    }
+
+    /// Sets the maximum amount of time to wait before flushing a batch.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = Some(delay);
+        self
+    }
+}
+
+/// Returns the approximate amount of memory used by a measurement point.
+pub(crate) fn estimated_size(point: &MeasurementPoint) -> usize {
+    let attributes: usize = point
+        .attributes()
+        .map(|(key, value)| {
+            let value_len = match value {
+                AttributeValue::String(s) => s.len(),
+                _ => 0,
+            };
+            key.len() + value_len
+        })
+        .sum();
+    std::mem::size_of::<MeasurementPoint>() + attributes
+}
+
+/// A receiver that accumulates the measurements of another receiver into batches.
+///
+/// `recv` is cancel-safe: the pending batch is stored in the receiver, not in the future.
+pub(crate) struct BatchedReceiver<R: MeasurementReceiver> {
+    inner: R,
+    settings: BatchSettings,
+    pending: MeasurementBuffer,
+    pending_bytes: usize,
+    /// When the pending batch must be flushed.
+    deadline: Option<Instant>,
+    /// Set to true when the inner receiver has been closed.
+    closed: bool,
+}
+
+impl<R: MeasurementReceiver> BatchedReceiver<R> {
+    pub fn new(inner: R, settings: BatchSettings) -> Self {
+        let capacity = settings.max_points.unwrap_or(0);
+        Self {
+            inner,
+            settings,
+            pending: MeasurementBuffer::with_capacity(capacity),
+            pending_bytes: 0,
+            deadline: None,
+            closed: false,
+        }
+    }
+
+    /// Adds the measurements to the pending batch.
+    ///
+    /// The buffer is only copied if it is shared with another output.
+    ///
+    /// Returns `true` if the batch is full and must be flushed.
+    fn push(&mut self, buf: Arc<MeasurementBuffer>) -> bool {
+        let mut buf = Arc::unwrap_or_clone(buf);
+        if self.pending.is_empty() {
+            self.deadline = self.settings.max_delay.map(|d| Instant::now() + d);
+        }
+        if self.settings.max_bytes.is_some() {
+            self.pending_bytes += buf.iter().map(estimated_size).sum::<usize>();
+        }
+        self.pending.merge(&mut buf);
+
+        let too_many_points = self.settings.max_points.is_some_and(|max| self.pending.len() >= max);
+        let too_many_bytes = self.settings.max_bytes.is_some_and(|max| self.pending_bytes >= max);
+        too_many_points || too_many_bytes
+    }
+
+    /// Takes the pending batch, leaving an empty batch in its place.
+    fn take(&mut self) -> Arc<MeasurementBuffer> {
+        let capacity = self.settings.max_points.unwrap_or(0);
+        self.pending_bytes = 0;
+        self.deadline = None;
+        Arc::new(std::mem::replace(
+            &mut self.pending,
+            MeasurementBuffer::with_capacity(capacity),
+        ))
+    }
+}
+
+impl<R: MeasurementReceiver> MeasurementReceiver for BatchedReceiver<R> {
+    async fn recv(&mut self) -> Result<Arc<MeasurementBuffer>, RecvError> {
+        loop {
+            if self.closed {
+                // Flush what remains, then report the closure.
+                return if self.pending.is_empty() {
+                    Err(RecvError::Closed)
+                } else {
+                    Ok(self.take())
+                };
+            }
+            let deadline = self.deadline;
+            tokio::select! {
+                received = self.inner.recv() => {
+                    match received {
+                        Ok(buf) => {
+                            if self.push(buf) {
+                                return Ok(self.take());
+                            }
+                        }
+                        Err(RecvError::Closed) => {
+                            self.closed = true;
+                        }
+                        Err(lagged) => return Err(lagged),
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    return Ok(self.take());
+                }
+            }
+        }
+    }
+
+    fn into_stream(self) -> impl Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>> {
+        futures::stream::unfold(self, |mut rx| async move {
+            match rx.recv().await {
+                Ok(buf) => Some((Ok(buf), rx)),
+                Err(RecvError::Lagged(n)) => Some((Err(StreamRecvError::Lagged(n)), rx)),
+                Err(RecvError::Closed) => None,
+            }
+        })
+    }
+}
+
+/// Adapts a stream of measurements to the [`MeasurementReceiver`] interface.
+pub(crate) struct StreamReceiver<S>(pub S);
+
+impl<S> MeasurementReceiver for StreamReceiver<S>
+where
+    S: Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>> + Unpin,
+{
+    async fn recv(&mut self) -> Result<Arc<MeasurementBuffer>, RecvError> {
+        match self.0.next().await {
+            Some(Ok(buf)) => Ok(buf),
+            Some(Err(StreamRecvError::Lagged(n))) => Err(RecvError::Lagged(n)),
+            None => Err(RecvError::Closed),
+        }
+    }
+
+    fn into_stream(self) -> impl Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use super::{BatchSettings, BatchedReceiver};
+    use crate::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::def::RawMetricId,
+        pipeline::util::channel::{MeasurementReceiver, RecvError},
+        resources::{Resource, ResourceConsumer},
+    };
+
+    fn buffer(n: usize) -> MeasurementBuffer {
+        (0..n)
+            .map(|i| {
+                MeasurementPoint::new_untyped(
+                    Timestamp::now(),
+                    RawMetricId(0),
+                    Resource::LocalMachine,
+                    ResourceConsumer::LocalMachine,
+                    WrappedMeasurementValue::U64(i as u64),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn flush_on_max_points() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut rx = BatchedReceiver::new(rx, BatchSettings::default().max_points(5));
+        tx.send(buffer(2)).await.unwrap();
+        tx.send(buffer(2)).await.unwrap();
+        tx.send(buffer(2)).await.unwrap();
+        tx.send(buffer(1)).await.unwrap();
+        assert_eq!(rx.recv().await.ok().unwrap().len(), 6);
+        drop(tx);
+        // flush on close
+        assert_eq!(rx.recv().await.ok().unwrap().len(), 1);
+        assert!(matches!(rx.recv().await, Err(RecvError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn flush_on_max_delay() {
+        let (tx, rx) = mpsc::channel(16);
+        let settings = BatchSettings::default()
+            .max_points(100)
+            .max_delay(Duration::from_millis(50));
+        let mut rx = BatchedReceiver::new(rx, settings);
+        tx.send(buffer(3)).await.unwrap();
+        tx.send(buffer(3)).await.unwrap();
+        assert_eq!(rx.recv().await.ok().unwrap().len(), 6);
+        drop(tx);
+        assert!(matches!(rx.recv().await, Err(RecvError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn flush_on_max_bytes() {
+        let (tx, rx) = mpsc::channel(16);
+        let one_point = super::estimated_size(buffer(1).iter().next().unwrap());
+        let mut rx = BatchedReceiver::new(rx, BatchSettings::default().max_bytes(one_point * 3));
+        tx.send(buffer(2)).await.unwrap();
+        tx.send(buffer(2)).await.unwrap();
+        assert_eq!(rx.recv().await.ok().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn deserialize_settings() {
+        let settings: BatchSettings = toml::from_str("max_points = 5000\nmax_delay = \"1s\"").unwrap();
+        assert_eq!(
+            settings,
+            BatchSettings::default()
+                .max_points(5000)
+                .max_delay(Duration::from_secs(1))
+        );
+        assert!(toml::from_str::<BatchSettings>("max_size = 1").is_err());
+    }
+}
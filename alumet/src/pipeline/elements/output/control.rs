@@ -1,4 +1,5 @@
 use anyhow::Context;
+use fxhash::FxHashMap;
 use std::sync::{
     atomic::{AtomicU8, Ordering},
     Arc, Mutex,
@@ -21,6 +22,7 @@ use crate::pipeline::util::{
 use crate::{measurement::MeasurementBuffer, pipeline::error::PipelineError};
 
 use super::{
+    batch::{BatchSettings, BatchedReceiver, StreamReceiver},
     builder::{self, OutputBuilder},
     run::run_blocking_output,
 };
@@ -108,10 +110,18 @@ struct TaskManager {
     rt_normal: runtime::Handle,
 
     metrics: MetricReader,
+
+    /// Batching settings of the outputs that have requested it.
+    batching: FxHashMap<OutputName, BatchSettings>,
 }
 
 impl OutputControl {
-    pub fn new(rx_provider: channel::ReceiverProvider, rt_normal: runtime::Handle, metrics: MetricReader) -> Self {
+    pub fn new(
+        rx_provider: channel::ReceiverProvider,
+        rt_normal: runtime::Handle,
+        metrics: MetricReader,
+        batching: FxHashMap<OutputName, BatchSettings>,
+    ) -> Self {
         Self {
             tasks: TaskManager {
                 spawned_tasks: JoinSet::new(),
@@ -119,13 +129,14 @@ impl OutputControl {
                 rx_provider,
                 rt_normal,
                 metrics: metrics.clone(),
+                batching,
             },
             metrics,
         }
     }
 
     pub fn blocking_create_outputs(&mut self, outputs: Namespace2<OutputBuilder>) -> anyhow::Result<()> {
-        let metrics = self.metrics.blocking_read();
+        let metrics = self.metrics.snapshot();
         for ((plugin, output_name), builder) in outputs {
             let mut ctx = builder::OutputBuildContext {
                 metrics: &metrics,
@@ -137,12 +148,15 @@ impl OutputControl {
                 .create_output(&mut ctx, full_name, builder)
                 .inspect_err(|e| log::error!("Error in output creation requested by plugin {plugin}: {e:#}"))?;
         }
+        for name in self.tasks.batching.keys() {
+            log::warn!("Batching has been configured for output {name}, but this output does not exist.");
+        }
         Ok(())
     }
 
     #[allow(unused)]
     pub async fn create_output(&mut self, name: OutputName, builder: builder::SendOutputBuilder) {
-        let metrics = self.metrics.read().await;
+        let metrics = self.metrics.snapshot();
         let mut ctx = builder::OutputBuildContext {
             metrics: &metrics,
             metrics_r: &self.metrics,
@@ -211,6 +225,7 @@ impl TaskManager {
         // Create the necessary context.
         let rx = self.rx_provider.get(); // to receive measurements
         let metrics = self.metrics.clone(); // to read metric definitions
+        let batching = self.batching.remove(&name);
 
         // Create and store the task controller.
         let config = Arc::new(SharedOutputConfig::new());
@@ -222,13 +237,23 @@ impl TaskManager {
         let guarded_output = Arc::new(Mutex::new(output));
 
         // Spawn the task on the runtime.
-        match rx {
+        match (rx, batching) {
             // Specialize on the kind of receiver at compile-time (for performance).
-            channel::ReceiverEnum::Broadcast(rx) => {
+            (channel::ReceiverEnum::Broadcast(rx), None) => {
+                let task = run_blocking_output(name, guarded_output, rx, metrics, shared_config);
+                self.spawned_tasks.spawn_on(task, &self.rt_normal);
+            }
+            (channel::ReceiverEnum::Single(rx), None) => {
                 let task = run_blocking_output(name, guarded_output, rx, metrics, shared_config);
                 self.spawned_tasks.spawn_on(task, &self.rt_normal);
             }
-            channel::ReceiverEnum::Single(rx) => {
+            (channel::ReceiverEnum::Broadcast(rx), Some(settings)) => {
+                let rx = BatchedReceiver::new(rx, settings);
+                let task = run_blocking_output(name, guarded_output, rx, metrics, shared_config);
+                self.spawned_tasks.spawn_on(task, &self.rt_normal);
+            }
+            (channel::ReceiverEnum::Single(rx), Some(settings)) => {
+                let rx = BatchedReceiver::new(rx, settings);
                 let task = run_blocking_output(name, guarded_output, rx, metrics, shared_config);
                 self.spawned_tasks.spawn_on(task, &self.rt_normal);
             }
@@ -246,20 +271,30 @@ impl TaskManager {
         use channel::MeasurementReceiver;
 
         fn box_controlled_stream<
-            S: futures::Stream<Item = Result<MeasurementBuffer, channel::StreamRecvError>> + Send + 'static,
+            S: futures::Stream<Item = Result<Arc<MeasurementBuffer>, channel::StreamRecvError>> + Send + 'static,
         >(
             stream: S,
+            batching: Option<BatchSettings>,
         ) -> (AsyncOutputStream, Arc<SharedStreamState>) {
             let stream = Box::pin(ControlledStream::new(stream));
             let state = stream.state();
-            (AsyncOutputStream(stream), state)
+            match batching {
+                None => (AsyncOutputStream(stream), state),
+                Some(settings) => {
+                    // The batching layer wraps the controlled stream, so that the pending batch
+                    // is flushed when the stream is stopped.
+                    let batched = BatchedReceiver::new(StreamReceiver(stream), settings).into_stream();
+                    (AsyncOutputStream(Box::pin(batched)), state)
+                }
+            }
         }
 
         // For async outputs, we need to build the stream first
         let rx = self.rx_provider.get();
+        let batching = self.batching.remove(&name);
         let (stream, state) = match rx {
-            channel::ReceiverEnum::Broadcast(receiver) => box_controlled_stream(receiver.into_stream()),
-            channel::ReceiverEnum::Single(receiver) => box_controlled_stream(receiver.into_stream()),
+            channel::ReceiverEnum::Broadcast(receiver) => box_controlled_stream(receiver.into_stream(), batching),
+            channel::ReceiverEnum::Single(receiver) => box_controlled_stream(receiver.into_stream(), batching),
         };
 
         // Create the output
@@ -1,4 +1,4 @@
-use std::{future::Future, pin::Pin};
+use std::{future::Future, pin::Pin, sync::Arc};
 
 use crate::{measurement::MeasurementBuffer, metrics::registry::MetricRegistry};
 
@@ -11,8 +11,12 @@ pub trait Output: Send {
 }
 
 /// An asynchronous stream of measurements, to be used by an asynchronous output.
+///
+/// The buffers are shared with the other outputs, hence the [`Arc`].
+/// If you need to own the buffer, use [`Arc::unwrap_or_clone`]: it only copies the buffer
+/// if it is still used by another output.
 pub struct AsyncOutputStream(
-    pub Pin<Box<dyn futures::Stream<Item = Result<MeasurementBuffer, StreamRecvError>> + Send>>,
+    pub Pin<Box<dyn futures::Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>> + Send>>,
 ); // TODO make opaque?
 
 pub use crate::pipeline::util::channel::StreamRecvError;
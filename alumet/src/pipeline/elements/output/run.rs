@@ -35,14 +35,14 @@ pub async fn run_blocking_output<Rx: channel::MeasurementReceiver>(
         name: &OutputName,
         output: Arc<Mutex<Box<dyn Output>>>,
         metrics_r: MetricReader,
-        maybe_measurements: Result<MeasurementBuffer, channel::RecvError>,
+        maybe_measurements: Result<Arc<MeasurementBuffer>, channel::RecvError>,
     ) -> anyhow::Result<ControlFlow<()>> {
         match maybe_measurements {
             Ok(measurements) => {
                 log::trace!("writing {} measurements to {name}", measurements.len());
                 let res = tokio::task::spawn_blocking(move || {
                     let ctx = OutputContext {
-                        metrics: &metrics_r.blocking_read(),
+                        metrics: &metrics_r.snapshot(),
                     };
                     output.lock().unwrap().write(&measurements, &ctx)
                 })
@@ -157,7 +157,7 @@ impl SourceControl {
     }
 
     pub fn blocking_create_sources(&mut self, sources: Namespace2<builder::SourceBuilder>) -> anyhow::Result<()> {
-        let metrics = self.metrics.0.blocking_read();
+        let metrics = self.metrics.0.snapshot();
         for ((plugin, name), builder) in sources {
             let mut ctx = builder::BuildContext {
                 metrics: &metrics,
@@ -179,8 +179,8 @@ impl SourceControl {
         &mut self,
         builders: Vec<(SourceName, builder::SendSourceBuilder)>,
     ) -> anyhow::Result<()> {
-        // We only get the lock and BuildContext once for all the sources.
-        let metrics = self.metrics.0.read().await;
+        // We only get the snapshot and BuildContext once for all the sources.
+        let metrics = self.metrics.0.snapshot();
         let mut ctx = builder::BuildContext {
             metrics: &metrics,
             metrics_r: &self.metrics.0,
@@ -1,3 +1,4 @@
+use std::ops::ControlFlow;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
@@ -57,6 +58,30 @@ pub(crate) async fn run_managed(
         buffer.reserve(hint_additional_elems);
     }
 
+    /// Polls the source once. Returns `ControlFlow::Break` if the source has stopped itself.
+    fn poll(
+        source: &mut dyn Source,
+        buffer: &mut MeasurementBuffer,
+        name: &SourceName,
+    ) -> Result<ControlFlow<()>, PipelineError> {
+        let timestamp = Timestamp::now();
+        match source.poll(&mut buffer.as_accumulator(), timestamp) {
+            Ok(()) => Ok(ControlFlow::Continue(())),
+            Err(PollError::NormalStop) => {
+                log::info!("Source {name} stopped itself.");
+                Ok(ControlFlow::Break(()))
+            }
+            Err(PollError::CanRetry(e)) => {
+                log::error!("Non-fatal error when polling {name} (will retry): {e:#}");
+                Ok(ControlFlow::Continue(()))
+            }
+            Err(PollError::Fatal(e)) => {
+                log::error!("Fatal error when polling {name} (will stop running): {e:?}");
+                Err(PipelineError::for_element(name.to_owned(), e))
+            }
+        }
+    }
+
     // Get the initial source configuration.
     let mut trigger = config
         .new_trigger
@@ -74,36 +99,32 @@ pub(crate) async fn run_managed(
     // by the control loop.
     let config_change = &config.change_notifier;
 
+    // If requested, the first round does not wait for the trigger.
+    let mut poll_now = trigger.config.poll_at_startup;
+
     // main loop
     let mut i = 1usize;
     'run: loop {
         // Wait for the trigger. It can return for two reasons:
         // - "normal case": the underlying mechanism (e.g. timer) triggers <- this is the most likely case
         // - "interrupt case": the underlying mechanism was idle (e.g. sleeping) but a new command arrived
-        let reason = trigger
-            .next(config_change)
-            .await
-            .map_err(|err| PipelineError::for_element(source_name.clone(), err))?;
+        let reason = if poll_now {
+            poll_now = false;
+            TriggerReason::Triggered
+        } else {
+            trigger
+                .next(config_change)
+                .await
+                .map_err(|err| PipelineError::for_element(source_name.clone(), err))?
+        };
 
         let mut update;
         match reason {
             TriggerReason::Triggered => {
                 // poll the source
-                let timestamp = Timestamp::now();
-                match source.poll(&mut buffer.as_accumulator(), timestamp) {
-                    Ok(()) => (),
-                    Err(PollError::NormalStop) => {
-                        log::info!("Source {source_name} stopped itself.");
-                        break 'run; // stop polling
-                    }
-                    Err(PollError::CanRetry(e)) => {
-                        log::error!("Non-fatal error when polling {source_name} (will retry): {e:#}");
-                    }
-                    Err(PollError::Fatal(e)) => {
-                        log::error!("Fatal error when polling {source_name} (will stop running): {e:?}");
-                        return Err(PipelineError::for_element(source_name, e));
-                    }
-                };
+                if poll(source.as_mut(), &mut buffer, &source_name)?.is_break() {
+                    break 'run; // stop polling
+                }
 
                 // Flush the measurements, not on every round for performance reasons.
                 // This is done _after_ polling, to ensure that we poll at least once before flushing, even if flush_rounds is 1.
@@ -139,6 +160,11 @@ pub(crate) async fn run_managed(
                     config_change.notified().await; // wait for the config to change
                 }
                 TaskState::Stop => {
+                    if trigger.config.poll_at_shutdown {
+                        // one last measurement, flushed below (the source stops anyway, ignore ControlFlow)
+                        log::trace!("{source_name} polled at shutdown");
+                        let _ = poll(source.as_mut(), &mut buffer, &source_name)?;
+                    }
                     break 'run; // stop polling
                 }
             }
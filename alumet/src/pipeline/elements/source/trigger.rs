@@ -56,6 +56,14 @@ pub(crate) struct TriggerLoopParams {
     /// but decreases the time it takes for a [source command](super::runtime::SourceCmd)
     /// to be applied.
     pub update_rounds: usize,
+
+    /// If `true`, the source is polled once as soon as it starts, without waiting for the trigger.
+    pub poll_at_startup: bool,
+
+    /// If `true`, the source is polled one last time when it is stopped.
+    ///
+    /// When the pipeline shuts down, this happens before the outputs receive their last measurements.
+    pub poll_at_shutdown: bool,
 }
 
 /// Constraints that can be applied to a [`TriggerSpec`] after its construction.
@@ -69,6 +77,13 @@ pub struct TriggerConstraints {
 
     /// If `true`, forces all managed sources to be triggered on-demand by a signal.
     pub allow_manual_trigger: bool,
+
+    /// If `true`, forces all managed sources to be polled once when they start.
+    pub poll_at_startup: bool,
+
+    /// If `true`, forces all managed sources to be polled one last time when they stop,
+    /// for instance when the pipeline shuts down.
+    pub poll_at_shutdown: bool,
 }
 
 /// Builder for source triggers.
@@ -89,6 +104,10 @@ pub(crate) mod private_impl {
                 (super::TriggerMechanismSpec::Future(_f1), super::TriggerMechanismSpec::Future(_f2)) => {
                     true // how to std::ptr::eq on this?
                 }
+                (super::TriggerMechanismSpec::Never, super::TriggerMechanismSpec::Never) => {
+                    self.loop_params.poll_at_startup == other.loop_params.poll_at_startup
+                        && self.loop_params.poll_at_shutdown == other.loop_params.poll_at_shutdown
+                }
                 _ => false,
             }
         }
@@ -111,14 +130,44 @@ impl TriggerSpec {
         builder::time_interval(poll_interval)
     }
 
+    /// Defines a one-shot trigger that polls the source exactly once, when it starts.
+    ///
+    /// For sources created during the startup phase, this is when the pipeline starts.
+    /// For more options, use [`builder::one_shot`].
+    pub fn at_startup() -> TriggerSpec {
+        builder::one_shot().at_startup().build().unwrap()
+    }
+
+    /// Defines a one-shot trigger that polls the source exactly once, when it stops.
+    ///
+    /// When the pipeline shuts down, the source is polled before the outputs receive their last measurements.
+    /// For more options, use [`builder::one_shot`].
+    pub fn at_shutdown() -> TriggerSpec {
+        builder::one_shot().at_shutdown().build().unwrap()
+    }
+
+    /// Defines a trigger that polls the source twice: once when it starts, once when it stops.
+    ///
+    /// See [`at_startup`](Self::at_startup) and [`at_shutdown`](Self::at_shutdown).
+    pub fn at_startup_and_shutdown() -> TriggerSpec {
+        builder::one_shot().at_startup().at_shutdown().build().unwrap()
+    }
+
     /// Adjusts the trigger specification to respect the given constraints.
     ///
     /// # Constraints
     /// - `max_update_interval`: maximum amount of time allowed between two command updates
+    /// - `allow_manual_trigger`, `poll_at_startup`, `poll_at_shutdown`: options to enable
     pub(crate) fn constrain(&mut self, constraints: &TriggerConstraints) {
         if constraints.allow_manual_trigger {
             self.allow_manual_trigger = true;
         }
+        if constraints.poll_at_startup {
+            self.loop_params.poll_at_startup = true;
+        }
+        if constraints.poll_at_shutdown {
+            self.loop_params.poll_at_shutdown = true;
+        }
         if !self.interruptible {
             let max_update_interval = constraints.max_update_interval;
 
@@ -155,6 +204,8 @@ impl Default for TriggerConstraints {
         Self {
             max_update_interval: Duration::MAX,
             allow_manual_trigger: false,
+            poll_at_startup: false,
+            poll_at_shutdown: false,
         }
     }
 }
@@ -262,6 +313,8 @@ enum TriggerMechanismSpec {
     TimeInterval(time::Instant, time::Duration),
     Future(fn() -> BoxFuture<'static, SourceTriggerOutput>),
     ManualOnly,
+    /// Never triggers by itself, used by one-shot triggers (see [`TriggerLoopParams::poll_at_startup`]).
+    Never,
 }
 
 /// A mechanism that can trigger things.
@@ -286,6 +339,9 @@ enum TriggerMechanism {
     ///
     /// The source is polled each time `f().await` returns.
     Future(fn() -> BoxFuture<'static, SourceTriggerOutput>),
+
+    /// A trigger that never wakes up.
+    Never,
 }
 
 impl TryFrom<TriggerMechanismSpec> for TriggerMechanism {
@@ -307,6 +363,7 @@ impl TryFrom<TriggerMechanismSpec> for TriggerMechanism {
             }
             TriggerMechanismSpec::Future(f) => TriggerMechanism::Future(f),
             TriggerMechanismSpec::ManualOnly => TriggerMechanism::Manual(Arc::new(Notify::new())),
+            TriggerMechanismSpec::Never => TriggerMechanism::Never,
         })
     }
 }
@@ -330,6 +387,7 @@ impl TriggerMechanism {
             }
             TriggerMechanism::Future(f) => f().await,
             TriggerMechanism::Manual(notify) => Ok(notify.notified().await),
+            TriggerMechanism::Never => std::future::pending().await,
         }
     }
 }
@@ -342,6 +400,7 @@ impl fmt::Debug for TriggerMechanism {
             Self::Sleep(_, _) => f.write_str("TriggerMechanism::Sleep"),
             Self::Future(ptr) => write!(f, "TriggerMechanism::Future({ptr:?})"),
             Self::Manual(_) => f.write_str("TriggerMechanism::Manual"),
+            Self::Never => f.write_str("TriggerMechanism::Never"),
         }
     }
 }
@@ -350,7 +409,7 @@ impl fmt::Debug for TriggerMechanism {
 mod tests {
     use std::time::Duration;
 
-    use super::{builder, TriggerConstraints, TriggerMechanismSpec};
+    use super::{builder, TriggerConstraints, TriggerMechanismSpec, TriggerSpec};
 
     #[test]
     fn trigger_auto_config() {
@@ -400,7 +459,7 @@ mod tests {
     fn trigger_constraints() {
         let constraints = TriggerConstraints {
             max_update_interval: Duration::from_secs(2),
-            allow_manual_trigger: false,
+            ..Default::default()
         };
 
         let mut trigger = builder::time_interval(Duration::from_secs(1)) // 1sec
@@ -454,4 +513,31 @@ mod tests {
         assert_eq!(trigger.loop_params.flush_rounds, 5);
         assert_eq!(trigger.loop_params.update_rounds, 1);
     }
+
+    #[test]
+    fn one_shot_triggers() {
+        assert!(
+            builder::one_shot().build().is_err(),
+            "a one-shot trigger must poll at least once"
+        );
+
+        let trigger = TriggerSpec::at_startup_and_shutdown();
+        assert!(matches!(trigger.mechanism, TriggerMechanismSpec::Never));
+        assert!(trigger.interruptible);
+        assert!(trigger.loop_params.poll_at_startup);
+        assert!(trigger.loop_params.poll_at_shutdown);
+        assert_ne!(trigger, TriggerSpec::at_startup());
+
+        // constraints can add one-shot polls to any trigger
+        let constraints = TriggerConstraints {
+            poll_at_startup: true,
+            poll_at_shutdown: true,
+            ..Default::default()
+        };
+        let mut trigger = TriggerSpec::at_interval(Duration::from_secs(1));
+        trigger.constrain(&constraints);
+        assert!(matches!(trigger.mechanism, TriggerMechanismSpec::TimeInterval(_, d) if d == Duration::from_secs(1)));
+        assert!(trigger.loop_params.poll_at_startup);
+        assert!(trigger.loop_params.poll_at_shutdown);
+    }
 }
@@ -33,6 +33,24 @@ pub fn manual() -> ManualTriggerBuilder {
     ManualTriggerBuilder::new()
 }
 
+/// Returns a builder for a one-shot source trigger spec, which polls the source
+/// when it starts, when it stops, or both.
+///
+/// # Example
+/// ```
+/// use alumet::pipeline::elements::source::trigger;
+///
+/// // poll the source once at startup and one last time at shutdown
+/// let trigger_config = trigger::builder::one_shot()
+///     .at_startup()
+///     .at_shutdown()
+///     .build()
+///     .unwrap();
+/// ```
+pub fn one_shot() -> OneShotTriggerBuilder {
+    OneShotTriggerBuilder::new()
+}
+
 struct TriggerSpecBuilder {
     mechanism: TriggerMechanismSpec,
     loop_params: TriggerLoopParams,
@@ -47,6 +65,9 @@ pub struct TimeTriggerBuilder(TriggerSpecBuilder);
 /// Builder for a trigger that only wakes up on "manual" notifications.
 pub struct ManualTriggerBuilder(TriggerSpecBuilder);
 
+/// Builder for a trigger that only wakes up when the source starts and/or stops.
+pub struct OneShotTriggerBuilder(TriggerSpecBuilder);
+
 #[derive(Debug)]
 pub enum Error {
     Io(std::io::Error),
@@ -71,6 +92,8 @@ impl TriggerSpecBuilder {
             loop_params: TriggerLoopParams {
                 flush_rounds: 1,
                 update_rounds: 1,
+                poll_at_startup: false,
+                poll_at_shutdown: false,
             },
             interruptible: false,
             manual_allowed: false,
@@ -197,6 +220,18 @@ impl TimeTriggerBuilder {
         self
     }
 
+    /// Polls the source once when it starts, in addition to the regular polling.
+    pub fn poll_at_startup(&mut self) -> &mut Self {
+        self.0.loop_params.poll_at_startup = true;
+        self
+    }
+
+    /// Polls the source one last time when it stops, in addition to the regular polling.
+    pub fn poll_at_shutdown(&mut self) -> &mut Self {
+        self.0.loop_params.poll_at_shutdown = true;
+        self
+    }
+
     /// Builds the trigger specification.
     pub fn build(&mut self) -> Result<TriggerSpec, Error> {
         let poll_interval = *self.poll_interval();
@@ -245,3 +280,57 @@ impl ManualTriggerBuilder {
         Ok(self.0.build())
     }
 }
+
+impl Default for OneShotTriggerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OneShotTriggerBuilder {
+    pub fn new() -> Self {
+        let mut inner = TriggerSpecBuilder::new(TriggerMechanismSpec::Never);
+        // Make it interruptible, otherwise config updates would never be applied.
+        inner.interruptible = true;
+        Self(inner)
+    }
+
+    /// Polls the source once, as soon as it starts.
+    ///
+    /// For sources created during the startup phase, this is when the pipeline starts.
+    pub fn at_startup(&mut self) -> &mut Self {
+        self.0.loop_params.poll_at_startup = true;
+        self
+    }
+
+    /// Polls the source once, when it stops.
+    ///
+    /// When the pipeline shuts down, the source is polled before the outputs receive their last measurements.
+    pub fn at_shutdown(&mut self) -> &mut Self {
+        self.0.loop_params.poll_at_shutdown = true;
+        self
+    }
+
+    /// Signals that the pipeline should run the source on a thread with a high scheduling priority.
+    ///
+    /// See [`TimeTriggerBuilder::realtime_priority`].
+    pub fn realtime_priority(&mut self) -> &mut Self {
+        self.0.realtime_sched_priority = true;
+        self
+    }
+
+    pub fn allow_manual_trigger(&mut self) -> &mut Self {
+        self.0.manual_allowed = true;
+        self
+    }
+
+    /// Builds the trigger specification.
+    pub fn build(&mut self) -> Result<TriggerSpec, Error> {
+        if !self.0.loop_params.poll_at_startup && !self.0.loop_params.poll_at_shutdown {
+            return Err(Error::InvalidConfig(String::from(
+                "a one-shot trigger must poll at startup, at shutdown, or both",
+            )));
+        }
+        Ok(self.0.build())
+    }
+}
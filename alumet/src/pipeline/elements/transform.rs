@@ -4,7 +4,8 @@ pub mod builder;
 pub mod control;
 pub mod error;
 pub mod interface;
+pub mod parallel;
 pub mod run;
 
 pub use error::TransformError;
-pub use interface::{Transform, TransformContext};
+pub use interface::{AsyncTransform, AsyncTransformContext, Transform, TransformContext};
@@ -1,9 +1,11 @@
+use std::num::NonZeroUsize;
+
 use crate::metrics::{
     def::{Metric, RawMetricId},
     registry::MetricRegistry,
 };
 
-use super::Transform;
+use super::{AsyncTransform, Transform};
 
 /// Trait for transform builders.
 ///
@@ -24,6 +26,55 @@ use super::Transform;
 pub trait TransformBuilder: FnOnce(&mut dyn TransformBuildContext) -> anyhow::Result<Box<dyn Transform>> {}
 impl<F> TransformBuilder for F where F: FnOnce(&mut dyn TransformBuildContext) -> anyhow::Result<Box<dyn Transform>> {}
 
+/// Trait for async transform builders.
+///
+///  # Example
+/// ```
+/// use std::num::NonZeroUsize;
+/// use alumet::pipeline::elements::transform::builder::{AsyncTransformBuilder, AsyncTransformSpec, TransformBuildContext};
+/// use alumet::pipeline::elements::transform::AsyncTransform;
+///
+/// fn build_my_transform() -> anyhow::Result<Box<dyn AsyncTransform>> {
+///     todo!("build a new async transform")
+/// }
+///
+/// let builder: &dyn AsyncTransformBuilder = &|ctx: &mut dyn TransformBuildContext| {
+///     let transform = build_my_transform()?;
+///     Ok(AsyncTransformSpec {
+///         transform,
+///         max_concurrency: NonZeroUsize::new(4).unwrap(),
+///     })
+/// };
+/// ```
+pub trait AsyncTransformBuilder: FnOnce(&mut dyn TransformBuildContext) -> anyhow::Result<AsyncTransformSpec> {}
+impl<F> AsyncTransformBuilder for F where F: FnOnce(&mut dyn TransformBuildContext) -> anyhow::Result<AsyncTransformSpec>
+{}
+
+/// A transform builder, for a synchronous or asynchronous transform.
+///
+/// Use this type in the pipeline builder.
+pub enum AnyTransformBuilder {
+    Sync(Box<dyn TransformBuilder>),
+    Async(Box<dyn AsyncTransformBuilder>),
+}
+
+impl std::fmt::Debug for AnyTransformBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sync(_) => f.debug_tuple("Sync").field(&"Box<dyn _>").finish(),
+            Self::Async(_) => f.debug_tuple("Async").field(&"Box<dyn _>").finish(),
+        }
+    }
+}
+
+/// Information required to register a new async transform to the measurement pipeline.
+pub struct AsyncTransformSpec {
+    /// The transform.
+    pub transform: Box<dyn AsyncTransform>,
+    /// Maximum number of buffers that can be processed concurrently by the transform.
+    pub max_concurrency: NonZeroUsize,
+}
+
 pub(super) struct BuildContext<'a> {
     pub(super) metrics: &'a MetricRegistry,
 }
@@ -14,8 +14,8 @@ use crate::pipeline::control::message::matching::TransformMatcher;
 use crate::pipeline::error::PipelineError;
 use crate::pipeline::naming::TransformName;
 
-use super::builder::{BuildContext, TransformBuilder};
-use super::run::run_all_in_order;
+use super::builder::{AnyTransformBuilder, AsyncTransformSpec, BuildContext};
+use super::run::{run_all_in_order, run_async, TransformOutput};
 use super::Transform;
 
 /// Controls the transforms of a measurement pipeline.
@@ -26,8 +26,8 @@ pub(crate) struct TransformControl {
 }
 
 struct TaskManager {
-    // Even though there is only one task, we don't use its JoinHandle directly,
-    // because awaiting it consumes the task.
+    // One task is spawned per group of consecutive synchronous transforms, and one per async transform.
+    // We don't use their JoinHandles directly, because awaiting them consumes the tasks.
     spawned_tasks: JoinSet<Result<(), PipelineError>>,
     active_bitset: Arc<AtomicU64>,
     names_by_bitset_position: Vec<TransformName>,
@@ -45,22 +45,26 @@ impl TransformControl {
     }
 
     pub fn with_transforms(
-        transforms: Vec<(TransformName, Box<dyn TransformBuilder>)>,
+        transforms: Vec<(TransformName, AnyTransformBuilder)>,
         metrics: MetricReader,
         rx: mpsc::Receiver<MeasurementBuffer>,
-        tx: broadcast::Sender<MeasurementBuffer>,
+        tx: broadcast::Sender<Arc<MeasurementBuffer>>,
+        channel_size: usize,
         rt_normal: &runtime::Handle,
     ) -> anyhow::Result<Self> {
-        let metrics_r = metrics.blocking_read();
+        let metrics_r = metrics.snapshot();
         let mut built = Vec::with_capacity(transforms.len());
         for (full_name, builder) in transforms {
             let mut ctx = BuildContext { metrics: &metrics_r };
-            let transform = builder(&mut ctx)
-                .context("transform creation failed")
-                .inspect_err(|e| log::error!("Failed to build transform {full_name}: {e:#}"))?;
+            let transform = match builder {
+                AnyTransformBuilder::Sync(builder) => builder(&mut ctx).map(BuiltTransform::Sync),
+                AnyTransformBuilder::Async(builder) => builder(&mut ctx).map(BuiltTransform::Async),
+            }
+            .context("transform creation failed")
+            .inspect_err(|e| log::error!("Failed to build transform {full_name}: {e:#}"))?;
             built.push((full_name, transform));
         }
-        let tasks = TaskManager::spawn(built, metrics.clone(), rx, tx, rt_normal);
+        let tasks = TaskManager::spawn(built, metrics.clone(), rx, tx, channel_size, rt_normal);
         Ok(Self { tasks })
     }
 
@@ -84,22 +88,37 @@ impl TransformControl {
     where
         F: FnMut(Result<Result<(), PipelineError>, tokio::task::JoinError>),
     {
-        // Nothing to do to stop the tasks: the transform task will naturally
-        // stop when the input channel is closed.
+        // Nothing to do to stop the tasks: the first transform task will naturally
+        // stop when the input channel is closed, which closes the input of the next task, and so on.
 
-        // We simply wait for the task to finish.
+        // We simply wait for the tasks to finish.
         while let Some(res) = self.tasks.spawned_tasks.join_next().await {
             handle_task_result(res);
         }
     }
 }
 
+/// A transform that has been built.
+enum BuiltTransform {
+    Sync(Box<dyn Transform>),
+    Async(AsyncTransformSpec),
+}
+
+/// A group of transforms that is executed by a single task.
+enum Stage {
+    /// Consecutive synchronous transforms, with the position of the first one in the bitset.
+    Sync(Vec<(TransformName, Box<dyn Transform>)>, usize),
+    /// An async transform, with its position in the bitset.
+    Async(TransformName, AsyncTransformSpec, usize),
+}
+
 impl TaskManager {
     pub fn spawn(
-        transforms: Vec<(TransformName, Box<dyn Transform>)>,
+        transforms: Vec<(TransformName, BuiltTransform)>,
         metrics_r: MetricReader,
         rx: mpsc::Receiver<MeasurementBuffer>,
-        tx: broadcast::Sender<MeasurementBuffer>,
+        tx: broadcast::Sender<Arc<MeasurementBuffer>>,
+        channel_size: usize,
         rt_normal: &runtime::Handle,
     ) -> Self {
         let mut active_bitset: u64 = 0;
@@ -110,11 +129,63 @@ impl TaskManager {
             names_by_bitset_position.push(name.clone());
         }
 
-        // Start the transforms task.
+        // Group the transforms in stages: the sync transforms that follow each other run in the same task,
+        // while each async transform gets its own task, so that it can process multiple buffers concurrently.
+        let mut stages = Vec::new();
+        for (i, (name, transform)) in transforms.into_iter().enumerate() {
+            match (transform, stages.last_mut()) {
+                (BuiltTransform::Sync(t), Some(Stage::Sync(group, _))) => group.push((name, t)),
+                (BuiltTransform::Sync(t), _) => stages.push(Stage::Sync(vec![(name, t)], i)),
+                (BuiltTransform::Async(spec), _) => stages.push(Stage::Async(name, spec, i)),
+            }
+        }
+        if stages.is_empty() {
+            // Without any transform, we still need a task to forward the measurements to the outputs.
+            stages.push(Stage::Sync(Vec::new(), 0));
+        }
+
+        // Start the transform tasks, connected by channels, in order.
         let mut set = JoinSet::new();
         let active_bitset = Arc::new(AtomicU64::new(active_bitset));
-        let task = run_all_in_order(transforms, rx, tx, active_bitset.clone(), metrics_r);
-        set.spawn_on(task, rt_normal);
+        let n_stages = stages.len();
+        let mut stage_rx = rx;
+        for (i, stage) in stages.into_iter().enumerate() {
+            let (stage_tx, next_rx) = if i == n_stages - 1 {
+                (TransformOutput::Outputs(tx.clone()), None)
+            } else {
+                let (next_tx, next_rx) = mpsc::channel(channel_size);
+                (TransformOutput::Next(next_tx), Some(next_rx))
+            };
+            match stage {
+                Stage::Sync(transforms, first_index) => {
+                    let task = run_all_in_order(
+                        transforms,
+                        first_index,
+                        stage_rx,
+                        stage_tx,
+                        active_bitset.clone(),
+                        metrics_r.clone(),
+                    );
+                    set.spawn_on(task, rt_normal);
+                }
+                Stage::Async(name, spec, index) => {
+                    let task = run_async(
+                        name,
+                        index,
+                        spec,
+                        stage_rx,
+                        stage_tx,
+                        active_bitset.clone(),
+                        metrics_r.clone(),
+                    );
+                    set.spawn_on(task, rt_normal);
+                }
+            }
+            match next_rx {
+                Some(next_rx) => stage_rx = next_rx,
+                None => break,
+            }
+        }
         Self {
             spawned_tasks: set,
             active_bitset,
@@ -155,3 +226,161 @@ pub enum TaskState {
     Enabled,
     Disabled,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::future::BoxFuture;
+    use tokio::sync::{broadcast, mpsc};
+    use tokio_util::sync::CancellationToken;
+
+    use super::TransformControl;
+    use crate::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::{def::RawMetricId, online::MetricRegistryControl, registry::MetricRegistry},
+        pipeline::{
+            elements::transform::{
+                builder::{AnyTransformBuilder, AsyncTransformSpec},
+                AsyncTransform, AsyncTransformContext, Transform, TransformContext, TransformError,
+            },
+            naming::TransformName,
+        },
+        resources::{Resource, ResourceConsumer},
+    };
+
+    struct AddOne;
+    struct SlowDouble {
+        running: AtomicUsize,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    fn map_values(measurements: &mut MeasurementBuffer, f: impl Fn(u64) -> u64) {
+        for m in measurements.iter_mut() {
+            if let WrappedMeasurementValue::U64(v) = m.value {
+                m.value = WrappedMeasurementValue::U64(f(v));
+            }
+        }
+    }
+
+    impl Transform for AddOne {
+        fn apply(
+            &mut self,
+            measurements: &mut MeasurementBuffer,
+            _ctx: &TransformContext,
+        ) -> Result<(), TransformError> {
+            map_values(measurements, |v| v + 1);
+            Ok(())
+        }
+    }
+
+    impl AsyncTransform for SlowDouble {
+        fn apply<'a>(
+            &'a self,
+            mut measurements: MeasurementBuffer,
+            _ctx: AsyncTransformContext,
+        ) -> BoxFuture<'a, Result<MeasurementBuffer, TransformError>> {
+            Box::pin(async move {
+                let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_running.fetch_max(running, Ordering::SeqCst);
+                // the first buffers take more time than the last ones
+                let value = match measurements.iter().next().unwrap().value {
+                    WrappedMeasurementValue::U64(v) => v,
+                    _ => unreachable!(),
+                };
+                tokio::time::sleep(Duration::from_millis(10 * (10 - value))).await;
+                map_values(&mut measurements, |v| v * 2);
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                Ok(measurements)
+            })
+        }
+    }
+
+    fn buffer(value: u64) -> MeasurementBuffer {
+        let mut buf = MeasurementBuffer::new();
+        buf.push(MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(value),
+        ));
+        buf
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_transform_preserves_order() {
+        let rt = tokio::runtime::Handle::current();
+        let (_, metrics, _) = MetricRegistryControl::new(MetricRegistry::new()).start(CancellationToken::new(), &rt);
+
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let slow = SlowDouble {
+            running: AtomicUsize::new(0),
+            max_running: max_running.clone(),
+        };
+        let transforms = vec![
+            (
+                TransformName::from_str("test", "add_one"),
+                AnyTransformBuilder::Sync(Box::new(|_: &mut _| Ok(Box::new(AddOne) as Box<dyn Transform>))),
+            ),
+            (
+                TransformName::from_str("test", "slow_double"),
+                AnyTransformBuilder::Async(Box::new(move |_: &mut _| {
+                    Ok(AsyncTransformSpec {
+                        transform: Box::new(slow),
+                        max_concurrency: NonZeroUsize::new(3).unwrap(),
+                    })
+                })),
+            ),
+            (
+                TransformName::from_str("test", "add_one_again"),
+                AnyTransformBuilder::Sync(Box::new(|_: &mut _| Ok(Box::new(AddOne) as Box<dyn Transform>))),
+            ),
+        ];
+
+        let (in_tx, in_rx) = mpsc::channel(16);
+        let out_tx = broadcast::Sender::new(16);
+        let mut out_rx = out_tx.subscribe();
+        let control =
+            TransformControl::with_transforms(transforms, metrics.into_read_only(), in_rx, out_tx, 16, &rt).unwrap();
+
+        for i in 0..8 {
+            in_tx.send(buffer(i)).await.unwrap();
+        }
+        drop(in_tx);
+
+        for i in 0..8 {
+            let buf = out_rx.recv().await.unwrap();
+            let value = buf.iter().next().unwrap().value.clone();
+            assert_eq!(value, WrappedMeasurementValue::U64((i + 1) * 2 + 1));
+        }
+        control.shutdown(|res| res.unwrap().unwrap()).await;
+
+        let max_running = max_running.load(Ordering::SeqCst);
+        assert!(max_running > 1, "buffers should be processed concurrently");
+        assert!(max_running <= 3, "concurrency should be bounded");
+    }
+
+    #[tokio::test]
+    async fn outputs_share_buffers() {
+        let rt = tokio::runtime::Handle::current();
+        let (_, metrics, _) = MetricRegistryControl::new(MetricRegistry::new()).start(CancellationToken::new(), &rt);
+
+        let (in_tx, in_rx) = mpsc::channel(16);
+        let out_tx = broadcast::Sender::new(16);
+        let mut out_rx1 = out_tx.subscribe();
+        let mut out_rx2 = out_tx.subscribe();
+        let control =
+            TransformControl::with_transforms(Vec::new(), metrics.into_read_only(), in_rx, out_tx, 16, &rt).unwrap();
+
+        in_tx.send(buffer(1)).await.unwrap();
+        drop(in_tx);
+        let buf1 = out_rx1.recv().await.unwrap();
+        let buf2 = out_rx2.recv().await.unwrap();
+        assert!(Arc::ptr_eq(&buf1, &buf2), "the buffer should not be copied");
+        control.shutdown(|res| res.unwrap().unwrap()).await;
+    }
+}
@@ -1,6 +1,11 @@
 //! Public interface for implementing transforms.
 
-use crate::{measurement::MeasurementBuffer, metrics::registry::MetricRegistry};
+use futures::future::BoxFuture;
+
+use crate::{
+    measurement::MeasurementBuffer,
+    metrics::{online::RegistrySnapshot, registry::MetricRegistry},
+};
 
 use super::error::TransformError;
 
@@ -24,3 +29,36 @@ pub trait Transform: Send {
 pub struct TransformContext<'a> {
     pub metrics: &'a MetricRegistry,
 }
+
+/// Transforms measurements asynchronously.
+///
+/// Unlike [`Transform`], an async transform can wait for external resources (a remote service,
+/// a file, etc.) without blocking the other transforms. Multiple buffers can be processed
+/// concurrently by the same transform, up to the maximum concurrency given when registering it
+/// (see [`AsyncTransformSpec`](super::builder::AsyncTransformSpec)).
+///
+/// # Ordering
+/// Even if the buffers are processed concurrently, their order is preserved: the results
+/// are passed to the next step in the order in which the buffers entered the transform.
+///
+/// Async transforms can be mixed with synchronous transforms, the execution order defined
+/// in the pipeline builder applies to both kinds.
+pub trait AsyncTransform: Send + Sync {
+    /// Applies the transform function on the measurements.
+    ///
+    /// The resulting buffer is passed to the next transform, if there is one, or to the outputs.
+    /// If an error is returned, the buffer is dropped.
+    fn apply<'a>(
+        &'a self,
+        measurements: MeasurementBuffer,
+        ctx: AsyncTransformContext,
+    ) -> BoxFuture<'a, Result<MeasurementBuffer, TransformError>>;
+}
+
+/// Shared data that can be accessed by async transforms.
+///
+/// It holds a snapshot of the metric registry, which can be kept as long as needed.
+#[derive(Clone)]
+pub struct AsyncTransformContext {
+    pub metrics: RegistrySnapshot,
+}
@@ -0,0 +1,326 @@
+//! Parallel execution of transforms.
+//!
+//! Synchronous transforms run one after another, on a single task. When a transform
+//! is expensive, this can limit the throughput of the whole pipeline, while the other threads
+//! of the normal runtime have nothing to do.
+//!
+//! This module provides two ways of running transforms in parallel:
+//! - A [`StatelessTransform`] does not keep any state between two calls to `apply`.
+//!   It can process multiple buffers at the same time, see [`ParallelTransform`].
+//! - A transform that keeps a state per resource or per consumer can be _sharded_.
+//!   Each shard is an independent [`Transform`], and the points of a buffer are dispatched
+//!   to the shards according to a [`ShardKey`], see [`ShardedTransform`].
+//!
+//! In both cases, the buffers are sent to the next step in the order in which they have been received.
+//! Inside a buffer, a sharded transform only preserves the order of the points that have the same key.
+//!
+//! Both adapters implement [`AsyncTransform`], they can be added to the pipeline like
+//! any async transform.
+//! The plugins can also use [`add_stateless_transform`](crate::plugin::AlumetPluginStart::add_stateless_transform)
+//! and [`add_sharded_transform`](crate::plugin::AlumetPluginStart::add_sharded_transform).
+
+use std::{
+    hash::{Hash, Hasher},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use fxhash::FxHasher;
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint};
+
+use super::{
+    builder::AsyncTransformSpec, error::TransformError, AsyncTransform, AsyncTransformContext, Transform,
+    TransformContext,
+};
+
+/// A transform that does not keep any state between two buffers.
+///
+/// Unlike [`Transform::apply`], [`StatelessTransform::apply`] takes `&self`, which allows
+/// the pipeline to apply the transform on multiple buffers concurrently.
+pub trait StatelessTransform: Send + Sync {
+    /// Applies the transform function on the measurements.
+    ///
+    /// See [`Transform::apply`].
+    fn apply(&self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError>;
+}
+
+/// Runs a [`StatelessTransform`] on multiple buffers concurrently.
+///
+/// Each buffer is processed in a separate tokio task, which can run on any thread of the runtime.
+pub struct ParallelTransform {
+    transform: Arc<dyn StatelessTransform>,
+}
+
+impl ParallelTransform {
+    pub fn new(transform: Box<dyn StatelessTransform>) -> Self {
+        Self {
+            transform: Arc::from(transform),
+        }
+    }
+
+    /// Returns the information required to add this transform to the pipeline.
+    pub fn into_spec(self, max_concurrency: NonZeroUsize) -> AsyncTransformSpec {
+        AsyncTransformSpec {
+            transform: Box::new(self),
+            max_concurrency,
+        }
+    }
+}
+
+impl AsyncTransform for ParallelTransform {
+    fn apply<'a>(
+        &'a self,
+        mut measurements: MeasurementBuffer,
+        ctx: AsyncTransformContext,
+    ) -> BoxFuture<'a, Result<MeasurementBuffer, TransformError>> {
+        let transform = self.transform.clone();
+        Box::pin(async move {
+            let task = tokio::task::spawn(async move {
+                let ctx = TransformContext { metrics: &ctx.metrics };
+                transform.apply(&mut measurements, &ctx).map(|_| measurements)
+            });
+            task.await
+                .map_err(|e| TransformError::Fatal(anyhow!("stateless transform task failed: {e}")))?
+        })
+    }
+}
+
+/// Determines how the points of a buffer are dispatched to the shards of a [`ShardedTransform`].
+///
+/// All the points with the same key are processed by the same shard, in order.
+///
+/// The points are regrouped by shard in the transformed buffer: the points that have different keys
+/// can end up in a different order than in the original buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardKey {
+    /// Shard by resource.
+    Resource,
+    /// Shard by resource consumer.
+    Consumer,
+    /// Shard by (resource, consumer) pair.
+    ResourceAndConsumer,
+}
+
+impl ShardKey {
+    fn shard_of(&self, point: &MeasurementPoint, n_shards: usize) -> usize {
+        let mut hasher = FxHasher::default();
+        match self {
+            ShardKey::Resource => point.resource.hash(&mut hasher),
+            ShardKey::Consumer => point.consumer.hash(&mut hasher),
+            ShardKey::ResourceAndConsumer => {
+                point.resource.hash(&mut hasher);
+                point.consumer.hash(&mut hasher);
+            }
+        }
+        (hasher.finish() % n_shards as u64) as usize
+    }
+}
+
+/// Runs multiple instances (shards) of a [`Transform`] in parallel.
+///
+/// Each buffer is split according to the [`ShardKey`], and each part is processed by its shard
+/// in a separate tokio task. The results are then merged in the order of the shards.
+/// Since a given key is always dispatched to the same shard, a shard can keep a state per key.
+///
+/// The buffers are processed one at a time, in order: the parallelism happens inside each buffer.
+/// The order of the points is preserved for each key, but not across keys.
+///
+/// # Errors
+/// If a shard returns an error, the whole buffer is dropped.
+/// A [`TransformError::Fatal`] error takes precedence over [`TransformError::UnexpectedInput`].
+pub struct ShardedTransform {
+    shards: Vec<Arc<Mutex<Box<dyn Transform>>>>,
+    key: ShardKey,
+}
+
+impl ShardedTransform {
+    /// Creates a sharded transform with `n_shards` shards, built by calling `make_shard`.
+    pub fn new(key: ShardKey, n_shards: NonZeroUsize, mut make_shard: impl FnMut() -> Box<dyn Transform>) -> Self {
+        let shards = (0..n_shards.get())
+            .map(|_| Arc::new(Mutex::new(make_shard())))
+            .collect();
+        Self { shards, key }
+    }
+
+    /// Returns the information required to add this transform to the pipeline.
+    pub fn into_spec(self) -> AsyncTransformSpec {
+        AsyncTransformSpec {
+            transform: Box::new(self),
+            // Sharded transforms can keep a state, process the buffers in order.
+            max_concurrency: NonZeroUsize::MIN,
+        }
+    }
+
+    fn split(&self, measurements: MeasurementBuffer) -> Vec<MeasurementBuffer> {
+        let n_shards = self.shards.len();
+        let mut parts: Vec<MeasurementBuffer> = (0..n_shards).map(|_| MeasurementBuffer::new()).collect();
+        for point in measurements {
+            let shard = self.key.shard_of(&point, n_shards);
+            parts[shard].push(point);
+        }
+        parts
+    }
+}
+
+impl AsyncTransform for ShardedTransform {
+    fn apply<'a>(
+        &'a self,
+        measurements: MeasurementBuffer,
+        ctx: AsyncTransformContext,
+    ) -> BoxFuture<'a, Result<MeasurementBuffer, TransformError>> {
+        Box::pin(async move {
+            // Start one task per non-empty part.
+            let parts = self.split(measurements);
+            let mut tasks = Vec::with_capacity(parts.len());
+            for (mut part, shard) in parts.into_iter().zip(&self.shards) {
+                if part.is_empty() {
+                    continue;
+                }
+                let shard = shard.clone();
+                let ctx = ctx.clone();
+                tasks.push(tokio::task::spawn(async move {
+                    let mut transform = shard
+                        .lock()
+                        .map_err(|_| TransformError::Fatal(anyhow!("a previous call to the shard has panicked")))?;
+                    let ctx = TransformContext { metrics: &ctx.metrics };
+                    transform.apply(&mut part, &ctx).map(|_| part)
+                }));
+            }
+
+            // Wait for all the tasks to finish, even if some of them fail, and merge the results.
+            let mut result = MeasurementBuffer::new();
+            let mut error = None;
+            for task in tasks {
+                let res = task
+                    .await
+                    .map_err(|e| TransformError::Fatal(anyhow!("shard task failed: {e}")))
+                    .and_then(|res| res);
+                match (res, &error) {
+                    (Ok(mut part), _) => result.merge(&mut part),
+                    (Err(e), None | Some(TransformError::UnexpectedInput(_))) => error = Some(e),
+                    (Err(_), Some(TransformError::Fatal(_))) => (),
+                }
+            }
+            match error {
+                None => Ok(result),
+                Some(e) => Err(e),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use fxhash::FxHashMap;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{ParallelTransform, ShardKey, ShardedTransform, StatelessTransform};
+    use crate::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::{def::RawMetricId, online::MetricRegistryControl, registry::MetricRegistry},
+        pipeline::elements::transform::{
+            AsyncTransform, AsyncTransformContext, Transform, TransformContext, TransformError,
+        },
+        resources::{Resource, ResourceConsumer},
+    };
+
+    fn context() -> AsyncTransformContext {
+        let rt = tokio::runtime::Handle::current();
+        let (_, metrics, _) = MetricRegistryControl::new(MetricRegistry::new()).start(CancellationToken::new(), &rt);
+        AsyncTransformContext {
+            metrics: metrics.snapshot(),
+        }
+    }
+
+    fn point(pid: u32, value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(0),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::U64(value),
+        )
+    }
+
+    /// Replaces each value by the sum of the values seen so far for the same consumer.
+    #[derive(Default)]
+    struct CumulativeSum {
+        sums: FxHashMap<ResourceConsumer, u64>,
+    }
+
+    impl Transform for CumulativeSum {
+        fn apply(
+            &mut self,
+            measurements: &mut MeasurementBuffer,
+            _ctx: &TransformContext,
+        ) -> Result<(), TransformError> {
+            for m in measurements.iter_mut() {
+                if let WrappedMeasurementValue::U64(v) = m.value {
+                    let sum = self.sums.entry(m.consumer.clone()).or_default();
+                    *sum += v;
+                    m.value = WrappedMeasurementValue::U64(*sum);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sharded_keeps_state_per_key() {
+        let ctx = context();
+        let transform = ShardedTransform::new(ShardKey::Consumer, NonZeroUsize::new(4).unwrap(), || {
+            Box::new(CumulativeSum::default())
+        });
+
+        for round in 1..=3 {
+            let buf: MeasurementBuffer = (0..16).map(|pid| point(pid, 1)).collect();
+            let res = transform.apply(buf, ctx.clone()).await.unwrap();
+            assert_eq!(res.len(), 16);
+            for m in res.iter() {
+                assert_eq!(m.value, WrappedMeasurementValue::U64(round));
+            }
+        }
+    }
+
+    struct Slow {
+        running: AtomicUsize,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    impl StatelessTransform for Slow {
+        fn apply(&self, _measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(50));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn stateless_runs_concurrently() {
+        let ctx = context();
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let transform = ParallelTransform::new(Box::new(Slow {
+            running: AtomicUsize::new(0),
+            max_running: max_running.clone(),
+        }));
+        let results = futures::future::join_all((0..3).map(|pid| {
+            let buf: MeasurementBuffer = [point(pid, 0)].into_iter().collect();
+            transform.apply(buf, ctx.clone())
+        }))
+        .await;
+        for res in results {
+            assert_eq!(res.unwrap().len(), 1);
+        }
+        assert!(max_running.load(Ordering::SeqCst) > 1);
+    }
+}
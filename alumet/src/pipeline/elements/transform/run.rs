@@ -1,4 +1,4 @@
-//! Runtime implementation of the task that executes transforms.
+//! Runtime implementation of the tasks that execute transforms.
 
 use std::sync::{
     atomic::{AtomicU64, Ordering},
@@ -6,6 +6,11 @@ use std::sync::{
 };
 
 use anyhow::Context;
+use futures::{
+    future::{self, BoxFuture},
+    stream::FuturesOrdered,
+    StreamExt,
+};
 use tokio::sync::{broadcast, mpsc};
 
 use crate::{
@@ -14,12 +19,41 @@ use crate::{
     pipeline::{error::PipelineError, naming::TransformName},
 };
 
-use super::{error::TransformError, Transform, TransformContext};
+use super::{builder::AsyncTransformSpec, error::TransformError, AsyncTransformContext, Transform, TransformContext};
+
+/// Where a transform task sends the measurements it has processed.
+pub(super) enum TransformOutput {
+    /// Next transform task.
+    Next(mpsc::Sender<MeasurementBuffer>),
+    /// Outputs of the pipeline.
+    ///
+    /// The buffer is shared between the outputs, which only need to read it.
+    Outputs(broadcast::Sender<Arc<MeasurementBuffer>>),
+}
+
+impl TransformOutput {
+    async fn send(&self, measurements: MeasurementBuffer) -> anyhow::Result<()> {
+        match self {
+            TransformOutput::Next(tx) => tx
+                .send(measurements)
+                .await
+                .context("could not send the measurements to the next transform"),
+            TransformOutput::Outputs(tx) => tx
+                .send(Arc::new(measurements))
+                .map(|_| ())
+                .context("could not send the measurements from transforms to the outputs"),
+        }
+    }
+}
 
-pub async fn run_all_in_order(
+/// Runs a sequence of synchronous transforms, one after another.
+///
+/// `first_index` is the position of the first transform in the bitset `active_flags`.
+pub(super) async fn run_all_in_order(
     mut transforms: Vec<(TransformName, Box<dyn Transform>)>,
+    first_index: usize,
     mut rx: mpsc::Receiver<MeasurementBuffer>,
-    tx: broadcast::Sender<MeasurementBuffer>,
+    tx: TransformOutput,
     active_flags: Arc<AtomicU64>,
     metrics_reader: MetricReader,
 ) -> Result<(), PipelineError> {
@@ -37,18 +71,15 @@ pub async fn run_all_in_order(
             let current_flags = active_flags.load(Ordering::Relaxed);
             log::trace!("current 'enabled' bitset: {current_flags}");
 
-            // Build the transform context.
-            // This will block the publication of any modification to the MetricRegistry until the context is dropped.
-            // TODO this need to change: if transforms take a "long" time to execute, the registry will be blocked for a long time,
-            // which is bad. Usually, transforms don't need to use the MetricRegistry for a long time (see next TODO).
-            // Or, we could store a separate copy of the registry just for transforms.
-            // TODO: this point should be emphasized in the transforms docs so that people don't implement bad transforms.
-            let metrics = &metrics_reader.read().await;
-            let ctx = TransformContext { metrics };
+            // Build the transform context from the latest snapshot of the registry.
+            // This does not lock anything: new metrics can be registered while the transforms run,
+            // they will be visible to the next buffer.
+            let metrics = metrics_reader.snapshot();
+            let ctx = TransformContext { metrics: &metrics };
 
             // Run the enabled transforms. If one of them fails, the ability to continue running depends on the error type.
             for (i, (name, t)) in &mut transforms.iter_mut().enumerate() {
-                let t_flag = 1 << i;
+                let t_flag = 1 << (first_index + i);
                 if current_flags & t_flag != 0 {
                     match t.apply(&mut measurements, &ctx) {
                         Ok(()) => (),
@@ -63,9 +94,8 @@ pub async fn run_all_in_order(
                 }
             }
 
-            // Send the results to the outputs.
-            tx.send(measurements)
-                .context("could not send the measurements from transforms to the outputs")?;
+            // Send the results to the next step.
+            tx.send(measurements).await?;
         } else {
             log::debug!("The channel connected to the transform step has been closed, the transforms will stop.");
             break;
@@ -73,3 +103,68 @@ pub async fn run_all_in_order(
     }
     Ok(())
 }
+
+/// Runs an asynchronous transform.
+///
+/// Up to `max_concurrency` buffers are processed at the same time, but the results
+/// are sent in the order in which the buffers have been received.
+///
+/// `index` is the position of the transform in the bitset `active_flags`.
+pub(super) async fn run_async(
+    name: TransformName,
+    index: usize,
+    spec: AsyncTransformSpec,
+    mut rx: mpsc::Receiver<MeasurementBuffer>,
+    tx: TransformOutput,
+    active_flags: Arc<AtomicU64>,
+    metrics_reader: MetricReader,
+) -> Result<(), PipelineError> {
+    let max_concurrency = spec.max_concurrency.get();
+    log::trace!("Running async transform {name} with a maximum concurrency of {max_concurrency}");
+
+    let transform = spec.transform;
+    let t_flag = 1 << index;
+
+    // FuturesOrdered polls the futures concurrently, but yields their results in order.
+    let mut in_progress: FuturesOrdered<BoxFuture<'_, Result<MeasurementBuffer, TransformError>>> =
+        FuturesOrdered::new();
+    let mut input_closed = false;
+    loop {
+        tokio::select! {
+            Some(res) = in_progress.next(), if !in_progress.is_empty() => {
+                match res {
+                    Ok(measurements) => tx.send(measurements).await?,
+                    Err(TransformError::UnexpectedInput(e)) => {
+                        log::error!("Transform {name} received unexpected measurements: {e:#}");
+                    }
+                    Err(TransformError::Fatal(e)) => {
+                        log::error!("Fatal error in transform {name} (this breaks the transform task!): {e:?}");
+                        return Err(PipelineError::for_element(name, e));
+                    }
+                }
+            },
+            received = rx.recv(), if !input_closed && in_progress.len() < max_concurrency => {
+                match received {
+                    Some(measurements) => {
+                        if active_flags.load(Ordering::Relaxed) & t_flag != 0 {
+                            let ctx = AsyncTransformContext {
+                                metrics: metrics_reader.snapshot(),
+                            };
+                            in_progress.push_back(transform.apply(measurements, ctx));
+                        } else {
+                            // The transform is disabled, but the order must be preserved:
+                            // pass the measurements through after the ones that are being processed.
+                            in_progress.push_back(Box::pin(future::ready(Ok(measurements))));
+                        }
+                    }
+                    None => {
+                        log::debug!("The channel connected to the async transform {name} has been closed, it will stop after the pending measurements.");
+                        input_closed = true;
+                    }
+                }
+            },
+            else => break,
+        }
+    }
+    Ok(())
+}
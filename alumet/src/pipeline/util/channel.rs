@@ -1,4 +1,10 @@
 //! Abstractions over different kinds of channel.
+//!
+//! The measurements are shared between the outputs through an [`Arc`]:
+//! when there are multiple outputs, each one receives a pointer to the same buffer
+//! instead of a copy of it.
+
+use std::sync::Arc;
 
 use futures::Stream;
 use tokio::sync::{broadcast, mpsc};
@@ -7,19 +13,19 @@ use crate::measurement::MeasurementBuffer;
 
 /// Trait that allows to receive measurements from different kinds of channel.
 pub trait MeasurementReceiver {
-    async fn recv(&mut self) -> Result<MeasurementBuffer, RecvError>;
-    fn into_stream(self) -> impl Stream<Item = Result<MeasurementBuffer, StreamRecvError>>;
+    async fn recv(&mut self) -> Result<Arc<MeasurementBuffer>, RecvError>;
+    fn into_stream(self) -> impl Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>>;
 }
 
 pub enum ReceiverEnum {
-    Broadcast(broadcast::Receiver<MeasurementBuffer>),
+    Broadcast(broadcast::Receiver<Arc<MeasurementBuffer>>),
     Single(mpsc::Receiver<MeasurementBuffer>),
 }
 
 pub struct ReceiverProvider(ProviderEnum);
 
 enum ProviderEnum {
-    Broadcast(broadcast::Sender<MeasurementBuffer>),
+    Broadcast(broadcast::Sender<Arc<MeasurementBuffer>>),
     Single(Option<mpsc::Receiver<MeasurementBuffer>>),
 }
 
@@ -38,15 +44,15 @@ pub enum StreamRecvError {
 
 // receiver implementations
 
-impl MeasurementReceiver for broadcast::Receiver<MeasurementBuffer> {
-    async fn recv(&mut self) -> Result<MeasurementBuffer, RecvError> {
+impl MeasurementReceiver for broadcast::Receiver<Arc<MeasurementBuffer>> {
+    async fn recv(&mut self) -> Result<Arc<MeasurementBuffer>, RecvError> {
         broadcast::Receiver::recv(self).await.map_err(|e| match e {
             broadcast::error::RecvError::Closed => RecvError::Closed,
             broadcast::error::RecvError::Lagged(n) => RecvError::Lagged(n),
         })
     }
 
-    fn into_stream(self) -> impl Stream<Item = Result<MeasurementBuffer, StreamRecvError>> {
+    fn into_stream(self) -> impl Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>> {
         use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
         use tokio_stream::StreamExt;
 
@@ -59,16 +65,16 @@ impl MeasurementReceiver for broadcast::Receiver<MeasurementBuffer> {
 }
 
 impl MeasurementReceiver for mpsc::Receiver<MeasurementBuffer> {
-    async fn recv(&mut self) -> Result<MeasurementBuffer, RecvError> {
+    async fn recv(&mut self) -> Result<Arc<MeasurementBuffer>, RecvError> {
         match mpsc::Receiver::recv(self).await {
-            Some(buf) => Ok(buf),
+            Some(buf) => Ok(Arc::new(buf)),
             None => Err(RecvError::Closed),
         }
     }
 
-    fn into_stream(self) -> impl Stream<Item = Result<MeasurementBuffer, StreamRecvError>> {
+    fn into_stream(self) -> impl Stream<Item = Result<Arc<MeasurementBuffer>, StreamRecvError>> {
         use tokio_stream::{wrappers::ReceiverStream, StreamExt};
-        ReceiverStream::new(self).map(Ok)
+        ReceiverStream::new(self).map(|buf| Ok(Arc::new(buf)))
     }
 }
 
@@ -86,8 +92,8 @@ impl ReceiverProvider {
     }
 }
 
-impl From<broadcast::Sender<MeasurementBuffer>> for ReceiverProvider {
-    fn from(value: broadcast::Sender<MeasurementBuffer>) -> Self {
+impl From<broadcast::Sender<Arc<MeasurementBuffer>>> for ReceiverProvider {
+    fn from(value: broadcast::Sender<Arc<MeasurementBuffer>>) -> Self {
         Self(ProviderEnum::Broadcast(value))
     }
 }
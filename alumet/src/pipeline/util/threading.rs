@@ -1,16 +1,188 @@
 //! Utilities for working with OS threads.
 
 use std::{
+    fmt::Display,
     io,
     path::PathBuf,
+    str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Mutex,
     },
 };
 
+use anyhow::{anyhow, Context};
 use tokio::runtime::Runtime;
 
+/// A set of CPUs, used to restrict the CPUs that the threads of a runtime can run on.
+///
+/// It can be parsed from the list format used by Linux (see `man 7 cpuset`),
+/// for instance `0-3,6`.
+///
+/// # Example
+/// ```
+/// use alumet::pipeline::builder::CpuSet;
+///
+/// let cpus: CpuSet = "0-2,5".parse().unwrap();
+/// assert_eq!(cpus.cpus(), &[0, 1, 2, 5]);
+/// assert_eq!(cpus.to_string(), "0-2,5");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuSet {
+    /// Sorted and deduplicated CPU ids.
+    cpus: Vec<usize>,
+}
+
+impl CpuSet {
+    /// Returns the ids of the CPUs in the set, in increasing order.
+    pub fn cpus(&self) -> &[usize] {
+        &self.cpus
+    }
+
+    /// Returns the number of CPUs in the set.
+    pub fn len(&self) -> usize {
+        self.cpus.len()
+    }
+
+    /// Returns `true` if the set contains no CPU.
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_empty()
+    }
+
+    /// Checks that all the CPUs of the set are online.
+    pub fn check_online(&self) -> anyhow::Result<()> {
+        let online = online_cpus().context("could not get the list of online CPUs")?;
+        let offline: Vec<String> = self
+            .cpus
+            .iter()
+            .filter(|cpu| online.cpus.binary_search(cpu).is_err())
+            .map(|cpu| cpu.to_string())
+            .collect();
+        if offline.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "CPUs {} are not online (online CPUs: {online})",
+                offline.join(",")
+            ))
+        }
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse_id(id: &str) -> anyhow::Result<usize> {
+            id.trim().parse().with_context(|| format!("invalid CPU id '{id}'"))
+        }
+
+        let mut cpus = Vec::new();
+        for part in s.trim().split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse_id(first)?, parse_id(last)?);
+                    if first > last {
+                        return Err(anyhow!("invalid CPU range '{part}': {first} > {last}"));
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => cpus.push(parse_id(part)?),
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(Self { cpus })
+    }
+}
+
+impl Display for CpuSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Group the consecutive ids into ranges.
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for &cpu in &self.cpus {
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == cpu => *last = cpu,
+                _ => ranges.push((cpu, cpu)),
+            }
+        }
+        let ranges: Vec<String> = ranges
+            .into_iter()
+            .map(|(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{first}-{last}")
+                }
+            })
+            .collect();
+        f.write_str(&ranges.join(","))
+    }
+}
+
+/// Returns the set of CPUs that are currently online.
+pub fn online_cpus() -> io::Result<CpuSet> {
+    #[cfg(target_os = "linux")]
+    {
+        let content = std::fs::read_to_string("/sys/devices/system/cpu/online")?;
+        content
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:#}")))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cannot get the list of online CPUs on this platform",
+    ))
+}
+
+/// Restricts the current thread to the given CPUs.
+pub fn set_cpu_affinity(cpus: &CpuSet) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: zero is a valid value for cpu_set_t, it represents an empty set.
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let max_cpus = 8 * std::mem::size_of::<libc::cpu_set_t>();
+        for &cpu in &cpus.cpus {
+            if cpu >= max_cpus {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("CPU id {cpu} is too large, the maximum is {}", max_cpus - 1),
+                ));
+            }
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        // pid 0 means "the calling thread"
+        let res = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+        if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = cpus;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cannot set the CPU affinity on this platform",
+        ))
+    }
+}
+
+/// Applies the CPU affinity (if any) to the current thread, and logs the error if it fails.
+fn apply_cpu_affinity(cpus: &Option<CpuSet>) {
+    if let Some(cpus) = cpus {
+        if let Err(e) = set_cpu_affinity(cpus) {
+            let current_thread = std::thread::current();
+            let thread_name = current_thread.name().unwrap_or("<unnamed>");
+            log::error!("Unable to restrict thread {thread_name} to CPUs {cpus}: {e}");
+        }
+    }
+}
+
 /// Sets the scheduling priority of the current thread to be closer to "real time".
 pub fn use_realtime_scheduling() -> std::io::Result<()> {
     // On Linux, the simplest thing would be to call libc::sched_setscheduler(0, libc::SCHED_FIFO, &params).
@@ -59,20 +231,23 @@ pub fn use_realtime_scheduling() -> std::io::Result<()> {
     ))
 }
 
-pub fn build_normal_runtime(worker_threads: Option<usize>) -> io::Result<Runtime> {
+pub fn build_normal_runtime(worker_threads: Option<usize>, cpu_affinity: Option<CpuSet>) -> io::Result<Runtime> {
     let mut builder = tokio::runtime::Builder::new_multi_thread();
-    builder.enable_all().thread_name_fn(|| {
-        static ATOMIC_ID: AtomicUsize = AtomicUsize::new(0);
-        let id = ATOMIC_ID.fetch_add(1, Ordering::SeqCst);
-        format!("normal-worker-{id}")
-    });
+    builder
+        .enable_all()
+        .thread_name_fn(|| {
+            static ATOMIC_ID: AtomicUsize = AtomicUsize::new(0);
+            let id = ATOMIC_ID.fetch_add(1, Ordering::SeqCst);
+            format!("normal-worker-{id}")
+        })
+        .on_thread_start(move || apply_cpu_affinity(&cpu_affinity));
     if let Some(n) = worker_threads {
         builder.worker_threads(n);
     }
     builder.build()
 }
 
-pub fn build_priority_runtime(worker_threads: Option<usize>) -> io::Result<Runtime> {
+pub fn build_priority_runtime(worker_threads: Option<usize>, cpu_affinity: Option<CpuSet>) -> io::Result<Runtime> {
     fn resolve_application_path() -> io::Result<PathBuf> {
         std::env::current_exe()?.canonicalize()
     }
@@ -92,7 +267,8 @@ pub fn build_priority_runtime(worker_threads: Option<usize>) -> io::Result<Runti
                 let id = ATOMIC_ID.fetch_add(1, Ordering::SeqCst);
                 format!("priority-worker-{id}")
             })
-            .on_thread_start(|| {
+            .on_thread_start(move || {
+                apply_cpu_affinity(&cpu_affinity);
                 if let Err(e) = super::threading::use_realtime_scheduling() {
                     let mut failure = THREAD_START_FAILURE.lock().unwrap();
                     if failure.is_none() {
@@ -145,3 +321,36 @@ pub fn build_priority_runtime(worker_threads: Option<usize>) -> io::Result<Runti
     }
     Ok(runtime)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CpuSet;
+
+    #[test]
+    fn parse_cpu_set() {
+        let cpus: CpuSet = "0-1".parse().unwrap();
+        assert_eq!(cpus.cpus(), &[0, 1]);
+
+        let cpus: CpuSet = "6, 0-2,1,4-4\n".parse().unwrap();
+        assert_eq!(cpus.cpus(), &[0, 1, 2, 4, 6]);
+        assert_eq!(cpus.to_string(), "0-2,4,6");
+
+        assert!("".parse::<CpuSet>().is_err());
+        assert!("2-1".parse::<CpuSet>().is_err());
+        assert!("0,a".parse::<CpuSet>().is_err());
+        assert!("-1".parse::<CpuSet>().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn online_cpus() {
+        let online = super::online_cpus().unwrap();
+        assert!(!online.is_empty());
+        online.check_online().unwrap();
+
+        let too_large = CpuSet {
+            cpus: vec![online.cpus().last().unwrap() + 1],
+        };
+        assert!(too_large.check_online().is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Phases of the plugins lifecycle.
 use std::marker::PhantomData;
+use std::num::NonZeroUsize;
 
 use crate::measurement::{MeasurementType, WrappedMeasurementType};
 use crate::metrics::def::{Metric, RawMetricId, TypedMetricId};
@@ -8,10 +9,15 @@ use crate::metrics::online::listener::{MetricListener, MetricListenerBuilder};
 use crate::metrics::online::{MetricReader, MetricSender};
 use crate::metrics::registry::MetricRegistry;
 use crate::pipeline::control::key::{OutputKey, SourceKey, TransformKey};
+use crate::pipeline::elements::output::batch::BatchSettings;
 use crate::pipeline::elements::source::builder::{ManagedSource, SourceBuilder};
 use crate::pipeline::elements::source::trigger::TriggerSpec;
+use crate::pipeline::elements::transform::parallel::{
+    ParallelTransform, ShardKey, ShardedTransform, StatelessTransform,
+};
+use crate::pipeline::elements::transform::AsyncTransform;
 use crate::pipeline::elements::{output, source, transform};
-use crate::pipeline::naming::{namespace::DuplicateNameError, PluginName};
+use crate::pipeline::naming::{namespace::DuplicateNameError, OutputName, PluginName};
 use crate::pipeline::{self, Output, Source, Transform};
 use crate::units::PrefixedUnit;
 
@@ -238,8 +244,120 @@ impl<'a> AlumetPluginStart<'a> {
         builder: F,
     ) -> Result<TransformKey, DuplicateNameError> {
         let plugin = self.current_plugin_name();
-        self.pipeline_builder
-            .add_transform_builder(plugin, name, Box::new(builder))
+        let builder = transform::builder::AnyTransformBuilder::Sync(Box::new(builder));
+        self.pipeline_builder.add_transform_builder(plugin, name, builder)
+    }
+
+    /// Adds an _async_ transform step to the Alumet pipeline.
+    ///
+    /// Up to `max_concurrency` buffers will be processed concurrently by the transform.
+    /// The order of the measurements is preserved.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::num::NonZeroUsize;
+    /// use futures::future::BoxFuture;
+    /// use alumet::pipeline::elements::transform::{AsyncTransform, AsyncTransformContext};
+    /// use alumet::pipeline::elements::error::TransformError;
+    /// use alumet::measurement::MeasurementBuffer;
+    /// # use alumet::plugin::AlumetPluginStart;
+    ///
+    /// // Define the transform
+    /// struct ExampleTransform;
+    /// impl AsyncTransform for ExampleTransform {
+    ///     fn apply<'a>(
+    ///         &'a self,
+    ///         m: MeasurementBuffer,
+    ///         ctx: AsyncTransformContext,
+    ///     ) -> BoxFuture<'a, Result<MeasurementBuffer, TransformError>> {
+    ///         Box::pin(async move {
+    ///             todo!(); // do something with the measurements
+    ///             Ok(m)
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// # let alumet: &AlumetPluginStart = todo!();
+    /// #
+    /// // In start(&mut self, alumet: &mut AlumetPluginStart),
+    /// // add the transform to the pipeline.
+    /// let transform = ExampleTransform;
+    /// alumet.add_async_transform("name", Box::new(transform), NonZeroUsize::new(4).unwrap());
+    /// ```
+    pub fn add_async_transform(
+        &mut self,
+        name: &str,
+        transform: Box<dyn AsyncTransform>,
+        max_concurrency: NonZeroUsize,
+    ) -> Result<TransformKey, DuplicateNameError> {
+        self.add_async_transform_builder(name, move |_| {
+            Ok(transform::builder::AsyncTransformSpec {
+                transform,
+                max_concurrency,
+            })
+        })
+    }
+
+    /// Adds a _stateless_ transform step to the Alumet pipeline.
+    ///
+    /// Since the transform does not keep any state, up to `max_concurrency` buffers
+    /// can be processed in parallel, on multiple threads. The order of the measurements is preserved.
+    ///
+    /// See [`transform::parallel`] for more information.
+    pub fn add_stateless_transform(
+        &mut self,
+        name: &str,
+        transform: Box<dyn StatelessTransform>,
+        max_concurrency: NonZeroUsize,
+    ) -> Result<TransformKey, DuplicateNameError> {
+        self.add_async_transform_builder(name, move |_| {
+            Ok(ParallelTransform::new(transform).into_spec(max_concurrency))
+        })
+    }
+
+    /// Adds a _sharded_ transform step to the Alumet pipeline.
+    ///
+    /// `make_shard` is called `n_shards` times to create the shards, which are independent instances of the transform.
+    /// The points of each buffer are dispatched to the shards according to the `key`, and the shards run in parallel.
+    ///
+    /// See [`transform::parallel`] for more information.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::num::NonZeroUsize;
+    /// use alumet::pipeline::elements::transform::{parallel::ShardKey, Transform};
+    /// # use alumet::plugin::AlumetPluginStart;
+    ///
+    /// fn new_transform() -> Box<dyn Transform> {
+    ///     todo!("create a transform that keeps a state per consumer")
+    /// }
+    ///
+    /// # let alumet: &mut AlumetPluginStart = todo!();
+    /// alumet.add_sharded_transform("name", ShardKey::Consumer, NonZeroUsize::new(4).unwrap(), new_transform);
+    /// ```
+    pub fn add_sharded_transform(
+        &mut self,
+        name: &str,
+        key: ShardKey,
+        n_shards: NonZeroUsize,
+        make_shard: impl FnMut() -> Box<dyn Transform> + 'static,
+    ) -> Result<TransformKey, DuplicateNameError> {
+        self.add_async_transform_builder(name, move |_| {
+            Ok(ShardedTransform::new(key, n_shards, make_shard).into_spec())
+        })
+    }
+
+    /// Adds the builder of an _async_ transform step to the Alumet pipeline.
+    pub fn add_async_transform_builder<F: transform::builder::AsyncTransformBuilder + 'static>(
+        &mut self,
+        name: &str,
+        builder: F,
+    ) -> Result<TransformKey, DuplicateNameError> {
+        let plugin = self.current_plugin_name();
+        let builder = transform::builder::AnyTransformBuilder::Async(Box::new(builder));
+        self.pipeline_builder.add_transform_builder(plugin, name, builder)
     }
 
     /// Adds a _blocking_ output to the Alumet pipeline.
@@ -310,6 +428,29 @@ impl<'a> AlumetPluginStart<'a> {
         self.pipeline_builder.add_output_builder(plugin, name, builder)
     }
 
+    /// Enables the batching of measurements for an output of this plugin.
+    ///
+    /// Instead of receiving every buffer as soon as it is produced, the output will receive
+    /// larger buffers, flushed according to the `settings` and on shutdown.
+    /// This works for blocking and async outputs.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use alumet::pipeline::elements::output::batch::BatchSettings;
+    /// # use alumet::plugin::AlumetPluginStart;
+    /// # use alumet::pipeline::Output;
+    ///
+    /// # let alumet: &mut AlumetPluginStart = todo!();
+    /// # let output: Box<dyn Output> = todo!();
+    /// alumet.add_blocking_output("out", output);
+    /// alumet.set_output_batching("out", BatchSettings::default().max_points(4096).max_delay(Duration::from_secs(1)));
+    /// ```
+    pub fn set_output_batching(&mut self, output_name: &str, settings: BatchSettings) {
+        let name = OutputName::new(self.current_plugin.0.clone(), output_name.to_owned());
+        self.pipeline_builder.set_output_batching(name, settings);
+    }
+
     /// Registers a callback that will run just after the pipeline startup.
     ///
     /// If you have some data to move to the pipeline start phase, it's easier
@@ -358,6 +499,64 @@ impl<'a> AlumetPreStart<'a> {
         &self.pipeline_builder.metrics
     }
 
+    /// Creates a new metric with a measurement type `T` (checked at compile time).
+    /// Fails if a metric with the same name already exists.
+    ///
+    /// At this stage, all the plugins have been started: the metrics they have created are
+    /// visible in [`metrics`](Self::metrics). This allows to derive a new metric from
+    /// the metric of another plugin, for instance to reuse its unit.
+    ///
+    /// See [`AlumetPluginStart::create_metric`].
+    pub fn create_metric<T: MeasurementType>(
+        &mut self,
+        name: impl Into<String>,
+        unit: impl Into<PrefixedUnit>,
+        description: impl Into<String>,
+    ) -> Result<TypedMetricId<T>, MetricCreationError> {
+        let m = Metric {
+            name: name.into(),
+            description: description.into(),
+            value_type: T::wrapped_type(),
+            unit: unit.into(),
+        };
+        let untyped_id = self.pipeline_builder.metrics.register(m)?;
+        Ok(TypedMetricId(untyped_id, PhantomData))
+    }
+
+    /// Creates a new metric with a measurement type `value_type` (checked at **run time**).
+    /// Fails if a metric with the same name already exists.
+    ///
+    /// See [`AlumetPluginStart::create_metric_untyped`].
+    pub fn create_metric_untyped(
+        &mut self,
+        name: &str,
+        value_type: WrappedMeasurementType,
+        unit: impl Into<PrefixedUnit>,
+        description: &str,
+    ) -> Result<RawMetricId, MetricCreationError> {
+        let m = Metric {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            value_type,
+            unit: unit.into(),
+        };
+        self.pipeline_builder.metrics.register(m)
+    }
+
+    /// Adds a transform step to the Alumet pipeline.
+    ///
+    /// This is useful for transforms that depend on the metrics of other plugins.
+    /// See [`AlumetPluginStart::add_transform`].
+    pub fn add_transform(
+        &mut self,
+        name: &str,
+        transform: Box<dyn Transform>,
+    ) -> Result<TransformKey, DuplicateNameError> {
+        let plugin = self.current_plugin_name();
+        let builder = transform::builder::AnyTransformBuilder::Sync(Box::new(|_| Ok(transform)));
+        self.pipeline_builder.add_transform_builder(plugin, name, builder)
+    }
+
     /// Registers a metric listener, which will be notified of all the new registered metrics.
     pub fn add_metric_listener<F: MetricListener + Send + 'static>(
         &mut self,
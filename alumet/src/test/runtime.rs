@@ -1,15 +1,14 @@
 use std::{cell::RefCell, ops::Deref, rc::Rc, time::Duration};
 
 use fxhash::FxHashMap;
-use tokio::sync::RwLockReadGuard;
 use wrapped_output::{OutputDone, SetOutputOutputCheck, WrappedOutput};
 use wrapped_source::{SetSourceCheck, SourceDone, WrappedManagedSource};
-use wrapped_transform::{SetTransformOutputCheck, TransformDone, WrappedTransform};
+use wrapped_transform::{SetTransformOutputCheck, TransformDone, WrappedAsyncTransform, WrappedTransform};
 
 use crate::{
     agent::builder::TestExpectations,
     measurement::MeasurementBuffer,
-    metrics::registry::MetricRegistry,
+    metrics::{online::RegistrySnapshot, registry::MetricRegistry},
     pipeline::{
         control::{
             message::matching::{SourceMatcher, TransformMatcher},
@@ -23,7 +22,10 @@ use crate::{
                 control::TriggerMessage,
                 trigger,
             },
-            transform::{self, builder::TransformBuilder},
+            transform::{
+                self,
+                builder::{AnyTransformBuilder, AsyncTransformBuilder, TransformBuilder},
+            },
         },
         matching::{SourceNamePattern, TransformNamePattern},
         naming::{OutputName, PluginName, SourceName, TransformName},
@@ -198,6 +200,37 @@ impl TestExpectations for RuntimeExpectations {
             })
         }
 
+        fn wrap_async_transform_builder(
+            name: TransformName,
+            checks: Vec<TransformCheck>,
+            builder: Box<dyn AsyncTransformBuilder>,
+            controllers: TestControllerMap<TransformName, TransformTestController>,
+        ) -> Box<dyn AsyncTransformBuilder> {
+            Box::new(move |ctx| {
+                let mut spec = builder(ctx)?;
+
+                // same as synchronous transforms
+                let (set_tx, set_rx) = tokio::sync::mpsc::channel(1);
+                let (done_tx, done_rx) = tokio::sync::mpsc::channel(1);
+                controllers.borrow_mut().insert(
+                    name,
+                    TransformTestController {
+                        checks,
+                        set_tx,
+                        done_rx,
+                    },
+                );
+
+                // wrap the transform
+                spec.transform = Box::new(WrappedAsyncTransform {
+                    transform: spec.transform,
+                    set_rx: std::sync::Mutex::new(set_rx),
+                    done_tx,
+                });
+                Ok(spec)
+            })
+        }
+
         fn wrap_blocking_output_builder(
             name: OutputName,
             checks: Vec<OutputCheck>,
@@ -320,7 +353,20 @@ impl TestExpectations for RuntimeExpectations {
                 log::debug!("preparing {name} for testing");
                 // Similar to sources, every transform must be wrapped to prevent any interference with output checks.
                 let checks = self.transforms.remove(&name).unwrap_or_default();
-                wrap_transform_builder(name, checks, builder, transform_tests_before.clone())
+                match builder {
+                    AnyTransformBuilder::Sync(b) => AnyTransformBuilder::Sync(wrap_transform_builder(
+                        name,
+                        checks,
+                        b,
+                        transform_tests_before.clone(),
+                    )),
+                    AnyTransformBuilder::Async(b) => AnyTransformBuilder::Async(wrap_async_transform_builder(
+                        name,
+                        checks,
+                        b,
+                        transform_tests_before.clone(),
+                    )),
+                }
             });
 
             // Wrap the outputs
@@ -449,8 +495,7 @@ impl TestExpectations for RuntimeExpectations {
                         set_tx.send(SetTransformOutputCheck(check.check_output)).await.unwrap();
 
                         // build the test input with user-provided code
-                        let lock = mr.read().await;
-                        let mut ctx = TransformCheckInputContext { metrics: lock };
+                        let mut ctx = TransformCheckInputContext { metrics: mr.snapshot() };
                         let test_data = (check.make_input)(&mut ctx);
 
                         // trigger the "tester" source with the test input
@@ -488,8 +533,7 @@ impl TestExpectations for RuntimeExpectations {
                         set_tx.send(SetOutputOutputCheck(check.check_output)).await.unwrap();
 
                         // build the test input with user-provided code
-                        let lock = mr.read().await;
-                        let mut ctx = OutputCheckInputContext { metrics: lock };
+                        let mut ctx = OutputCheckInputContext { metrics: mr.snapshot() };
                         let test_data = (check.make_input)(&mut ctx);
 
                         // trigger the "tester" source with the test input
@@ -615,22 +659,22 @@ pub struct OutputCheck {
     check_output: Box<dyn Fn() + Send>,
 }
 
-pub struct TransformCheckInputContext<'a> {
-    metrics: RwLockReadGuard<'a, MetricRegistry>,
+pub struct TransformCheckInputContext {
+    metrics: RegistrySnapshot,
 }
 
-impl<'a> TransformCheckInputContext<'a> {
-    pub fn metrics(&'a self) -> &'a MetricRegistry {
+impl TransformCheckInputContext {
+    pub fn metrics(&self) -> &MetricRegistry {
         self.metrics.deref()
     }
 }
 
-pub struct OutputCheckInputContext<'a> {
-    metrics: RwLockReadGuard<'a, MetricRegistry>,
+pub struct OutputCheckInputContext {
+    metrics: RegistrySnapshot,
 }
 
-impl<'a> OutputCheckInputContext<'a> {
-    pub fn metrics(&'a self) -> &'a MetricRegistry {
+impl OutputCheckInputContext {
+    pub fn metrics(&self) -> &MetricRegistry {
         self.metrics.deref()
     }
 }
@@ -1,12 +1,19 @@
-use std::panic::{self, AssertUnwindSafe};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+};
 
 use anyhow::anyhow;
+use futures::{future::BoxFuture, FutureExt};
 use tokio::sync::mpsc::{self, error::TryRecvError};
 
 use crate::{
     measurement::MeasurementBuffer,
     pipeline::{
-        elements::{error::TransformError, transform::TransformContext},
+        elements::{
+            error::TransformError,
+            transform::{AsyncTransform, AsyncTransformContext, TransformContext},
+        },
         Transform,
     },
 };
@@ -19,6 +26,13 @@ pub(super) struct WrappedTransform {
     pub done_tx: mpsc::Sender<TransformDone>,
 }
 
+pub(super) struct WrappedAsyncTransform {
+    pub transform: Box<dyn AsyncTransform>,
+    // AsyncTransform::apply takes `&self`, hence the Mutex
+    pub set_rx: Mutex<mpsc::Receiver<SetTransformOutputCheck>>,
+    pub done_tx: mpsc::Sender<TransformDone>,
+}
+
 pub struct SetTransformOutputCheck(pub Box<dyn Fn(&MeasurementBuffer) + Send>);
 pub struct TransformDone;
 
@@ -67,3 +81,49 @@ impl WrappedTransform {
         }
     }
 }
+
+impl AsyncTransform for WrappedAsyncTransform {
+    fn apply<'a>(
+        &'a self,
+        measurements: MeasurementBuffer,
+        ctx: AsyncTransformContext,
+    ) -> BoxFuture<'a, Result<MeasurementBuffer, TransformError>> {
+        Box::pin(async move {
+            // run the transform
+            log::trace!("applying underlying async transform");
+            let res = AssertUnwindSafe(self.transform.apply(measurements, ctx))
+                .catch_unwind()
+                .await;
+            let measurements = match res {
+                Ok(res) => res?,
+                Err(panic) => {
+                    return Err(TransformError::Fatal(anyhow!(
+                        "transform panicked: {:?}",
+                        PrettyAny(panic)
+                    )))
+                }
+            };
+
+            // if set, check the output
+            let check = self.set_rx.lock().unwrap().try_recv();
+            match check {
+                Ok(check) => {
+                    log::trace!("applying check");
+                    panic::catch_unwind(AssertUnwindSafe(|| (check.0)(&measurements))).map_err(|panic| {
+                        TransformError::Fatal(anyhow!("transform panicked: {:?}", PrettyAny(panic)))
+                    })?;
+
+                    log::trace!("wrapped transform done");
+                    self.done_tx.try_send(TransformDone).unwrap();
+                }
+                Err(TryRecvError::Empty) => {
+                    log::trace!("no check to perform on this operation");
+                }
+                Err(TryRecvError::Disconnected) => {
+                    log::trace!("there will be no more transform checks");
+                }
+            }
+            Ok(measurements)
+        })
+    }
+}
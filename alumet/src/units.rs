@@ -218,6 +218,28 @@ impl PrefixedUnit {
     pub fn display_name(&self) -> String {
         format!("{self}")
     }
+
+    /// Returns the factor to apply to a value in this unit to get a value in `unit`, without prefix.
+    ///
+    /// Returns `None` if the two units do not measure the same quantity.
+    ///
+    /// # Example
+    /// ```
+    /// use alumet::units::{PrefixedUnit, Unit};
+    ///
+    /// assert_eq!(PrefixedUnit::milli(Unit::Joule).factor(&Unit::Joule), Some(1e-3));
+    /// assert_eq!(PrefixedUnit::kilo(Unit::WattHour).factor(&Unit::Joule), Some(3.6e6));
+    /// assert_eq!(PrefixedUnit::micro(Unit::Second).factor(&Unit::Joule), None);
+    /// ```
+    pub fn factor(&self, unit: &Unit) -> Option<f64> {
+        let base = match (&self.base_unit, unit) {
+            (a, b) if a == b => 1.0,
+            (Unit::WattHour, Unit::Joule) => 3600.0,
+            (Unit::Joule, Unit::WattHour) => 1.0 / 3600.0,
+            _ => return None,
+        };
+        Some(base * self.prefix.factor())
+    }
 }
 
 impl From<Unit> for PrefixedUnit {
@@ -248,6 +270,19 @@ impl UnitPrefix {
         }
     }
 
+    /// Returns the factor to apply to a value with this prefix to get a value without prefix.
+    pub fn factor(&self) -> f64 {
+        match self {
+            UnitPrefix::Nano => 1e-9,
+            UnitPrefix::Micro => 1e-6,
+            UnitPrefix::Milli => 1e-3,
+            UnitPrefix::Plain => 1.0,
+            UnitPrefix::Kilo => 1e3,
+            UnitPrefix::Mega => 1e6,
+            UnitPrefix::Giga => 1e9,
+        }
+    }
+
     /// Returns the name to use when displaying (aka printing) the prefix, as specified by the Unified Code for Units of Measure (UCUM).
     ///
     /// See <https://ucum.org/ucum#section-Prefixes>
@@ -291,7 +326,7 @@ impl FromStr for UnitPrefix {
 
 #[cfg(test)]
 mod tests {
-    use super::{Unit, UnitPrefix};
+    use super::{PrefixedUnit, Unit, UnitPrefix};
 
     #[test]
     fn unit_serde() {
@@ -328,4 +363,13 @@ mod tests {
         assert_eq!(parse_self(UnitPrefix::Mega), UnitPrefix::Mega);
         assert_eq!(parse_self(UnitPrefix::Giga), UnitPrefix::Giga);
     }
+
+    #[test]
+    fn conversion_factors() {
+        assert_eq!(PrefixedUnit::from(Unit::Joule).factor(&Unit::Joule), Some(1.0));
+        assert_eq!(PrefixedUnit::nano(Unit::Second).factor(&Unit::Second), Some(1e-9));
+        assert_eq!(PrefixedUnit::giga(Unit::Byte).factor(&Unit::Byte), Some(1e9));
+        assert_eq!(PrefixedUnit::from(Unit::WattHour).factor(&Unit::Joule), Some(3600.0));
+        assert_eq!(PrefixedUnit::from(Unit::Watt).factor(&Unit::Joule), None);
+    }
 }
@@ -0,0 +1,130 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+use alumet::{
+    agent::{self, plugin::PluginSet},
+    measurement::{MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::TypedMetricId,
+    pipeline::{
+        self,
+        elements::{
+            error::{PollError, WriteError},
+            output::OutputContext,
+            source::trigger::TriggerSpec,
+        },
+        Output, Source,
+    },
+    plugin::{rust::AlumetPlugin, AlumetPluginStart, ConfigTable},
+    resources::{Resource, ResourceConsumer},
+    static_plugins,
+    units::Unit,
+};
+use anyhow::Context;
+
+/// Number of polls, by source: `startup`, `shutdown`, `both`.
+static POLLS: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+/// Number of points written to the output, by source.
+static WRITTEN: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+struct TestPlugin;
+
+struct TestSource {
+    index: u64,
+    metric: TypedMetricId<u64>,
+}
+
+struct TestOutput;
+
+impl AlumetPlugin for TestPlugin {
+    fn name() -> &'static str {
+        "one_shot_trigger"
+    }
+
+    fn version() -> &'static str {
+        "0.0.1"
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(None)
+    }
+
+    fn init(_config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        Ok(Box::new(TestPlugin))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let metric = alumet.create_metric("source_index", Unit::Unity, "index of the source")?;
+        let triggers = [
+            TriggerSpec::at_startup(),
+            TriggerSpec::at_shutdown(),
+            TriggerSpec::at_startup_and_shutdown(),
+        ];
+        for (index, trigger) in triggers.into_iter().enumerate() {
+            let source = TestSource {
+                index: index as u64,
+                metric,
+            };
+            alumet.add_source(&format!("source_{index}"), Box::new(source), trigger)?;
+        }
+        alumet.add_blocking_output("out", Box::new(TestOutput))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl Source for TestSource {
+    fn poll(&mut self, m: &mut MeasurementAccumulator, t: Timestamp) -> Result<(), PollError> {
+        POLLS[self.index as usize].fetch_add(1, Ordering::Relaxed);
+        m.push(MeasurementPoint::new(
+            t,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            self.index,
+        ));
+        Ok(())
+    }
+}
+
+impl Output for TestOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        for m in measurements {
+            if let WrappedMeasurementValue::U64(index) = m.value {
+                WRITTEN[index as usize].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn one_shot_triggers() -> anyhow::Result<()> {
+    let plugins = PluginSet::from(static_plugins![TestPlugin]);
+    let agent = agent::Builder::from_pipeline(plugins, pipeline::Builder::new())
+        .build_and_start()
+        .expect("agent should start fine");
+
+    // Let the sources start, then check that only the startup triggers have polled.
+    thread::sleep(Duration::from_millis(500));
+    let polls: Vec<u64> = POLLS.iter().map(|n| n.load(Ordering::Relaxed)).collect();
+    assert_eq!(polls, vec![1, 0, 1]);
+
+    // Stop Alumet
+    agent.pipeline.control_handle().shutdown();
+    agent
+        .wait_for_shutdown(Duration::from_secs(2))
+        .context("error while shutting down")?;
+
+    // The shutdown triggers have polled, and every measurement has reached the output.
+    let polls: Vec<u64> = POLLS.iter().map(|n| n.load(Ordering::Relaxed)).collect();
+    let written: Vec<u64> = WRITTEN.iter().map(|n| n.load(Ordering::Relaxed)).collect();
+    assert_eq!(polls, vec![1, 1, 2]);
+    assert_eq!(written, vec![1, 1, 2]);
+    Ok(())
+}
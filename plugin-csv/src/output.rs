@@ -6,7 +6,7 @@ use std::{
     time::SystemTime,
 };
 
-use alumet::measurement::WrappedMeasurementValue;
+use alumet::measurement::{WrappedMeasurementValue, STALE_MARKER_ATTRIBUTE};
 use alumet::{
     measurement::MeasurementBuffer,
     pipeline::elements::{error::WriteError, output::OutputContext},
@@ -61,7 +61,11 @@ impl CsvOutput {
 fn collect_attribute_keys(buf: &MeasurementBuffer) -> HashSet<String> {
     let mut res = HashSet::new();
     for m in buf.iter() {
-        res.extend(m.attributes_keys().map(|k| k.to_owned()));
+        res.extend(
+            m.attributes_keys()
+                .filter(|k| *k != STALE_MARKER_ATTRIBUTE)
+                .map(|k| k.to_owned()),
+        );
     }
     res
 }
@@ -120,7 +124,9 @@ impl alumet::pipeline::Output for CsvOutput {
             // convert every field to string
             let datetime: OffsetDateTime = SystemTime::from(m.timestamp).into();
             let datetime: String = datetime.format(&Rfc3339)?;
+            // staleness markers have no real value
             let value = match m.value {
+                _ if m.is_stale_marker() => String::from("stale"),
                 WrappedMeasurementValue::F64(x) => x.to_string(),
                 WrappedMeasurementValue::U64(x) => x.to_string(),
             };
@@ -141,7 +147,10 @@ impl alumet::pipeline::Output for CsvOutput {
             ];
 
             // Sort the attributes by key
-            let mut attr_sorted = m.attributes().collect::<Vec<_>>();
+            let mut attr_sorted = m
+                .attributes()
+                .filter(|(k, _)| *k != STALE_MARKER_ATTRIBUTE)
+                .collect::<Vec<_>>();
             attr_sorted.sort_by_key(|(k, _)| *k);
 
             // Handle known as well as new attributes.
@@ -0,0 +1,58 @@
+//! Attribution of the DRAM energy according to the memory footprint of the consumers.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{UsageAggregation, UsageConfig};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DramConfig {
+    /// Metric that gives the resident memory of each consumer, such as `cgroup_memory_total` or `process_memory`.
+    pub memory: String,
+    /// Only use the memory points whose attributes match these regular expressions (for instance `memory_kind = "resident"`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub memory_attributes: BTreeMap<String, String>,
+    /// Metric that approximates the memory bandwidth used by each consumer, such as the last-level cache misses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<String>,
+    /// Weight of the bandwidth in the shares of the consumers, between 0 and 1. The memory has the rest.
+    #[serde(default = "default_bandwidth_weight")]
+    pub bandwidth_weight: f64,
+}
+
+fn default_bandwidth_weight() -> f64 {
+    0.5
+}
+
+impl DramConfig {
+    /// Returns the usage metrics that implement this model.
+    ///
+    /// The memory is a level: its points are averaged over the window. The bandwidth proxies are counters
+    /// of events that happened since the last point: they are summed.
+    pub fn usage(&self) -> anyhow::Result<Vec<UsageConfig>> {
+        if !(0.0..=1.0).contains(&self.bandwidth_weight) {
+            return Err(anyhow::anyhow!(
+                "bandwidth_weight must be between 0 and 1, not {}",
+                self.bandwidth_weight
+            ));
+        }
+        let mut usage = vec![UsageConfig {
+            metric: self.memory.clone(),
+            weight: 1.0,
+            attributes: self.memory_attributes.clone(),
+            aggregation: UsageAggregation::Mean,
+        }];
+        if let Some(bandwidth) = &self.bandwidth {
+            usage[0].weight = 1.0 - self.bandwidth_weight;
+            usage.push(UsageConfig {
+                metric: bandwidth.clone(),
+                weight: self.bandwidth_weight,
+                attributes: BTreeMap::new(),
+                aggregation: UsageAggregation::Sum,
+            });
+        }
+        Ok(usage)
+    }
+}
@@ -0,0 +1,188 @@
+//! Split of the energy between its static (idle) and dynamic parts.
+
+use std::time::Duration;
+
+use alumet::{metrics::RawMetricId, resources::ResourceConsumer};
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+/// Weight of the last low-utilization window in the learned baseline.
+const LEARNING_SMOOTHING: f64 = 0.1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdleConfig {
+    /// Idle power of each package.
+    pub baseline: BaselineConfig,
+    /// How to split the static energy among the consumers.
+    pub policy: IdlePolicy,
+    /// With the `reserved` policy, metric that gives the resources reserved by each consumer,
+    /// such as the CPU requests or limits of the pods.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_metric: Option<String>,
+    /// Name of the metric to create for the dynamic part. Defaults to `{output}_dynamic`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamic_output: Option<String>,
+    /// Name of the metric to create for the static part. Defaults to `{output}_static`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub static_output: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BaselineConfig {
+    /// A known idle power, in watts per package.
+    Fixed { power: f64 },
+    /// The idle power is learned from the windows in which the CPU utilization of the consumers
+    /// (measured by the first usage metric, in CPU time) is below `max_utilization`.
+    Learned {
+        #[serde(default = "default_max_utilization")]
+        max_utilization: f64,
+        /// Idle power to use until it has been learned, in watts per package.
+        #[serde(default)]
+        initial_power: f64,
+    },
+}
+
+fn default_max_utilization() -> f64 {
+    0.1
+}
+
+/// How to split the static energy among the consumers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdlePolicy {
+    /// Evenly among the consumers that are measured in the window.
+    Even,
+    /// Proportionally to the resources reserved by the consumers.
+    Reserved,
+    /// Not split: the static energy is reported on a separate "idle" consumer.
+    Separate,
+}
+
+enum Baseline {
+    Fixed(f64),
+    Learned {
+        max_utilization: f64,
+        initial_power: f64,
+        /// Learned idle power of each package, in watts.
+        learned: FxHashMap<u32, f64>,
+    },
+}
+
+/// Idle power model of an attribution.
+pub struct IdleModel {
+    baseline: Baseline,
+    pub policy: IdlePolicy,
+    /// With the `reserved` policy, metric that gives the resources reserved by each consumer.
+    pub reserved_metric: Option<RawMetricId>,
+    /// Last value of the reserved metric, for each consumer.
+    pub reserved: FxHashMap<ResourceConsumer, f64>,
+    pub dynamic_output: RawMetricId,
+    pub static_output: RawMetricId,
+    /// Factor to apply to the energy to get joules.
+    to_joules: f64,
+    /// Factor to apply to the first usage metric to get seconds of CPU time.
+    to_cpu_seconds: f64,
+}
+
+impl IdleModel {
+    pub fn new(
+        config: &BaselineConfig,
+        policy: IdlePolicy,
+        reserved_metric: Option<RawMetricId>,
+        outputs: (RawMetricId, RawMetricId),
+        to_joules: f64,
+        to_cpu_seconds: f64,
+    ) -> Self {
+        let baseline = match config {
+            BaselineConfig::Fixed { power } => Baseline::Fixed(*power),
+            BaselineConfig::Learned {
+                max_utilization,
+                initial_power,
+            } => Baseline::Learned {
+                max_utilization: *max_utilization,
+                initial_power: *initial_power,
+                learned: FxHashMap::default(),
+            },
+        };
+        Self {
+            baseline,
+            policy,
+            reserved_metric,
+            reserved: FxHashMap::default(),
+            dynamic_output: outputs.0,
+            static_output: outputs.1,
+            to_joules,
+            to_cpu_seconds,
+        }
+    }
+
+    /// Returns the idle power of a package, in watts.
+    fn power(&self, package: u32) -> f64 {
+        match &self.baseline {
+            Baseline::Fixed(power) => *power,
+            Baseline::Learned {
+                initial_power, learned, ..
+            } => learned.get(&package).copied().unwrap_or(*initial_power),
+        }
+    }
+
+    /// Returns the static part of the energy consumed by a package during a window.
+    ///
+    /// The static energy cannot be larger than the total energy.
+    pub fn static_energy(&self, package: u32, energy: f64, window: Duration) -> f64 {
+        let idle = self.power(package) * window.as_secs_f64() / self.to_joules;
+        idle.clamp(0.0, energy.max(0.0))
+    }
+
+    /// Learns the idle power of a package, if the CPU utilization is low in the window.
+    ///
+    /// `cpu_time` is the total of the first usage metric on the package, and `n_cpus` the number of CPUs of the package.
+    pub fn learn(&mut self, package: u32, energy: f64, cpu_time: f64, n_cpus: usize, window: Duration) {
+        let Baseline::Learned {
+            max_utilization,
+            learned,
+            ..
+        } = &mut self.baseline
+        else {
+            return;
+        };
+        let window = window.as_secs_f64();
+        let utilization = cpu_time * self.to_cpu_seconds / (window * n_cpus.max(1) as f64);
+        if utilization > *max_utilization {
+            return;
+        }
+        let power = energy * self.to_joules / window;
+        let estimate = learned.entry(package).or_insert(power);
+        *estimate += LEARNING_SMOOTHING * (power - *estimate);
+        log::debug!("Idle power of package {package} updated to {estimate:.2} W (utilization {utilization:.3}).");
+    }
+
+    /// Returns the share of the static energy of each consumer, or `None` if it cannot be split.
+    ///
+    /// With the `separate` policy, the static energy is never split among the consumers.
+    pub fn static_shares(&self, consumers: &[&ResourceConsumer]) -> Option<Vec<f64>> {
+        if consumers.is_empty() {
+            return None;
+        }
+        let even = || vec![1.0 / consumers.len() as f64; consumers.len()];
+        match self.policy {
+            IdlePolicy::Separate => None,
+            IdlePolicy::Even => Some(even()),
+            IdlePolicy::Reserved => {
+                let reserved: Vec<f64> = consumers
+                    .iter()
+                    .map(|c| self.reserved.get(*c).copied().unwrap_or(0.0).max(0.0))
+                    .collect();
+                let total: f64 = reserved.iter().sum();
+                if total > 0.0 {
+                    Some(reserved.into_iter().map(|r| r / total).collect())
+                } else {
+                    log::debug!("No reserved resources known for the consumers, the static energy is split evenly.");
+                    Some(even())
+                }
+            }
+        }
+    }
+}
@@ -1,35 +1,36 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use alumet::{
-    metrics::{RawMetricId, TypedMetricId},
+    measurement::WrappedMeasurementType,
     plugin::{
+        event,
         rust::{deserialize_config, serialize_config, AlumetPlugin},
-        ConfigTable,
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
     },
+    resources::ResourceConsumer,
     units::Unit,
 };
-
-use anyhow::Context;
-
+use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
 
-use transform::EnergyAttributionTransform;
+use dram::DramConfig;
+use idle::{BaselineConfig, IdleConfig, IdleModel, IdlePolicy};
+use process::{ProcessTree, SharedRoots};
+use selector::{anchored_regex, ConsumerSelector, SelectorConfig};
+use topology::{CpuTopology, CpusetCache};
+use transform::{AttributionSpec, EnergyAttributionTransform, UsageSpec};
 
+mod dram;
+mod idle;
+mod process;
+mod selector;
+mod topology;
 mod transform;
 
 pub struct EnergyAttributionPlugin {
     config: Config,
-}
-
-struct Metrics {
-    // To attribute the CPU consumption to K8S pods, we need three metrics:
-    // - overall consumed energy per pod
-    // - overall hardware usage per pod
-    // - energy attributed to a pod
-    //
-    // Their IDs are gathered in different phases of the plugin initialization,
-    // that is why they are Options.
-    hardware_usage: RawMetricId,
-    consumed_energy: RawMetricId,
-    pod_attributed_energy: TypedMetricId<f64>,
+    /// Processes launched by `exec`, whose children are aggregated into them.
+    roots: SharedRoots,
 }
 
 impl AlumetPlugin for EnergyAttributionPlugin {
@@ -45,42 +46,207 @@ impl AlumetPlugin for EnergyAttributionPlugin {
         Ok(Some(serialize_config(Config::default())?))
     }
 
-    fn init(config: alumet::plugin::ConfigTable) -> anyhow::Result<Box<Self>> {
-        let config = deserialize_config(config)?;
-        Ok(Box::new(EnergyAttributionPlugin { config }))
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        if config.window.is_zero() {
+            return Err(anyhow!("the attribution window must not be zero"));
+        }
+        for attribution in &config.attributions {
+            match &attribution.dram {
+                Some(_) if !attribution.usage.is_empty() => {
+                    return Err(anyhow!(
+                        "attribution {} has both usage metrics and a dram model, remove one of them",
+                        attribution.output
+                    ))
+                }
+                Some(dram) => {
+                    dram.usage()
+                        .with_context(|| format!("invalid dram model for {}", attribution.output))?;
+                }
+                None if attribution.usage.is_empty() => {
+                    return Err(anyhow!("attribution {} has no usage metric", attribution.output))
+                }
+                None => (),
+            }
+        }
+        Ok(Box::new(EnergyAttributionPlugin {
+            config,
+            roots: SharedRoots::default(),
+        }))
     }
 
-    fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
-        // Create the energy attribution metric and add its id to the
-        // transform builder's metrics list.
-        let attribution_energy_metric = alumet.create_metric(
-            "pod_attributed_energy",
-            Unit::Joule,
-            "Energy consumption attributed to the pod",
-        )?;
-
-        let consumed_energy = self.config.consumed_energy_rapl.clone();
-        let hardware_usage = self.config.hardware_usage_cgroup.clone();
-
-        // Add the transform builder and its metrics
-        alumet.add_transform_builder("transform", move |ctx| {
-            let consumed_energy_metric = ctx
-                .metric_by_name(&consumed_energy)
-                .with_context(|| format!("Metric not found : {}", consumed_energy))?
-                .0;
-            let hardware_usage_metric = ctx
-                .metric_by_name(&hardware_usage)
-                .with_context(|| format!("Metric not found {}", hardware_usage))?
-                .0;
-            let metrics = Metrics {
-                pod_attributed_energy: attribution_energy_metric,
-                consumed_energy: consumed_energy_metric,
-                hardware_usage: hardware_usage_metric,
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        if self.config.attributions.iter().any(|a| a.aggregate_children) {
+            let roots = self.roots.clone();
+            event::start_consumer_measurement().subscribe(move |e| {
+                let mut roots = roots
+                    .lock()
+                    .map_err(|_| anyhow!("the set of root processes is poisoned"))?;
+                for consumer in e.0 {
+                    if let ResourceConsumer::Process { pid } = consumer {
+                        log::debug!("The children of process {pid} will be aggregated into it.");
+                        roots.insert(pid);
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        // The metrics of the other plugins are only known now, and the attributed energy
+        // has the same unit as the energy to attribute.
+        let mut specs = Vec::new();
+        for attribution in &self.config.attributions {
+            let Some((energy_id, energy)) = alumet.metrics().by_name(&attribution.energy) else {
+                log::warn!(
+                    "Metric {} not found, it will not be attributed to the consumers.",
+                    attribution.energy
+                );
+                continue;
+            };
+            let energy_unit = energy.unit.clone();
+            let usage_configs = match &attribution.dram {
+                Some(dram) => dram.usage()?,
+                None => attribution.usage.clone(),
+            };
+            let mut usage = Vec::new();
+            let mut usage_units = Vec::new();
+            for u in &usage_configs {
+                match alumet.metrics().by_name(&u.metric) {
+                    Some((id, metric)) => {
+                        let attributes = u
+                            .attributes
+                            .iter()
+                            .map(|(key, pattern)| Ok((key.clone(), anchored_regex(pattern)?)))
+                            .collect::<anyhow::Result<_>>()?;
+                        usage.push(UsageSpec {
+                            metric: id,
+                            weight: u.weight,
+                            attributes,
+                            aggregation: u.aggregation,
+                        });
+                        usage_units.push(metric.unit.clone());
+                    }
+                    None => log::warn!(
+                        "Metric {} not found, it will not be used for the attribution.",
+                        u.metric
+                    ),
+                }
+            }
+            if usage.is_empty() {
+                log::warn!(
+                    "None of the usage metrics of {} exist, it will not be attributed.",
+                    attribution.output
+                );
+                continue;
+            }
+            let consumers = attribution
+                .consumers
+                .iter()
+                .map(ConsumerSelector::try_from)
+                .collect::<anyhow::Result<_>>()?;
+            let mut create_output = |name: &str, description: String| {
+                alumet
+                    .create_metric_untyped(name, WrappedMeasurementType::F64, energy_unit.clone(), &description)
+                    .with_context(|| format!("could not create metric {name}"))
             };
+            let output = create_output(
+                &attribution.output,
+                format!("energy {} attributed to the consumers", attribution.energy),
+            )?;
+            let idle = match &attribution.idle {
+                Some(idle) => {
+                    let dynamic_name = idle
+                        .dynamic_output
+                        .clone()
+                        .unwrap_or_else(|| format!("{}_dynamic", attribution.output));
+                    let static_name = idle
+                        .static_output
+                        .clone()
+                        .unwrap_or_else(|| format!("{}_static", attribution.output));
+                    let outputs = (
+                        create_output(
+                            &dynamic_name,
+                            format!(
+                                "dynamic part of the energy {} attributed to the consumers",
+                                attribution.energy
+                            ),
+                        )?,
+                        create_output(
+                            &static_name,
+                            format!(
+                                "static (idle) part of the energy {} attributed to the consumers",
+                                attribution.energy
+                            ),
+                        )?,
+                    );
+                    let to_joules = energy_unit.factor(&Unit::Joule).with_context(|| {
+                        format!(
+                            "metric {} is not an energy, its unit is {energy_unit}",
+                            attribution.energy
+                        )
+                    })?;
+                    let to_cpu_seconds = match &idle.baseline {
+                        BaselineConfig::Fixed { .. } => 1.0,
+                        BaselineConfig::Learned { .. } => usage_units[0].factor(&Unit::Second).with_context(|| {
+                            format!(
+                                "the idle power can only be learned if the first usage metric is a CPU time, its unit is {}",
+                                usage_units[0]
+                            )
+                        })?,
+                    };
+                    if idle.policy == IdlePolicy::Reserved && idle.reserved_metric.is_none() {
+                        return Err(anyhow!("the reserved idle policy requires a reserved_metric"));
+                    }
+                    let reserved_metric = match &idle.reserved_metric {
+                        Some(name) => {
+                            let id = alumet.metrics().by_name(name).map(|(id, _)| id);
+                            if id.is_none() {
+                                log::warn!("Metric {name} not found, the static energy will be split evenly.");
+                            }
+                            id
+                        }
+                        None => None,
+                    };
+                    Some(IdleModel::new(
+                        &idle.baseline,
+                        idle.policy,
+                        reserved_metric,
+                        outputs,
+                        to_joules,
+                        to_cpu_seconds,
+                    ))
+                }
+                None => None,
+            };
+            specs.push(AttributionSpec {
+                output,
+                energy: energy_id,
+                domain: attribution.domain(),
+                usage,
+                consumers,
+                per_package: attribution.per_package,
+                unattributed: attribution.unattributed,
+                aggregate_children: attribution.aggregate_children,
+                idle,
+            });
+        }
+        if specs.is_empty() {
+            log::warn!("Nothing to attribute, the energy attribution is disabled.");
+            return Ok(());
+        }
 
-            let transform = Box::new(EnergyAttributionTransform::new(metrics));
-            Ok(transform)
-        })?;
+        let transform = EnergyAttributionTransform::new(
+            specs,
+            self.config.window,
+            self.config.max_delay,
+            CpuTopology::read(),
+            CpusetCache::new(self.config.cpuset_refresh_interval),
+            ProcessTree::new(self.roots.clone(), self.config.cpuset_refresh_interval),
+        );
+        alumet.add_transform("transform", Box::new(transform))?;
         Ok(())
     }
 
@@ -90,16 +256,123 @@ impl AlumetPlugin for EnergyAttributionPlugin {
 }
 
 #[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 struct Config {
-    consumed_energy_rapl: String,
-    hardware_usage_cgroup: String,
+    /// The measurements are grouped in windows of this duration, and the energy of each window is attributed.
+    #[serde(with = "humantime_serde")]
+    window: Duration,
+    /// How long to wait for the late measurements, after the end of a window.
+    ///
+    /// The sources buffer their measurements before sending them (RAPL flushes them every 5 seconds),
+    /// so this delay must be larger than the flush interval of the slowest source.
+    #[serde(with = "humantime_serde")]
+    max_delay: Duration,
+    /// How often to read the cpusets of the consumers and the parents of the processes again.
+    #[serde(with = "humantime_serde")]
+    cpuset_refresh_interval: Duration,
+    attributions: Vec<AttributionConfig>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct AttributionConfig {
+    /// Name of the metric to create for the attributed energy.
+    output: String,
+    /// Name of the energy metric to attribute, measured per CPU package.
+    energy: String,
+    /// If set, only the energy measurements with this `domain` attribute are attributed (for instance `package`).
+    /// Defaults to `dram` with the dram model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
+    /// The consumers to attribute the energy to. If empty, every consumer of the usage metrics is selected.
+    #[serde(default)]
+    consumers: Vec<SelectorConfig>,
+    /// The metrics that measure how much the consumers use the CPU, and their weights.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    usage: Vec<UsageConfig>,
+    /// Attribute the DRAM energy according to the memory footprint of the consumers, instead of the usage metrics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dram: Option<DramConfig>,
+    /// Attribute the energy of each CPU package separately, according to the cpusets of the consumers.
+    #[serde(default = "default_true")]
+    per_package: bool,
+    /// What to do with the energy that cannot be attributed to any consumer.
+    #[serde(default = "default_unattributed")]
+    unattributed: UnattributedPolicy,
+    /// Attribute the energy of the child processes to the process launched by `exec`.
+    #[serde(default)]
+    aggregate_children: bool,
+    /// Split the energy into a static (idle) part and a dynamic part.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idle: Option<IdleConfig>,
+}
+
+impl AttributionConfig {
+    fn domain(&self) -> Option<String> {
+        match (&self.domain, &self.dram) {
+            (None, Some(_)) => Some(String::from("dram")),
+            (domain, _) => domain.clone(),
+        }
+    }
+}
+
+/// What to do with the energy that cannot be attributed to any consumer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnattributedPolicy {
+    /// Drop it.
+    Drop,
+    /// Report it on the `local_machine` consumer, with the attribute `attribution = "unattributed"`.
+    Report,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct UsageConfig {
+    metric: String,
+    #[serde(default = "default_weight")]
+    weight: f64,
+    /// Only use the points whose attributes match these regular expressions (for instance `cpu_state = "user|system"`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<String, String>,
+    /// How to combine the points of a consumer in a window.
+    #[serde(default = "default_aggregation")]
+    aggregation: UsageAggregation,
+}
+
+/// How to combine the usage points of a consumer in a window.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageAggregation {
+    /// Sum the values, for the metrics that measure what has been used since the last point (such as a CPU time).
+    Sum,
+    /// Average the values, for the metrics that measure a level (such as a memory footprint).
+    Mean,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_unattributed() -> UnattributedPolicy {
+    UnattributedPolicy::Report
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_aggregation() -> UsageAggregation {
+    UsageAggregation::Sum
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            consumed_energy_rapl: String::from("rapl_consumed_energy"),
-            hardware_usage_cgroup: String::from("cgroup_cpu_usage_user"),
+            window: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            cpuset_refresh_interval: Duration::from_secs(30),
+            attributions: Vec::new(),
         }
     }
 }
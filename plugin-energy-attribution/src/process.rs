@@ -0,0 +1,105 @@
+//! Process trees, to aggregate the child processes into their parent.
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use fxhash::{FxHashMap, FxHashSet};
+
+/// Maximum depth of the process trees, to protect against loops in the parent ids.
+const MAX_DEPTH: usize = 64;
+
+/// Processes whose children are aggregated into them: the processes launched by `alumet-agent exec`.
+pub type SharedRoots = Arc<Mutex<FxHashSet<u32>>>;
+
+/// Finds the root process of the processes.
+pub struct ProcessTree {
+    roots: SharedRoots,
+    /// Parent of each process, and when it has been read.
+    parents: FxHashMap<u32, (Instant, Option<u32>)>,
+    refresh_interval: Duration,
+    read_parent: fn(u32) -> Option<u32>,
+}
+
+impl ProcessTree {
+    pub fn new(roots: SharedRoots, refresh_interval: Duration) -> Self {
+        Self::with_reader(roots, refresh_interval, read_parent)
+    }
+
+    /// Creates a tree that gets the parent of the processes with `read_parent`.
+    pub fn with_reader(roots: SharedRoots, refresh_interval: Duration, read_parent: fn(u32) -> Option<u32>) -> Self {
+        Self {
+            roots,
+            parents: FxHashMap::default(),
+            refresh_interval,
+            read_parent,
+        }
+    }
+
+    /// Returns the root process that `pid` belongs to, or `None` if it is not part of the tree of a root.
+    pub fn root_of(&mut self, pid: u32) -> Option<u32> {
+        let roots = match self.roots.lock() {
+            Ok(roots) => roots.clone(),
+            Err(_) => return None,
+        };
+        if roots.is_empty() {
+            return None;
+        }
+        let mut current = pid;
+        for _ in 0..MAX_DEPTH {
+            if roots.contains(&current) {
+                return Some(current);
+            }
+            current = self.parent(current)?;
+        }
+        None
+    }
+
+    fn parent(&mut self, pid: u32) -> Option<u32> {
+        let now = Instant::now();
+        let expired = self
+            .parents
+            .get(&pid)
+            .is_none_or(|(read_at, _)| now.duration_since(*read_at) >= self.refresh_interval);
+        if expired {
+            let parent = (self.read_parent)(pid);
+            self.parents.insert(pid, (now, parent));
+        }
+        self.parents.get(&pid).and_then(|(_, parent)| *parent)
+    }
+
+    /// Forgets the processes that have not been seen for a while.
+    pub fn clean(&mut self) {
+        let max_age = self.refresh_interval * 10;
+        self.parents.retain(|_, (read_at, _)| read_at.elapsed() < max_age);
+    }
+}
+
+/// Reads the id of the parent of a process from `/proc/<pid>/stat`.
+fn read_parent(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    parse_parent(&stat)
+}
+
+fn parse_parent(stat: &str) -> Option<u32> {
+    // The name of the process is between parentheses and can contain spaces or parentheses:
+    // the fields that follow the last ')' are the state, then the parent id.
+    let (_, fields) = stat.rsplit_once(')')?;
+    let ppid = fields.split_whitespace().nth(1)?.parse().ok()?;
+    // 0 means no parent (for init)
+    (ppid != 0).then_some(ppid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_parent;
+
+    #[test]
+    fn parse_stat() {
+        assert_eq!(parse_parent("1234 (my (weird) name) S 56 1234 1234 0 -1"), Some(56));
+        assert_eq!(parse_parent("1 (systemd) S 0 1 1 0 -1"), None);
+        assert_eq!(parse_parent("garbage"), None);
+    }
+}
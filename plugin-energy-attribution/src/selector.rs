@@ -0,0 +1,74 @@
+//! Selection of the consumers to attribute the energy to.
+
+use std::collections::BTreeMap;
+
+use alumet::measurement::MeasurementPoint;
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Selects consumers by kind, id and attributes. Every condition that is set must match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelectorConfig {
+    /// Kind of consumer, such as `cgroup` or `process`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Regular expression that the id of the consumer must match entirely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Regular expressions that the value of some attributes must match entirely.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+pub struct ConsumerSelector {
+    kind: Option<String>,
+    id: Option<Regex>,
+    attributes: Vec<(String, Regex)>,
+}
+
+pub fn anchored_regex(pattern: &str) -> anyhow::Result<Regex> {
+    Regex::new(&format!("^(?:{pattern})$")).with_context(|| format!("invalid regular expression '{pattern}'"))
+}
+
+impl TryFrom<&SelectorConfig> for ConsumerSelector {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &SelectorConfig) -> Result<Self, Self::Error> {
+        let id = config.id.as_deref().map(anchored_regex).transpose()?;
+        let attributes = config
+            .attributes
+            .iter()
+            .map(|(key, pattern)| Ok((key.clone(), anchored_regex(pattern)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            kind: config.kind.clone(),
+            id,
+            attributes,
+        })
+    }
+}
+
+impl ConsumerSelector {
+    pub fn matches(&self, point: &MeasurementPoint) -> bool {
+        if self.kind.as_ref().is_some_and(|kind| kind != point.consumer.kind()) {
+            return false;
+        }
+        if let Some(id) = &self.id {
+            if !id.is_match(&point.consumer.id_string().unwrap_or_default()) {
+                return false;
+            }
+        }
+        has_attributes(point, &self.attributes)
+    }
+}
+
+/// Checks that the point has every attribute, with a value that matches the regex.
+pub fn has_attributes(point: &MeasurementPoint, attributes: &[(String, Regex)]) -> bool {
+    attributes.iter().all(|(key, regex)| {
+        point
+            .attributes()
+            .any(|(k, v)| k == key && regex.is_match(&v.to_string()))
+    })
+}
@@ -0,0 +1,153 @@
+//! Mapping between the CPUs, the packages and the control groups.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use alumet::{pipeline::builder::CpuSet, resources::ResourceConsumer};
+use fxhash::FxHashMap;
+
+/// The CPUs of each package (socket).
+#[derive(Debug, Default, Clone)]
+pub struct CpuTopology {
+    packages: BTreeMap<u32, Vec<usize>>,
+    n_cpus: usize,
+}
+
+impl CpuTopology {
+    pub fn new(packages: BTreeMap<u32, Vec<usize>>) -> Self {
+        let n_cpus = packages.values().map(Vec::len).sum();
+        Self { packages, n_cpus }
+    }
+
+    /// Reads the topology of the CPUs from the sysfs.
+    ///
+    /// If the topology is not available, returns an empty topology: the energy of the packages
+    /// will then be split without taking the cpusets into account.
+    pub fn read() -> Self {
+        let mut packages: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        let entries = match fs::read_dir("/sys/devices/system/cpu") {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("Could not read the CPU topology, the cpusets will not be used: {e}");
+                return Self::default();
+            }
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(cpu) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix("cpu"))
+                .and_then(|n| n.parse().ok())
+            else {
+                continue;
+            };
+            let path = entry.path().join("topology/physical_package_id");
+            match fs::read_to_string(&path).map(|s| s.trim().parse::<u32>()) {
+                Ok(Ok(package)) => packages.entry(package).or_default().push(cpu),
+                // offline CPUs have no topology
+                _ => log::debug!("No package for CPU {cpu} in {}", path.display()),
+            }
+        }
+        for cpus in packages.values_mut() {
+            cpus.sort_unstable();
+        }
+        Self::new(packages)
+    }
+
+    /// Returns the number of CPUs of a package, if the topology is known.
+    pub fn n_cpus(&self, package: u32) -> Option<usize> {
+        self.packages.get(&package).map(Vec::len)
+    }
+
+    /// Returns the fraction of the CPU usage of a consumer that runs on `package`.
+    ///
+    /// If the consumer is restricted to a set of CPUs, its usage is assumed to be spread evenly on these CPUs.
+    /// Otherwise, or if the topology is unknown, it is spread evenly on all the CPUs.
+    /// `n_packages` is the number of packages to use if the topology is unknown.
+    pub fn package_fraction(&self, package: u32, cpuset: Option<&CpuSet>, n_packages: usize) -> f64 {
+        let Some(package_cpus) = self.packages.get(&package) else {
+            return 1.0 / n_packages.max(1) as f64;
+        };
+        match cpuset {
+            Some(cpuset) if !cpuset.is_empty() => {
+                let on_package = cpuset
+                    .cpus()
+                    .iter()
+                    .filter(|cpu| package_cpus.binary_search(cpu).is_ok())
+                    .count();
+                on_package as f64 / cpuset.len() as f64
+            }
+            _ => package_cpus.len() as f64 / self.n_cpus as f64,
+        }
+    }
+}
+
+/// Cache of the CPUs that the consumers are allowed to run on.
+pub struct CpusetCache {
+    entries: FxHashMap<ResourceConsumer, (Instant, Option<CpuSet>)>,
+    refresh_interval: Duration,
+}
+
+impl CpusetCache {
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            entries: FxHashMap::default(),
+            refresh_interval,
+        }
+    }
+
+    /// Returns the CPUs that the consumer is allowed to run on, if they can be read.
+    ///
+    /// This is the effective cpuset of a cgroup, or the allowed CPUs of a process.
+    pub fn get(&mut self, consumer: &ResourceConsumer) -> Option<&CpuSet> {
+        let now = Instant::now();
+        let expired = self
+            .entries
+            .get(consumer)
+            .is_none_or(|(read_at, _)| now.duration_since(*read_at) >= self.refresh_interval);
+        if expired {
+            let cpuset = match consumer {
+                ResourceConsumer::ControlGroup { path } => read_cgroup_cpuset(Path::new(path.as_ref())),
+                ResourceConsumer::Process { pid } => read_process_cpuset(*pid),
+                _ => None,
+            };
+            self.entries.insert(consumer.clone(), (now, cpuset));
+        }
+        self.entries.get(consumer).and_then(|(_, cpuset)| cpuset.as_ref())
+    }
+
+    /// Forgets the consumers that have not been used for a while.
+    pub fn clean(&mut self) {
+        let max_age = self.refresh_interval * 10;
+        self.entries.retain(|_, (read_at, _)| read_at.elapsed() < max_age);
+    }
+}
+
+fn parse_cpuset(content: &str, path: &Path) -> Option<CpuSet> {
+    match content.parse() {
+        Ok(cpuset) => Some(cpuset),
+        Err(e) => {
+            log::warn!("Invalid cpuset in {}: {e:#}", path.display());
+            None
+        }
+    }
+}
+
+fn read_cgroup_cpuset(cgroup_dir: &Path) -> Option<CpuSet> {
+    let path = cgroup_dir.join("cpuset.cpus.effective");
+    let content = fs::read_to_string(&path).ok()?;
+    parse_cpuset(&content, &path)
+}
+
+fn read_process_cpuset(pid: u32) -> Option<CpuSet> {
+    let path = PathBuf::from(format!("/proc/{pid}/status"));
+    let content = fs::read_to_string(&path).ok()?;
+    let list = content
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))?;
+    parse_cpuset(list, &path)
+}
@@ -1,151 +1,951 @@
 use std::{
-    collections::HashMap,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use alumet::{
-    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
     pipeline::{
         elements::{error::TransformError, transform::TransformContext},
         Transform,
     },
-    resources::Resource,
+    resources::{Resource, ResourceConsumer},
 };
+use fxhash::FxHashMap;
+use regex::Regex;
 
+use crate::{
+    idle::{IdleModel, IdlePolicy},
+    process::ProcessTree,
+    selector::{has_attributes, ConsumerSelector},
+    topology::{CpuTopology, CpusetCache},
+    UnattributedPolicy, UsageAggregation,
+};
+
+/// A metric that measures how much a consumer uses the resource.
+pub struct UsageSpec {
+    pub metric: RawMetricId,
+    pub weight: f64,
+    /// Only the points with these attributes are used.
+    pub attributes: Vec<(String, Regex)>,
+    pub aggregation: UsageAggregation,
+}
+
+/// How to attribute an energy metric to the consumers.
+pub struct AttributionSpec {
+    /// The attributed energy.
+    pub output: RawMetricId,
+    /// The energy to attribute, measured per package.
+    pub energy: RawMetricId,
+    /// If set, only the energy points with this `domain` attribute are used.
+    pub domain: Option<String>,
+    pub usage: Vec<UsageSpec>,
+    /// Consumers to attribute the energy to. If empty, every consumer of the usage metrics is selected.
+    pub consumers: Vec<ConsumerSelector>,
+    /// Attribute the energy of each package separately, or sum it over the packages.
+    pub per_package: bool,
+    pub unattributed: UnattributedPolicy,
+    /// Attribute the usage of the child processes to their root process.
+    pub aggregate_children: bool,
+    /// If set, the energy is split into a static (idle) part and a dynamic part.
+    pub idle: Option<IdleModel>,
+}
+
+/// Role of a metric in an attribution.
+#[derive(Clone, Copy)]
+enum Input {
+    Energy,
+    Usage(usize),
+    /// Resources reserved by the consumers, for the idle model.
+    Reserved,
+}
+
+/// Measurements of a time window.
+#[derive(Default)]
+struct Bin {
+    /// Energy consumed in the window, per package.
+    energy: BTreeMap<u32, (Resource, f64)>,
+    /// Usage of each consumer in the window, in the same order as the usage metrics.
+    usage: FxHashMap<ResourceConsumer, ConsumerUsage>,
+}
+
+struct ConsumerUsage {
+    /// For each usage metric, the sum and the number of points of each measured series.
+    ///
+    /// A consumer has several series when the usage of its child processes is aggregated into it.
+    series: Vec<FxHashMap<ResourceConsumer, (f64, u32)>>,
+    /// Attributes of the last usage point of the consumer, copied to the attributed energy.
+    attributes: Vec<(String, AttributeValue)>,
+}
+
+/// Attributes the energy consumed by the CPU packages (or by their DRAM) to the consumers that use them.
+///
+/// The measurements are grouped in time windows. When a window is complete, the energy of each
+/// package is split among the consumers according to their share of the usage metrics: each usage
+/// metric gives a share, and the shares are combined with the weights of the metrics.
+/// The usage of a consumer that is restricted to some CPUs by its cpuset only counts on the
+/// packages of these CPUs.
+///
+/// A window is complete when a measurement more recent than its end plus `max_delay` has been received.
+/// The measurements of a window that arrive after that are ignored, hence `max_delay` must cover the time
+/// between two flushes of the sources.
 pub struct EnergyAttributionTransform {
-    pub metrics: super::Metrics,
-    buffer_pod: HashMap<u64, Vec<MeasurementPoint>>,
-    buffer_rapl: HashMap<u64, MeasurementPoint>,
+    specs: Vec<AttributionSpec>,
+    /// Metric -> (spec index, role)
+    inputs: FxHashMap<RawMetricId, Vec<(usize, Input)>>,
+    /// Window duration, in nanoseconds.
+    window: u128,
+    max_delay: u128,
+    /// Windows of each spec, by index (timestamp / window).
+    bins: Vec<BTreeMap<u128, Bin>>,
+    /// The windows before this index have been attributed already.
+    closed_before: u128,
+    /// Largest timestamp seen so far.
+    max_time: u128,
+    topology: CpuTopology,
+    cpusets: CpusetCache,
+    processes: ProcessTree,
 }
+
 impl EnergyAttributionTransform {
-    /// Instantiates a new EnergyAttributionTransform with its private fields initialized.
-    pub fn new(metrics: super::Metrics) -> Self {
+    pub fn new(
+        specs: Vec<AttributionSpec>,
+        window: Duration,
+        max_delay: Duration,
+        topology: CpuTopology,
+        cpusets: CpusetCache,
+        processes: ProcessTree,
+    ) -> Self {
+        let mut inputs: FxHashMap<RawMetricId, Vec<(usize, Input)>> = FxHashMap::default();
+        for (i, spec) in specs.iter().enumerate() {
+            inputs.entry(spec.energy).or_default().push((i, Input::Energy));
+            for (u, usage) in spec.usage.iter().enumerate() {
+                inputs.entry(usage.metric).or_default().push((i, Input::Usage(u)));
+            }
+            if let Some(reserved) = spec.idle.as_ref().and_then(|idle| idle.reserved_metric) {
+                inputs.entry(reserved).or_default().push((i, Input::Reserved));
+            }
+        }
+        let bins = specs.iter().map(|_| BTreeMap::new()).collect();
         Self {
-            metrics,
-            buffer_pod: HashMap::<u64, Vec<MeasurementPoint>>::new(),
-            buffer_rapl: HashMap::<u64, MeasurementPoint>::new(),
+            specs,
+            inputs,
+            window: window.as_nanos(),
+            max_delay: max_delay.as_nanos(),
+            bins,
+            closed_before: 0,
+            max_time: 0,
+            topology,
+            cpusets,
+            processes,
         }
     }
 
-    /// Empties the buffers and send the energy attribution points to the MeasurementBuffer.
-    fn buffer_bouncer(&mut self, measurements: &mut alumet::measurement::MeasurementBuffer) {
-        // Retrieving the metric_id of the energy attribution.
-        // Using a nested scope to reduce the lock time.
-        let metric_id = self.metrics.pod_attributed_energy;
-
-        // If the buffers do have enough (every) MeasurementPoints,
-        // then we compute the energy attribution.
-        while self.buffer_rapl.len() >= 2 && self.buffer_pod.len() >= 2 {
-            // Get the smallest rapl id i.e. the oldest timestamp (key) present in the buffer.
-            let rapl_mini_id = self
-                .buffer_rapl
-                .keys()
-                .reduce(|x, y| if x < y { x } else { y })
-                .unwrap()
-                .clone();
-
-            // Check if the buffer_pod contains the key to prevent any panic/error bellow.
-            if !self.buffer_pod.contains_key(&rapl_mini_id) {
-                todo!("decide what to do in this case");
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        for point in measurements.iter() {
+            let Some(inputs) = self.inputs.get(&point.metric) else {
+                continue;
+            };
+            let t = SystemTime::from(point.timestamp)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            let index = t / self.window;
+            if index < self.closed_before {
+                log::debug!(
+                    "Point of metric {:?} at {:?} arrived after the attribution of its window, it is ignored.",
+                    point.metric,
+                    point.timestamp
+                );
+                continue;
             }
+            self.max_time = self.max_time.max(t);
+            for (i, input) in inputs {
+                let spec = &mut self.specs[*i];
+                let selected = spec.consumers.is_empty() || spec.consumers.iter().any(|s| s.matches(point));
+                match input {
+                    Input::Energy => {
+                        if !has_domain(point, spec.domain.as_deref()) {
+                            continue;
+                        }
+                        let Some(package) = package_of(&point.resource) else {
+                            continue;
+                        };
+                        let bin = self.bins[*i].entry(index).or_default();
+                        let energy = bin.energy.entry(package).or_insert((point.resource.clone(), 0.0));
+                        energy.1 += value_as_f64(&point.value);
+                    }
+                    Input::Usage(u) => {
+                        let filter = &spec.usage[*u].attributes;
+                        if !selected || !has_attributes(point, filter) {
+                            continue;
+                        }
+                        let series = consumer_key(&point.consumer);
+                        let consumer = match &series {
+                            ResourceConsumer::Process { pid } if spec.aggregate_children => {
+                                match self.processes.root_of(*pid) {
+                                    Some(root) => ResourceConsumer::Process { pid: root },
+                                    None => series.clone(),
+                                }
+                            }
+                            _ => series.clone(),
+                        };
+                        let n_usage = spec.usage.len();
+                        let bin = self.bins[*i].entry(index).or_default();
+                        let usage = bin.usage.entry(consumer).or_insert_with(|| ConsumerUsage {
+                            series: vec![FxHashMap::default(); n_usage],
+                            attributes: Vec::new(),
+                        });
+                        let (sum, count) = usage.series[*u].entry(series).or_default();
+                        *sum += value_as_f64(&point.value);
+                        *count += 1;
+                        // the attributes used to filter the points differ between the points of a consumer
+                        usage.attributes = point
+                            .attributes()
+                            .filter(|(k, _)| !filter.iter().any(|(key, _)| key == k))
+                            .map(|(k, v)| (k.to_owned(), v.clone()))
+                            .collect();
+                    }
+                    Input::Reserved => {
+                        if let (true, Some(idle)) = (selected, &mut spec.idle) {
+                            idle.reserved
+                                .insert(consumer_key(&point.consumer), value_as_f64(&point.value));
+                        }
+                    }
+                }
+            }
+        }
 
-            let rapl_point = self.buffer_rapl.remove(&rapl_mini_id).unwrap();
-
-            // Compute the sum of every `total_usage_usec` for the given timestamp: `rapl_mini_id`.
-            let tot_time_sum = self
-                .buffer_pod
-                .get(&rapl_mini_id)
-                .unwrap()
-                .iter()
-                .map(|x| match x.value {
-                    WrappedMeasurementValue::F64(fx) => fx,
-                    WrappedMeasurementValue::U64(ux) => ux as f64,
-                })
-                .sum::<f64>();
-
-            // Then for every points in the buffer_pod at `rapl_mini_id`.
-            for point in self.buffer_pod.remove(&rapl_mini_id).unwrap().iter() {
-                // We extract the current tot_time as f64.
-                let cur_tot_time_f64 = match point.value {
-                    WrappedMeasurementValue::F64(fx) => fx,
-                    WrappedMeasurementValue::U64(ux) => ux as f64,
+        // attribute the energy of the complete windows
+        let complete_before = self.max_time.saturating_sub(self.max_delay) / self.window;
+        if complete_before <= self.closed_before {
+            return;
+        }
+        for i in 0..self.specs.len() {
+            let pending = self.bins[i].split_off(&complete_before);
+            let complete = std::mem::replace(&mut self.bins[i], pending);
+            for (index, bin) in complete {
+                let end = Timestamp::from(UNIX_EPOCH + Duration::from_nanos(((index + 1) * self.window) as u64));
+                let window = Duration::from_nanos(self.window as u64);
+                let context = WindowContext {
+                    timestamp: end,
+                    duration: window,
+                    topology: &self.topology,
                 };
-
-                // Extract the attributes of the current point to add them
-                // to the new measurement point.
-                let point_attributes = point
-                    .attributes()
-                    .map(|(key, value)| (key.to_owned(), value.clone()))
-                    .collect();
-
-                // We create the new MeasurementPoint for the energy attribution.
-                let new_m = MeasurementPoint::new(
-                    rapl_point.timestamp,
-                    metric_id,
-                    point.resource.clone(),
-                    point.consumer.clone(),
-                    match rapl_point.value {
-                        WrappedMeasurementValue::F64(fx) => cur_tot_time_f64 / tot_time_sum * fx,
-                        WrappedMeasurementValue::U64(ux) => cur_tot_time_f64 / tot_time_sum * (ux as f64),
-                    },
-                )
-                .with_attr_vec(point_attributes);
-
-                // And finally, the MeasurementPoint is pushed to the MeasurementBuffer.
-                measurements.push(new_m.clone());
+                attribute(&mut self.specs[i], bin, &context, &mut self.cpusets, measurements);
             }
         }
+        self.closed_before = complete_before;
+        self.cpusets.clean();
+        self.processes.clean();
     }
 }
 
-impl Transform for EnergyAttributionTransform {
-    /// Applies the transform on the measurements.
-    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
-        // Retrieve the pod_id and the rapl_id.
-        // Using a nested scope to reduce the lock time.
-        let (pod_id, rapl_id) = {
-            let metrics = &self.metrics;
+/// The window being attributed.
+struct WindowContext<'a> {
+    /// End of the window.
+    timestamp: Timestamp,
+    duration: Duration,
+    topology: &'a CpuTopology,
+}
+
+/// Who receives some attributed energy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Recipient {
+    /// A consumer, by index.
+    Consumer(usize),
+    /// The separate idle consumer.
+    Idle,
+    /// Nobody.
+    Unattributed,
+}
+
+/// Attributes the energy of a complete window.
+fn attribute(
+    spec: &mut AttributionSpec,
+    bin: Bin,
+    window: &WindowContext,
+    cpusets: &mut CpusetCache,
+    measurements: &mut MeasurementBuffer,
+) {
+    let timestamp = window.timestamp;
+    if bin.energy.is_empty() {
+        if !bin.usage.is_empty() {
+            log::debug!("No energy measured in the window that ends at {timestamp:?}, nothing to attribute.");
+        }
+        return;
+    }
 
-            let pod_id = metrics.hardware_usage.as_u64();
-            let rapl_id = metrics.consumed_energy.as_u64();
+    let n_packages = bin.energy.len();
+    let consumers: Vec<(&ResourceConsumer, &ConsumerUsage)> = bin.usage.iter().collect();
+    let consumer_ids: Vec<&ResourceConsumer> = consumers.iter().map(|(c, _)| *c).collect();
 
-            (pod_id, rapl_id)
+    // (package if per_package, recipient) -> (dynamic energy, static energy)
+    let mut attributed: FxHashMap<(Option<u32>, Recipient), (f64, f64)> = FxHashMap::default();
+    for (package, (_, energy)) in &bin.energy {
+        let key_package = spec.per_package.then_some(*package);
+        let mut give = |recipient: Recipient, dynamic: f64, static_: f64| {
+            let entry = attributed.entry((key_package, recipient)).or_default();
+            entry.0 += dynamic;
+            entry.1 += static_;
         };
 
-        // Filling the buffers.
-        for m in measurements.clone().iter() {
-            if m.metric.as_u64() == rapl_id {
-                match m.resource {
-                    // If the metric is rapl then we insert only the cpu package one in the buffer.
-                    Resource::CpuPackage { id: _ } => {
-                        let id = SystemTime::from(m.timestamp).duration_since(UNIX_EPOCH)?.as_secs();
+        // usage of each consumer on this package
+        let usage_on_package: Vec<Vec<f64>> = consumers
+            .iter()
+            .map(|(consumer, usage)| {
+                let fraction = window
+                    .topology
+                    .package_fraction(*package, cpusets.get(consumer), n_packages);
+                usage.totals(&spec.usage).into_iter().map(|v| v * fraction).collect()
+            })
+            .collect();
 
-                        self.buffer_rapl.insert(id, m.clone());
+        // static part
+        let static_energy = match &mut spec.idle {
+            Some(idle) => {
+                let static_energy = idle.static_energy(*package, *energy, window.duration);
+                match idle.static_shares(&consumer_ids) {
+                    Some(shares) => {
+                        for (c, share) in shares.into_iter().enumerate() {
+                            give(Recipient::Consumer(c), 0.0, static_energy * share);
+                        }
                     }
-                    _ => continue,
+                    None if idle.policy == IdlePolicy::Separate => give(Recipient::Idle, 0.0, static_energy),
+                    None => give(Recipient::Unattributed, 0.0, static_energy),
                 }
-            } else if m.metric.as_u64() == pod_id {
-                // Else, if the metric is pod, then we keep only the ones that are prefixed with "pod"
-                // before inserting them in the buffer.
-                if m.attributes().any(|(_, value)| value.to_string().starts_with("pod")) {
-                    let id = SystemTime::from(m.timestamp).duration_since(UNIX_EPOCH)?.as_secs();
-                    match self.buffer_pod.get_mut(&id) {
-                        Some(vec_points) => {
-                            vec_points.push(m.clone());
-                        }
-                        None => {
-                            // If the buffer does not have any value for the current id (timestamp)
-                            // then we create the vec with its first value.
-                            self.buffer_pod.insert(id, vec![m.clone()]);
-                        }
+                let cpu_time: f64 = usage_on_package.iter().filter_map(|values| values.first()).sum();
+                let n_cpus = window
+                    .topology
+                    .n_cpus(*package)
+                    .unwrap_or_else(|| available_cpus() / n_packages);
+                idle.learn(*package, *energy, cpu_time, n_cpus, window.duration);
+                static_energy
+            }
+            None => 0.0,
+        };
+
+        // dynamic part
+        let dynamic_energy = energy - static_energy;
+        match shares(&usage_on_package, &spec.usage) {
+            Some(shares) => {
+                for (c, share) in shares.into_iter().enumerate() {
+                    if share > 0.0 {
+                        give(Recipient::Consumer(c), dynamic_energy * share, 0.0);
                     }
                 }
             }
+            None => {
+                log::debug!("No usage measured on package {package} in the window that ends at {timestamp:?}.");
+                give(Recipient::Unattributed, dynamic_energy, 0.0);
+            }
         }
+    }
 
-        // Emptying the buffers and pushing the energy attribution to the MeasurementBuffer
-        self.buffer_bouncer(measurements);
+    for ((package, recipient), (dynamic, static_)) in attributed {
+        let resource = match package.and_then(|p| bin.energy.get(&p)) {
+            Some((resource, _)) => resource.clone(),
+            None => Resource::LocalMachine,
+        };
+        let (consumer, attributes) = match recipient {
+            Recipient::Consumer(c) => (consumers[c].0.clone(), consumers[c].1.attributes.clone()),
+            Recipient::Idle => (ResourceConsumer::custom("attribution", "idle"), Vec::new()),
+            Recipient::Unattributed if spec.unattributed == UnattributedPolicy::Report => (
+                ResourceConsumer::LocalMachine,
+                vec![(String::from("attribution"), AttributeValue::Str("unattributed"))],
+            ),
+            Recipient::Unattributed => continue,
+        };
+        let mut outputs = vec![(spec.output, dynamic + static_)];
+        if let Some(idle) = &spec.idle {
+            outputs.push((idle.dynamic_output, dynamic));
+            outputs.push((idle.static_output, static_));
+        }
+        for (metric, energy) in outputs {
+            let point = MeasurementPoint::new_untyped(
+                timestamp,
+                metric,
+                resource.clone(),
+                consumer.clone(),
+                WrappedMeasurementValue::F64(energy),
+            )
+            .with_attr_vec(attributes.clone());
+            measurements.push(point);
+        }
+    }
+}
+
+impl ConsumerUsage {
+    /// Returns the usage of the consumer for each usage metric.
+    fn totals(&self, specs: &[UsageSpec]) -> Vec<f64> {
+        self.series
+            .iter()
+            .zip(specs)
+            .map(|(series, spec)| {
+                series
+                    .values()
+                    .map(|(sum, count)| match spec.aggregation {
+                        UsageAggregation::Sum => *sum,
+                        UsageAggregation::Mean => sum / f64::from(*count),
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
 
+fn available_cpus() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Computes the share of each consumer, given their usage for each usage metric.
+///
+/// The usage metrics that have not been measured for any consumer are ignored, and the weights
+/// of the others are normalized. Returns `None` if no usage has been measured at all.
+fn shares(usage: &[Vec<f64>], specs: &[UsageSpec]) -> Option<Vec<f64>> {
+    let totals: Vec<f64> = (0..specs.len())
+        .map(|m| usage.iter().map(|values| values[m]).sum())
+        .collect();
+    let total_weight: f64 = specs
+        .iter()
+        .zip(&totals)
+        .filter(|(_, total)| **total > 0.0)
+        .map(|(spec, _)| spec.weight)
+        .sum();
+    if total_weight <= 0.0 {
+        return None;
+    }
+    let shares = usage
+        .iter()
+        .map(|values| {
+            let weighted: f64 = specs
+                .iter()
+                .zip(&totals)
+                .zip(values)
+                .filter(|((_, total), _)| **total > 0.0)
+                .map(|((spec, total), value)| spec.weight * value / total)
+                .sum();
+            weighted / total_weight
+        })
+        .collect();
+    Some(shares)
+}
+
+/// Returns the consumer that a usage point is about.
+///
+/// The cgroup sources use the path of the file they read (like `cpu.stat` or `memory.stat`) as the consumer.
+/// They are replaced by the directory of the cgroup, so that all the metrics of a cgroup have the same consumer.
+fn consumer_key(consumer: &ResourceConsumer) -> ResourceConsumer {
+    match consumer {
+        ResourceConsumer::ControlGroup { path } if path.ends_with(".stat") => {
+            match Path::new(path.as_ref()).parent().and_then(|dir| dir.to_str()) {
+                Some(dir) => ResourceConsumer::ControlGroup {
+                    path: dir.to_owned().into(),
+                },
+                None => consumer.clone(),
+            }
+        }
+        _ => consumer.clone(),
+    }
+}
+
+fn package_of(resource: &Resource) -> Option<u32> {
+    match resource {
+        Resource::CpuPackage { id } => Some(*id),
+        Resource::Dram { pkg_id } => Some(*pkg_id),
+        _ => None,
+    }
+}
+
+fn has_domain(point: &MeasurementPoint, domain: Option<&str>) -> bool {
+    match domain {
+        None => true,
+        Some(domain) => point
+            .attributes()
+            .any(|(k, v)| k == "domain" && v.to_string() == domain),
+    }
+}
+
+fn value_as_f64(value: &WrappedMeasurementValue) -> f64 {
+    match value {
+        WrappedMeasurementValue::F64(x) => *x,
+        WrappedMeasurementValue::U64(x) => *x as f64,
+    }
+}
+
+impl Transform for EnergyAttributionTransform {
+    /// Applies the transform on the measurements.
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, SystemTime},
+    };
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{AttributionSpec, EnergyAttributionTransform, UsageSpec};
+    use crate::{
+        idle::{BaselineConfig, IdleModel, IdlePolicy},
+        process::{ProcessTree, SharedRoots},
+        selector::{anchored_regex, ConsumerSelector, SelectorConfig},
+        topology::{CpuTopology, CpusetCache},
+        UnattributedPolicy, UsageAggregation,
+    };
+
+    const ENERGY: u64 = 0;
+    const CPU: u64 = 1;
+    const MEMORY: u64 = 2;
+    const RESERVED: u64 = 3;
+    const LLC_MISSES: u64 = 4;
+    const OUTPUT: u64 = 10;
+    const DYNAMIC: u64 = 11;
+    const STATIC: u64 = 12;
+
+    fn at(t_ms: u64) -> Timestamp {
+        Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_millis(t_ms))
+    }
+
+    fn energy(t_ms: u64, package: u32, joules: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            at(t_ms),
+            RawMetricId::from_u64(ENERGY),
+            Resource::CpuPackage { id: package },
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(joules),
+        )
+        .with_attr("domain", "package")
+    }
+
+    fn usage(t_ms: u64, metric: u64, cgroup: &str, value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            at(t_ms),
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            ResourceConsumer::ControlGroup {
+                path: format!("{cgroup}/cpu.stat").into(),
+            },
+            WrappedMeasurementValue::U64(value),
+        )
+    }
+
+    fn process(
+        t_ms: u64,
+        metric: u64,
+        pid: u32,
+        value: u64,
+        attribute: (&'static str, &'static str),
+    ) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            at(t_ms),
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::U64(value),
+        )
+        .with_attr(attribute.0, attribute.1)
+    }
+
+    /// Process tree of the tests: 12 -> 11 -> 10 -> 1 and 20 -> 1.
+    fn parent_of(pid: u32) -> Option<u32> {
+        match pid {
+            12 => Some(11),
+            11 => Some(10),
+            10 | 20 => Some(1),
+            _ => None,
+        }
+    }
+
+    fn spec(usage: Vec<UsageSpec>, per_package: bool) -> AttributionSpec {
+        AttributionSpec {
+            output: RawMetricId::from_u64(OUTPUT),
+            energy: RawMetricId::from_u64(ENERGY),
+            domain: Some(String::from("package")),
+            usage,
+            consumers: Vec::new(),
+            per_package,
+            unattributed: UnattributedPolicy::Report,
+            aggregate_children: false,
+            idle: None,
+        }
+    }
+
+    fn usage_spec(metric: u64) -> UsageSpec {
+        UsageSpec {
+            metric: RawMetricId::from_u64(metric),
+            weight: 1.0,
+            attributes: Vec::new(),
+            aggregation: UsageAggregation::Sum,
+        }
+    }
+
+    fn cpu_usage() -> Vec<UsageSpec> {
+        vec![usage_spec(CPU)]
+    }
+
+    fn idle(baseline: BaselineConfig, policy: IdlePolicy) -> Option<IdleModel> {
+        let outputs = (RawMetricId::from_u64(DYNAMIC), RawMetricId::from_u64(STATIC));
+        let reserved = Some(RawMetricId::from_u64(RESERVED));
+        Some(IdleModel::new(&baseline, policy, reserved, outputs, 1.0, 1e-6))
+    }
+
+    fn transform(spec: AttributionSpec, topology: CpuTopology) -> EnergyAttributionTransform {
+        EnergyAttributionTransform::new(
+            vec![spec],
+            Duration::from_secs(1),
+            Duration::from_millis(500),
+            topology,
+            CpusetCache::new(Duration::from_secs(60)),
+            ProcessTree::with_reader(SharedRoots::default(), Duration::from_secs(60), parent_of),
+        )
+    }
+
+    /// Runs the transform and returns the attributed energy as (package, consumer id, joules).
+    fn run(t: &mut EnergyAttributionTransform, points: Vec<MeasurementPoint>) -> Vec<(String, String, f64)> {
+        run_metric(t, points, OUTPUT)
+    }
+
+    /// Runs the transform and returns the given output metric as (package, consumer id, joules).
+    fn run_metric(
+        t: &mut EnergyAttributionTransform,
+        points: Vec<MeasurementPoint>,
+        metric: u64,
+    ) -> Vec<(String, String, f64)> {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf);
+        let mut res: Vec<_> = buf
+            .iter()
+            .filter(|p| p.metric == RawMetricId::from_u64(metric))
+            .map(|p| {
+                let WrappedMeasurementValue::F64(v) = p.value else {
+                    panic!("unexpected value {:?}", p.value);
+                };
+                let consumer = p.consumer.id_string().unwrap_or_else(|| String::from("unattributed"));
+                (p.resource.id_display().to_string(), consumer, v)
+            })
+            .collect();
+        res.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        res
+    }
+
+    fn s(x: &str) -> String {
+        x.to_owned()
+    }
+
+    #[test]
+    fn weighted_usage_metrics() {
+        let usage_specs = vec![
+            UsageSpec {
+                weight: 3.0,
+                ..usage_spec(CPU)
+            },
+            usage_spec(MEMORY),
+        ];
+        let mut t = transform(spec(usage_specs, false), CpuTopology::default());
+        let res = run(
+            &mut t,
+            vec![
+                energy(200, 0, 80.0),
+                usage(300, CPU, "/a", 100),
+                usage(300, CPU, "/b", 300),
+                usage(300, MEMORY, "/a", 1),
+                usage(300, MEMORY, "/b", 1),
+            ],
+        );
+        // the window is not complete yet
+        assert!(res.is_empty());
+        let res = run(&mut t, vec![energy(1600, 0, 10.0)]);
+        // a: 3/4 * 1/4 + 1/4 * 1/2 = 5/16, b: 3/4 * 3/4 + 1/4 * 1/2 = 11/16
+        assert_eq!(res, vec![(s(""), s("/a"), 25.0), (s(""), s("/b"), 55.0)]);
+    }
+
+    #[test]
+    fn per_package_with_cpusets() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        for (cgroup, cpus) in [("a", "0-1"), ("b", "0-3")] {
+            std::fs::create_dir(dir.path().join(cgroup)).unwrap();
+            std::fs::write(dir.path().join(cgroup).join("cpuset.cpus.effective"), cpus).unwrap();
+        }
+        let topology = CpuTopology::new(BTreeMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let usage_specs = vec![usage_spec(CPU)];
+        let mut t = transform(spec(usage_specs, true), topology);
+        let (a, b) = (format!("{root}/a"), format!("{root}/b"));
+        let res = run(
+            &mut t,
+            vec![
+                energy(100, 0, 30.0),
+                energy(100, 1, 20.0),
+                usage(100, CPU, &a, 100),
+                usage(100, CPU, &b, 100),
+                energy(2000, 0, 1.0),
+            ],
+        );
+        // a only runs on package 0, b runs on both packages
+        assert_eq!(
+            res,
+            vec![(s("0"), a.clone(), 20.0), (s("0"), b.clone(), 10.0), (s("1"), b, 20.0)]
+        );
+    }
+
+    #[test]
+    fn missing_data_and_selectors() {
+        let usage_specs = vec![usage_spec(CPU)];
+        let mut spec = spec(usage_specs, false);
+        let selector = SelectorConfig {
+            kind: Some(s("cgroup")),
+            id: Some(s(".*pod.*")),
+            ..Default::default()
+        };
+        spec.consumers = vec![ConsumerSelector::try_from(&selector).unwrap()];
+        let mut t = transform(spec, CpuTopology::default());
+        let res = run(
+            &mut t,
+            vec![
+                // energy without any usage of the selected consumers
+                energy(100, 0, 10.0),
+                usage(100, CPU, "/system", 100),
+                // usage without energy
+                usage(1100, CPU, "/pod1", 100),
+                energy(3000, 0, 1.0),
+            ],
+        );
+        assert_eq!(res, vec![(s(""), s("unattributed"), 10.0)]);
+
+        // a late point is ignored
+        assert!(run(&mut t, vec![energy(100, 0, 10.0), energy(3100, 0, 1.0)]).is_empty());
+    }
+
+    #[test]
+    fn energy_in_a_later_buffer() {
+        // the energy is flushed less often than the usage
+        let mut t = EnergyAttributionTransform::new(
+            vec![spec(cpu_usage(), false)],
+            Duration::from_secs(1),
+            Duration::from_secs(6),
+            CpuTopology::default(),
+            CpusetCache::new(Duration::from_secs(60)),
+            ProcessTree::with_reader(SharedRoots::default(), Duration::from_secs(60), parent_of),
+        );
+        let res = run(
+            &mut t,
+            vec![
+                usage(100, CPU, "/a", 100),
+                usage(100, CPU, "/b", 300),
+                usage(1100, CPU, "/a", 100),
+                usage(4900, CPU, "/a", 100),
+            ],
+        );
+        assert!(res.is_empty());
+        let res = run(&mut t, vec![energy(100, 0, 40.0), energy(1100, 0, 10.0)]);
+        assert!(res.is_empty());
+
+        // the first window is complete
+        let res = run(&mut t, vec![usage(7500, CPU, "/a", 100)]);
+        assert_eq!(res, vec![(s(""), s("/a"), 10.0), (s(""), s("/b"), 30.0)]);
+    }
+
+    #[test]
+    fn idle_policies() {
+        let points = || {
+            vec![
+                energy(100, 0, 30.0),
+                usage(100, CPU, "/a", 100),
+                usage(100, CPU, "/b", 300),
+                usage(100, RESERVED, "/a", 1),
+                usage(100, RESERVED, "/b", 3),
+                energy(2000, 0, 1.0),
+            ]
+        };
+        let baseline = BaselineConfig::Fixed { power: 10.0 };
+
+        // 10 J of static energy, 20 J of dynamic energy
+        let mut spec = spec(cpu_usage(), false);
+        spec.idle = idle(baseline.clone(), IdlePolicy::Even);
+        let mut t = transform(spec, CpuTopology::default());
+        let mut buf = MeasurementBuffer::from(points());
+        t.process(&mut buf);
+        let values = |metric: u64| -> Vec<f64> {
+            let mut v: Vec<(String, f64)> = buf
+                .iter()
+                .filter(|p| p.metric == RawMetricId::from_u64(metric))
+                .map(|p| match p.value {
+                    WrappedMeasurementValue::F64(x) => (p.consumer.id_string().unwrap_or_default(), x),
+                    _ => panic!("unexpected value"),
+                })
+                .collect();
+            v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            v.into_iter().map(|(_, x)| x).collect()
+        };
+        assert_eq!(values(DYNAMIC), vec![5.0, 15.0]);
+        assert_eq!(values(STATIC), vec![5.0, 5.0]);
+        assert_eq!(values(OUTPUT), vec![10.0, 20.0]);
+
+        let mut spec = spec_with_idle(baseline.clone(), IdlePolicy::Reserved);
+        spec.per_package = false;
+        let mut t = transform(spec, CpuTopology::default());
+        let res = run_metric(&mut t, points(), STATIC);
+        assert_eq!(res, vec![(s(""), s("/a"), 2.5), (s(""), s("/b"), 7.5)]);
+
+        let mut t = transform(spec_with_idle(baseline, IdlePolicy::Separate), CpuTopology::default());
+        let res = run_metric(&mut t, points(), OUTPUT);
+        assert_eq!(
+            res,
+            vec![
+                (s("0"), s("/a"), 5.0),
+                (s("0"), s("/b"), 15.0),
+                (s("0"), s("idle"), 10.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn process_trees() {
+        let filter = |key: &str, pattern: &str| vec![(key.to_owned(), anchored_regex(pattern).unwrap())];
+        let usage_specs = vec![
+            UsageSpec {
+                attributes: filter("cpu_state", "user|system"),
+                ..usage_spec(CPU)
+            },
+            UsageSpec {
+                attributes: filter("memory_kind", "resident"),
+                aggregation: UsageAggregation::Mean,
+                ..usage_spec(MEMORY)
+            },
+        ];
+        let mut spec = spec(usage_specs, false);
+        spec.aggregate_children = true;
+        let roots = SharedRoots::default();
+        roots.lock().unwrap().insert(10);
+        let mut t = EnergyAttributionTransform::new(
+            vec![spec],
+            Duration::from_secs(1),
+            Duration::from_millis(500),
+            CpuTopology::default(),
+            CpusetCache::new(Duration::from_secs(60)),
+            ProcessTree::with_reader(roots, Duration::from_secs(60), parent_of),
+        );
+        let res = run(
+            &mut t,
+            vec![
+                energy(100, 0, 40.0),
+                // cpu: 200 ms for the tree of 10, 200 ms for 20
+                process(100, CPU, 11, 100, ("cpu_state", "user")),
+                process(100, CPU, 11, 500, ("cpu_state", "guest")),
+                process(100, CPU, 12, 100, ("cpu_state", "system")),
+                process(100, CPU, 20, 200, ("cpu_state", "user")),
+                // memory: 200 + 100 kB for the tree of 10, 100 kB for 20
+                process(100, MEMORY, 11, 100, ("memory_kind", "resident")),
+                process(500, MEMORY, 11, 300, ("memory_kind", "resident")),
+                process(100, MEMORY, 12, 100, ("memory_kind", "resident")),
+                process(100, MEMORY, 12, 10000, ("memory_kind", "vmsize")),
+                process(100, MEMORY, 20, 100, ("memory_kind", "resident")),
+                energy(2000, 0, 1.0),
+            ],
+        );
+        // 10: 1/2 * 1/2 + 1/2 * 3/4 = 5/8, 20: 1/2 * 1/2 + 1/2 * 1/4 = 3/8
+        assert_eq!(res, vec![(s(""), s("10"), 25.0), (s(""), s("20"), 15.0)]);
+    }
+
+    #[test]
+    fn dram_by_memory_footprint() {
+        let dram = |t_ms: u64, package: u32, joules: f64| {
+            MeasurementPoint::new_untyped(
+                at(t_ms),
+                RawMetricId::from_u64(ENERGY),
+                Resource::Dram { pkg_id: package },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(joules),
+            )
+            .with_attr("domain", "dram")
+        };
+        let usage_specs = vec![
+            UsageSpec {
+                weight: 0.5,
+                aggregation: UsageAggregation::Mean,
+                ..usage_spec(MEMORY)
+            },
+            UsageSpec {
+                weight: 0.5,
+                ..usage_spec(LLC_MISSES)
+            },
+        ];
+        let mut spec = spec(usage_specs, true);
+        spec.domain = Some(s("dram"));
+        let mut t = transform(spec, CpuTopology::default());
+        let res = run(
+            &mut t,
+            vec![
+                dram(100, 0, 10.0),
+                dram(100, 1, 6.0),
+                energy(100, 0, 50.0),
+                // memory: 200 for a (average), 200 for b
+                usage(100, MEMORY, "/a", 100),
+                usage(600, MEMORY, "/a", 300),
+                usage(100, MEMORY, "/b", 200),
+                usage(100, LLC_MISSES, "/a", 300),
+                usage(100, LLC_MISSES, "/b", 100),
+                dram(2000, 0, 1.0),
+            ],
+        );
+        // a: 1/2 * 1/2 + 1/2 * 3/4 = 5/8, b: 1/2 * 1/2 + 1/2 * 1/4 = 3/8
+        assert_eq!(
+            res,
+            vec![
+                (s("0"), s("/a"), 6.25),
+                (s("0"), s("/b"), 3.75),
+                (s("1"), s("/a"), 3.75),
+                (s("1"), s("/b"), 2.25)
+            ]
+        );
+    }
+
+    fn spec_with_idle(baseline: BaselineConfig, policy: IdlePolicy) -> AttributionSpec {
+        let mut spec = spec(cpu_usage(), true);
+        spec.idle = idle(baseline, policy);
+        spec
+    }
+
+    #[test]
+    fn learned_idle_power() {
+        let baseline = BaselineConfig::Learned {
+            max_utilization: 0.1,
+            initial_power: 0.0,
+        };
+        let mut t = transform(spec_with_idle(baseline, IdlePolicy::Even), CpuTopology::default());
+        // low utilization: 1 ms of CPU time in 1 s
+        let res = run_metric(
+            &mut t,
+            vec![energy(100, 0, 12.0), usage(100, CPU, "/a", 1000), energy(1600, 0, 40.0)],
+            STATIC,
+        );
+        // the idle power was not known yet
+        assert_eq!(res, vec![(s("0"), s("/a"), 0.0)]);
+
+        // high utilization: the idle power is not learned again
+        let res = run_metric(
+            &mut t,
+            vec![usage(1600, CPU, "/a", 1_000_000), energy(2600, 0, 40.0)],
+            STATIC,
+        );
+        assert_eq!(res, vec![(s("0"), s("/a"), 12.0)]);
+        let res = run_metric(&mut t, vec![usage(2600, CPU, "/a", 1000), energy(3600, 0, 1.0)], STATIC);
+        assert_eq!(res, vec![(s("0"), s("/a"), 12.0)]);
+
+        // the static energy is never larger than the energy
+        let res = run_metric(&mut t, vec![usage(3600, CPU, "/a", 1000), energy(4600, 0, 1.0)], STATIC);
+        assert_eq!(res, vec![(s("0"), s("/a"), 1.0)]);
+    }
+}
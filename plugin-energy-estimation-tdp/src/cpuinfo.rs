@@ -0,0 +1,121 @@
+//! Information about the CPUs of the host, and the bundled table of TDP values.
+
+use std::{collections::BTreeSet, fs};
+
+/// TDP of some CPU models, in watts per package: `model,tdp`.
+const TDP_TABLE: &str = include_str!("cpu_tdp.csv");
+
+/// What `/proc/cpuinfo` tells about the CPUs.
+#[derive(Debug, Default, PartialEq)]
+pub struct CpuInfo {
+    /// Name of the CPU model, such as `Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz`.
+    pub model_name: Option<String>,
+    /// Number of logical CPUs.
+    pub n_cpus: u32,
+    /// Number of packages (sockets).
+    pub n_packages: u32,
+}
+
+impl CpuInfo {
+    pub fn read() -> anyhow::Result<Self> {
+        let content = fs::read_to_string("/proc/cpuinfo")?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut model_name = None;
+        let mut n_cpus = 0;
+        let mut packages = BTreeSet::new();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            match key.trim() {
+                "processor" => n_cpus += 1,
+                "model name" if model_name.is_none() => model_name = Some(value.trim().to_owned()),
+                "physical id" => {
+                    packages.insert(value.trim().to_owned());
+                }
+                _ => (),
+            }
+        }
+        Self {
+            model_name,
+            n_cpus,
+            // virtual machines and some architectures do not report the packages
+            n_packages: packages.len().max(1) as u32,
+        }
+    }
+}
+
+/// Looks up the TDP of a CPU model in the bundled table.
+///
+/// The names of the table must appear entirely in the model name, which is compared without
+/// the trademark symbols and the case. If several names match, the longest wins.
+pub fn lookup_tdp(model_name: &str) -> Option<f64> {
+    let model = normalize(model_name);
+    TDP_TABLE
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, tdp) = line.rsplit_once(',')?;
+            let name = normalize(name);
+            if !contains_words(&model, &name) {
+                return None;
+            }
+            Some((name.len(), tdp.trim().parse::<f64>().ok()?))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, tdp)| tdp)
+}
+
+fn normalize(name: &str) -> String {
+    name.replace("(R)", " ")
+        .replace("(TM)", " ")
+        .split_whitespace()
+        .filter(|word| *word != "CPU")
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Checks that `words` appears in `text`, and is not part of a longer word.
+fn contains_words(text: &str, words: &str) -> bool {
+    text.match_indices(words).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + words.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup_tdp, CpuInfo};
+
+    #[test]
+    fn parse_cpuinfo() {
+        let content = "processor\t: 0\nmodel name\t: Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz\nphysical id\t: 0\n\n\
+                       processor\t: 1\nmodel name\t: Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz\nphysical id\t: 1\n\n\
+                       processor\t: 2\nmodel name\t: Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz\nphysical id\t: 1\n";
+        let info = CpuInfo::parse(content);
+        assert_eq!(
+            info,
+            CpuInfo {
+                model_name: Some(String::from("Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz")),
+                n_cpus: 3,
+                n_packages: 2,
+            }
+        );
+        assert_eq!(CpuInfo::parse("processor : 0\n").n_packages, 1);
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(lookup_tdp("Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz"), Some(125.0));
+        assert_eq!(lookup_tdp("Intel(R) Xeon(R) Gold 6226R CPU @ 2.90GHz"), Some(150.0));
+        assert_eq!(lookup_tdp("Intel(R) Xeon(R) CPU E5-2630 v3 @ 2.40GHz"), Some(85.0));
+        assert_eq!(lookup_tdp("AMD EPYC 7452 32-Core Processor"), Some(155.0));
+        assert_eq!(lookup_tdp("Intel(R) Xeon(R) Gold 61300 CPU"), None);
+        assert_eq!(lookup_tdp("Unknown CPU"), None);
+    }
+}
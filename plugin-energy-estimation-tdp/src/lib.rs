@@ -1,5 +1,6 @@
 use alumet::{
-    metrics::{RawMetricId, TypedMetricId},
+    measurement::WrappedMeasurementType,
+    metrics::RawMetricId,
     plugin::{
         rust::{deserialize_config, serialize_config, AlumetPlugin},
         ConfigTable,
@@ -11,8 +12,10 @@ use std::time::Duration;
 
 use anyhow::Context;
 
+use cpuinfo::CpuInfo;
 use transform::EnergyEstimationTdpTransform;
 
+mod cpuinfo;
 mod transform;
 
 pub struct EnergyEstimationTdpPlugin {
@@ -20,13 +23,23 @@ pub struct EnergyEstimationTdpPlugin {
 }
 
 struct Metrics {
-    // To attribute the CPU consumption to K8S pods, we need 2 metrics:
-    // - cpu usage per pod
+    // To attribute the CPU consumption to the consumers (pods, processes...), we need 2 metrics:
+    // - cpu usage per consumer
     // - energy attribution (to store the result)
 
-    // The other parameters (tdp and number of virtual cpu is provided by configuration)
-    cpu_usage_per_pod: RawMetricId,
-    pod_estimate_attributed_energy: TypedMetricId<f64>,
+    // The other parameters (tdp and number of cpus) are provided by the configuration or read from the host.
+    cpu_usage: RawMetricId,
+    /// Factor to apply to the cpu usage to get seconds.
+    cpu_usage_to_seconds: f64,
+    estimated_energy: RawMetricId,
+}
+
+/// The power model: each CPU consumes `tdp * n_packages / n_cpus` watts when it is busy.
+struct Estimation {
+    /// TDP of one package, in watts.
+    tdp: f64,
+    n_packages: u32,
+    n_cpus: u32,
 }
 
 impl AlumetPlugin for EnergyEstimationTdpPlugin {
@@ -47,33 +60,46 @@ impl AlumetPlugin for EnergyEstimationTdpPlugin {
 
     fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
         let config = deserialize_config(config)?;
-        Ok(Box::new(EnergyEstimationTdpPlugin { config }))
+        Ok(Box::new(EnergyEstimationTdpPlugin { config: Some(config) }))
     }
 
     fn start(&mut self, alumet: &mut alumet::plugin::AlumetPluginStart) -> anyhow::Result<()> {
+        let config = self.config.take().context("the plugin has already been started")?;
+        let estimation = config.estimation()?;
+        log::info!(
+            "Estimating the energy with a TDP of {} W per package, {} package(s) and {} CPU(s).",
+            estimation.tdp,
+            estimation.n_packages,
+            estimation.n_cpus
+        );
+
         // Create the energy attribution metric and add its id to the
         // transform plugin metrics' list.
-        let pod_estimate_attributed_energy_metric = alumet.create_metric(
-            "pod_estimate_attributed_energy",
+        let estimated_energy_metric = alumet.create_metric_untyped(
+            &config.output,
+            WrappedMeasurementType::F64,
             Unit::Joule,
-            "Pod's estimated energy consumption",
+            "Consumer's estimated energy consumption",
         )?;
 
-        let cpu_usage = self.config.as_ref().unwrap().cpu_usage_per_pod.clone();
-        let config = self.config.take().unwrap();
-
         // Add the transform now but fill its metrics later.
         alumet.add_transform_builder("transform", move |ctx| {
-            let cpu_usage_metric = ctx
-                .metric_by_name(&cpu_usage)
-                .with_context(|| format!("metric not found : {}", cpu_usage))?
-                .0;
+            let (cpu_usage_metric, metric) = ctx
+                .metric_by_name(&config.cpu_usage)
+                .with_context(|| format!("metric not found : {}", config.cpu_usage))?;
+            let cpu_usage_to_seconds = metric.unit.factor(&Unit::Second).with_context(|| {
+                format!(
+                    "metric {} is not a cpu time, its unit is {}",
+                    config.cpu_usage, metric.unit
+                )
+            })?;
             let metrics = Metrics {
-                cpu_usage_per_pod: cpu_usage_metric,
-                pod_estimate_attributed_energy: pod_estimate_attributed_energy_metric,
+                cpu_usage: cpu_usage_metric,
+                cpu_usage_to_seconds,
+                estimated_energy: estimated_energy_metric,
             };
 
-            let transform = Box::new(EnergyEstimationTdpTransform::new(config, metrics));
+            let transform = Box::new(EnergyEstimationTdpTransform::new(config, estimation, metrics));
             Ok(transform)
         })?;
         Ok(())
@@ -86,25 +112,77 @@ impl AlumetPlugin for EnergyEstimationTdpPlugin {
     }
 }
 
-// for 1st version, tdp,vcpu, cpu are defined in configuration plugin
+// The tdp and the number of cpus can be defined in the configuration, or read from the host.
 #[derive(Serialize, Deserialize)]
 struct Config {
+    /// Expected interval between two points of a series, used when the previous point is unknown.
     #[serde(with = "humantime_serde")]
     poll_interval: Duration,
-    tdp: f64,
-    nb_vcpu: f64,
-    nb_cpu: f64,
-    cpu_usage_per_pod: String,
+    /// TDP of one CPU package, in watts. If not set, it is looked up in the bundled table of CPU models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tdp: Option<f64>,
+    /// Number of CPU packages (sockets). If not set, it is read from `/proc/cpuinfo`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nb_packages: Option<u32>,
+    /// Number of logical CPUs that share the power of the packages. If not set, it is read from `/proc/cpuinfo`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nb_cpu: Option<u32>,
+    /// Metric that gives the cpu time used by each consumer, such as `cgroup_cpu_usage_total` or `process_cpu_time`.
+    #[serde(alias = "cpu_usage_per_pod")]
+    cpu_usage: String,
+    /// Whether `cpu_usage` is a cumulative counter instead of the cpu time used since the previous point.
+    #[serde(default)]
+    cumulative: bool,
+    /// Name of the metric to create for the estimated energy.
+    #[serde(default = "default_output")]
+    output: String,
+}
+
+fn default_output() -> String {
+    String::from("pod_estimate_attributed_energy")
+}
+
+impl Config {
+    fn estimation(&self) -> anyhow::Result<Estimation> {
+        let needs_cpuinfo = self.tdp.is_none() || self.nb_packages.is_none() || self.nb_cpu.is_none();
+        let cpuinfo = match needs_cpuinfo {
+            true => CpuInfo::read().context("could not read /proc/cpuinfo, set tdp, nb_packages and nb_cpu")?,
+            false => CpuInfo::default(),
+        };
+        let tdp = match self.tdp {
+            Some(tdp) => tdp,
+            None => {
+                let model = cpuinfo
+                    .model_name
+                    .as_deref()
+                    .context("the CPU model is unknown, set the tdp in the configuration")?;
+                cpuinfo::lookup_tdp(model)
+                    .with_context(|| format!("the TDP of {model} is unknown, set it in the configuration"))?
+            }
+        };
+        let n_packages = self.nb_packages.unwrap_or(cpuinfo.n_packages);
+        let n_cpus = match self.nb_cpu.unwrap_or(cpuinfo.n_cpus) {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+            n => n,
+        };
+        Ok(Estimation {
+            tdp,
+            n_packages,
+            n_cpus,
+        })
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             poll_interval: Duration::from_secs(1), // 1Hz
-            tdp: 100.0,
-            nb_vcpu: 1.0,
-            nb_cpu: 1.0,
-            cpu_usage_per_pod: String::from("cgroup_cpu_usage_total"),
+            tdp: None,
+            nb_packages: None,
+            nb_cpu: None,
+            cpu_usage: String::from("cgroup_cpu_usage_total"),
+            cumulative: false,
+            output: default_output(),
         }
     }
 }
@@ -1,5 +1,4 @@
-use core::f64;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use alumet::{
     measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
@@ -7,94 +6,194 @@ use alumet::{
         elements::{error::TransformError, transform::TransformContext},
         Transform,
     },
+    resources::{Resource, ResourceConsumer},
 };
+use fxhash::FxHashMap;
 
-use crate::Config;
+use crate::{Config, Estimation};
+
+/// The series are forgotten when they have not been measured for this number of poll intervals.
+const SERIES_TIMEOUT: u32 = 10;
 
 pub struct EnergyEstimationTdpTransform {
     pub config: Config,
+    pub estimation: Estimation,
     pub metrics: super::Metrics,
+    /// Last point of each series of cpu usage: (timestamp, value).
+    series: FxHashMap<(Resource, ResourceConsumer), (SystemTime, f64)>,
 }
 
 impl EnergyEstimationTdpTransform {
-    /// Instantiates a new EnergyAttributionTransform with its private fields initialized.
-    pub fn new(config: Config, metrics: super::Metrics) -> Self {
-        Self { config, metrics }
+    /// Instantiates a new EnergyEstimationTdpTransform with its private fields initialized.
+    pub fn new(config: Config, estimation: Estimation, metrics: super::Metrics) -> Self {
+        Self {
+            config,
+            estimation,
+            metrics,
+            series: FxHashMap::default(),
+        }
+    }
+
+    /// Estimates the energy consumed by a consumer that used `cpu_usage` (in the unit of the metric)
+    /// since its previous point, or returns `None` if it cannot be estimated yet.
+    fn estimate(&mut self, point: &MeasurementPoint, cpu_usage: f64) -> Option<f64> {
+        let key = (point.resource.clone(), point.consumer.clone());
+        let now = SystemTime::from(point.timestamp);
+        let previous = self.series.insert(key, (now, cpu_usage));
+
+        // elapsed time since the previous point, and cpu time used in this interval
+        let (elapsed, used) = match (previous, self.config.cumulative) {
+            (Some((t, _)), _) if t >= now => {
+                log::trace!("ignoring a point that is not more recent than the previous one of its series");
+                return None;
+            }
+            (Some((t, value)), true) => {
+                if cpu_usage < value {
+                    log::debug!("the cumulative cpu usage of {:?} has been reset", point.consumer);
+                    return None;
+                }
+                (elapsed_between(t, now), cpu_usage - value)
+            }
+            (Some((t, _)), false) => (elapsed_between(t, now), cpu_usage),
+            (None, true) => return None,
+            (None, false) => (self.config.poll_interval, cpu_usage),
+        };
+
+        // The consumer cannot use more than all the cpus during the interval.
+        let estimation = &self.estimation;
+        let max_cpu_seconds = elapsed.as_secs_f64() * estimation.n_cpus as f64;
+        let cpu_seconds = (used * self.metrics.cpu_usage_to_seconds).min(max_cpu_seconds);
+        let cpu_power = estimation.tdp * estimation.n_packages as f64 / estimation.n_cpus as f64;
+        Some(cpu_seconds * cpu_power)
+    }
+
+    /// Forgets the series that have not been measured for a while.
+    fn clean(&mut self, now: SystemTime) {
+        let timeout = self.config.poll_interval * SERIES_TIMEOUT;
+        self.series.retain(|_, (t, _)| elapsed_between(*t, now) < timeout);
     }
 }
 
+fn elapsed_between(start: SystemTime, end: SystemTime) -> Duration {
+    end.duration_since(start).unwrap_or_default()
+}
+
 impl Transform for EnergyEstimationTdpTransform {
     /// Applies the transform on the measurements.
     fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
-        // Retrieve the pod_id and the rapl_id.
-        // Using a nested scope to reduce the lock time.
-        log::trace!("enter in apply transform function");
-
-        let pod_id = self.metrics.cpu_usage_per_pod.as_u64();
-        let metric_id = self.metrics.pod_estimate_attributed_energy;
-
         log::trace!(
             "enter in apply transform function, number of measurements: {}",
             measurements.len()
         );
 
-        for point in measurements.clone().iter() {
-            if point.metric.as_u64() == pod_id {
-                let id = SystemTime::from(point.timestamp).duration_since(UNIX_EPOCH)?.as_secs();
-                log::trace!("we get a measurement for pod with timestamp: {}", id);
-
-                let value = match point.value {
-                    WrappedMeasurementValue::F64(x) => x.to_string(),
-                    WrappedMeasurementValue::U64(x) => x.to_string(),
-                };
-
-                // from k8s plugin we get the cpu_usage_per_pod in micro second
-                // energy = cpu_usage_per_pod * nb_vcpu/nb_cpu * tdp / poll_interval
-                let mut estimated_energy = value.parse().unwrap();
-                estimated_energy = estimated_energy * self.config.nb_vcpu / self.config.nb_cpu * self.config.tdp
-                    / (1000000.0)
-                    / (self.config.poll_interval.as_secs() as f64);
-
-                log::trace!(
-                    "we get a measurement with resource:{}",
-                    point.resource.id_display().to_string()
-                );
-                log::trace!(
-                    "we get a measurement with consumer:{}",
-                    point.consumer.id_display().to_string()
-                );
-                log::trace!("we get a measurement with value:{}", value);
-                log::trace!("estimate energy consumption:{}", estimated_energy);
-
-                let point_attributes: Vec<(String, AttributeValue)> = point
-                    .attributes()
-                    .map(|(key, value)| (key.to_owned(), value.clone()))
-                    .collect();
-
-                // Sort the attributes by key
-                for (key, value_attr) in &point_attributes {
-                    log::trace!(
-                        "read attribute key / value: {} / {}",
-                        key.as_str(),
-                        value_attr.to_string()
-                    );
-                    if key.as_str().contains("node") {
-                        let node_value: String = value_attr.to_string();
-                        log::trace!("read attribute node value: {}", node_value);
-                    }
-                }
-
-                let new_m = MeasurementPoint::new(
-                    point.timestamp,
-                    metric_id,
-                    point.resource.clone(),
-                    point.consumer.clone(),
-                    estimated_energy,
-                )
-                .with_attr_vec(point_attributes);
-                measurements.push(new_m.clone());
+        let cpu_usage_metric = self.metrics.cpu_usage;
+        let energy_metric = self.metrics.estimated_energy;
+        let mut estimated = Vec::new();
+        let mut latest = UNIX_EPOCH;
+        for point in measurements.iter() {
+            if point.metric != cpu_usage_metric {
+                continue;
             }
+            latest = latest.max(SystemTime::from(point.timestamp));
+
+            let value = match point.value {
+                WrappedMeasurementValue::F64(x) => x,
+                WrappedMeasurementValue::U64(x) => x as f64,
+            };
+            let Some(estimated_energy) = self.estimate(point, value) else {
+                continue;
+            };
+            log::trace!(
+                "estimate energy consumption of {}: {} J",
+                point.consumer.id_display(),
+                estimated_energy
+            );
+
+            let point_attributes: Vec<(String, AttributeValue)> = point
+                .attributes()
+                .map(|(key, value)| (key.to_owned(), value.clone()))
+                .collect();
+            let new_m = MeasurementPoint::new_untyped(
+                point.timestamp,
+                energy_metric,
+                point.resource.clone(),
+                point.consumer.clone(),
+                WrappedMeasurementValue::F64(estimated_energy),
+            )
+            .with_attr_vec(point_attributes);
+            estimated.push(new_m);
+        }
+        for point in estimated {
+            measurements.push(point);
         }
+        self.clean(latest);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::EnergyEstimationTdpTransform;
+    use crate::{Config, Estimation, Metrics};
+
+    fn transform(cumulative: bool) -> EnergyEstimationTdpTransform {
+        let config = Config {
+            poll_interval: Duration::from_millis(500),
+            cumulative,
+            ..Default::default()
+        };
+        // 4 cpus of 25 W each
+        let estimation = Estimation {
+            tdp: 50.0,
+            n_packages: 2,
+            n_cpus: 4,
+        };
+        let metrics = Metrics {
+            cpu_usage: RawMetricId::from_u64(0),
+            cpu_usage_to_seconds: 1e-6,
+            estimated_energy: RawMetricId::from_u64(1),
+        };
+        EnergyEstimationTdpTransform::new(config, estimation, metrics)
+    }
+
+    fn cpu_usage(t_ms: u64, pid: u32, usec: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_millis(t_ms)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::U64(usec),
+        )
+    }
+
+    #[test]
+    fn cumulative_counters() {
+        let mut t = transform(true);
+        assert_eq!(t.estimate(&cpu_usage(1000, 1, 1_000_000), 1_000_000.0), None);
+        // 0.2 s of cpu time in 250 ms
+        assert_eq!(t.estimate(&cpu_usage(1250, 1, 1_200_000), 1_200_000.0), Some(5.0));
+        // the series are independent
+        assert_eq!(t.estimate(&cpu_usage(1250, 2, 0), 0.0), None);
+        assert_eq!(t.estimate(&cpu_usage(1500, 2, 100_000), 100_000.0), Some(2.5));
+        // reset of the counter
+        assert_eq!(t.estimate(&cpu_usage(1500, 1, 0), 0.0), None);
+    }
+
+    #[test]
+    fn deltas_with_real_elapsed_time() {
+        let mut t = transform(false);
+        // the first point uses the poll interval: at most 4 cpus during 500 ms
+        assert_eq!(t.estimate(&cpu_usage(1000, 1, 3_000_000), 3_000_000.0), Some(50.0));
+        // at most 4 cpus during 100 ms
+        assert_eq!(t.estimate(&cpu_usage(1100, 1, 1_000_000), 1_000_000.0), Some(10.0));
+        assert_eq!(t.estimate(&cpu_usage(1200, 1, 100_000), 100_000.0), Some(2.5));
+    }
+}
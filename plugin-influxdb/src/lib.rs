@@ -1,9 +1,12 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, time::Duration};
 
 use alumet::{
-    measurement::{AttributeValue, MeasurementBuffer, WrappedMeasurementValue},
+    measurement::{AttributeValue, MeasurementBuffer, WrappedMeasurementValue, STALE_MARKER_ATTRIBUTE},
     pipeline::{
-        elements::{error::WriteError, output::OutputContext},
+        elements::{
+            error::WriteError,
+            output::{batch::BatchSettings, OutputContext},
+        },
         Output,
     },
     plugin::rust::{deserialize_config, serialize_config, AlumetPlugin},
@@ -65,6 +68,7 @@ impl AlumetPlugin for InfluxDbPlugin {
                 attributes_as_fields: config.attributes_as_fields.unwrap_or_default(),
             }),
         )?;
+        alumet.set_output_batching("out", config.batch);
         Ok(())
     }
 
@@ -126,7 +130,10 @@ impl Output for InfluxDbOutput {
                     }
                 }
             };
-            let (tags, fields): (Vec<_>, Vec<_>) = m.attributes().partition(|(key, _)| partition_tag(key));
+            let (tags, fields): (Vec<_>, Vec<_>) = m
+                .attributes()
+                .filter(|(key, _)| *key != STALE_MARKER_ATTRIBUTE)
+                .partition(|(key, _)| partition_tag(key));
 
             // Append tags.
             for (tag_key, tag_value) in tags {
@@ -154,7 +161,9 @@ impl Output for InfluxDbOutput {
             }
 
             // Alumet value is a field.
+            // A staleness marker has no value (InfluxDB does not accept NaN), it gets the field `stale` instead.
             match m.value {
+                _ if m.is_stale_marker() => builder.field_bool("stale", true),
                 WrappedMeasurementValue::F64(v) => builder.field_float("value", v),
                 WrappedMeasurementValue::U64(v) => builder.field_uint("value", v),
             };
@@ -184,6 +193,15 @@ struct Config {
     attributes_as: AttributeAs,
     attributes_as_tags: Option<HashSet<String>>,
     attributes_as_fields: Option<HashSet<String>>,
+    /// Batching of the measurements, to reduce the number of write requests.
+    #[serde(default = "default_batch")]
+    batch: BatchSettings,
+}
+
+fn default_batch() -> BatchSettings {
+    BatchSettings::default()
+        .max_points(5000)
+        .max_delay(Duration::from_secs(1))
 }
 
 /// How to serialize Alumet attributes by default?
@@ -208,6 +226,7 @@ impl Default for Config {
             attributes_as: AttributeAs::Field,
             attributes_as_tags: None,
             attributes_as_fields: None,
+            batch: default_batch(),
         }
     }
 }
@@ -1,7 +1,12 @@
+use std::time::Duration;
+
 use alumet::{
     measurement::{AttributeValue, MeasurementBuffer, WrappedMeasurementValue},
     pipeline::{
-        elements::{error::WriteError, output::OutputContext},
+        elements::{
+            error::WriteError,
+            output::{batch::BatchSettings, OutputContext},
+        },
         Output,
     },
     plugin::rust::{deserialize_config, serialize_config, AlumetPlugin},
@@ -61,6 +66,7 @@ impl AlumetPlugin for MongoDbPlugin {
                 collection: config.collection,
             }),
         )?;
+        alumet.set_output_batching("out", config.batch);
         Ok(())
     }
 
@@ -169,6 +175,15 @@ struct Config {
     collection: String,
     username: Option<String>,
     password: Option<String>,
+    /// Batching of the measurements, to reduce the number of insertions.
+    #[serde(default = "default_batch")]
+    batch: BatchSettings,
+}
+
+fn default_batch() -> BatchSettings {
+    BatchSettings::default()
+        .max_points(5000)
+        .max_delay(Duration::from_secs(1))
 }
 
 impl Default for Config {
@@ -180,6 +195,7 @@ impl Default for Config {
             collection: String::from("FILL ME"),
             username: Some(String::from("FILL ME")),
             password: Some(String::from("FILL ME")),
+            batch: default_batch(),
         }
     }
 }
@@ -1,8 +1,4 @@
-use std::{
-    future::Future,
-    io,
-    time::{Duration, Instant},
-};
+use std::{future::Future, io};
 
 use alumet::{
     measurement::MeasurementBuffer,
@@ -21,8 +17,6 @@ pub struct TcpOutput {
     settings: Settings,
     alumet: AlumetLink,
     out_relay: protocol::MessageStream<TcpStream>,
-    buffer: MeasurementBuffer,
-    buffer_last_send: Instant,
 }
 
 /// Links between the Alumet pipeline and the relay output.
@@ -39,17 +33,10 @@ pub struct AlumetLink {
 pub struct Settings {
     pub client_name: String,
     pub server_address: String,
-    pub buffer: BufferSettings,
     pub msg_retry: ExponentialRetryPolicy,
     pub init_retry: ExponentialRetryPolicy,
 }
 
-pub struct BufferSettings {
-    pub initial_capacity: usize,
-    pub max_length: usize,
-    pub timeout: Duration,
-}
-
 pub enum RetryAction {
     /// Fail immediately and propagate the error.
     Fail,
@@ -86,71 +73,50 @@ impl TcpOutput {
         let out_relay = res.unwrap();
         log::info!("Successfully connected to relay server.");
 
-        // Create a buffer for sending measurements in a more efficient way.
-        let buffer = MeasurementBuffer::with_capacity(settings.buffer.initial_capacity);
-
         Ok(TcpOutput {
             settings,
             alumet,
             out_relay,
-            buffer,
-            buffer_last_send: Instant::now(),
         })
     }
 
     /// Serialize the measurements and send the result via TCP.
-    async fn send_measurements(&mut self, mut measurements: MeasurementBuffer) -> Result<(), protocol::Error> {
-        let now = Instant::now();
-        let size_limit_reached = self.buffer.len() + measurements.len() > self.settings.buffer.max_length;
-        let timeout_expired = (now - self.buffer_last_send) > self.settings.buffer.timeout;
-
-        log::trace!("size_limit_reached={size_limit_reached}, timeout_expired={timeout_expired}, now={now:?}");
-
-        if !size_limit_reached {
-            self.buffer.merge(&mut measurements);
-        }
-        // TODO it would be better to use a transform step for the buffering, wouldn't it?
-
-        if size_limit_reached || timeout_expired {
-            self.buffer_last_send = now;
-            let msg = protocol::MessageBody {
-                sender: self.settings.client_name.clone(),
-                content: protocol::MessageEnum::SendMeasurements(protocol::SendMeasurements {
-                    buf: serde_impl::SerdeMeasurementBuffer::Borrowed(&self.buffer),
-                }),
-            };
-            // --- writing
-            let mut retry_state = RetryState::new(&self.settings.msg_retry);
-            let mut res = self.out_relay.write_message(&msg).await;
-            while let Err(e) = res {
-                if !retry_state.can_retry() {
-                    return Err(e);
-                }
-                log::error!("Sending measurements failed: {e:?} - retrying...");
-                retry_state.after_attempt().await;
-                match retry_action(&e) {
-                    RetryAction::Fail => return Err(e),
-                    RetryAction::RetryOp => res = self.out_relay.write_message(&msg).await,
-                    RetryAction::Reconnect => {
-                        res = async {
-                            self.out_relay = connect_to_server(
-                                &self.settings.server_address,
-                                &self.settings.client_name,
-                                &self.alumet.metrics_reader,
-                            )
-                            .await?;
-                            self.out_relay.write_message(&msg).await
-                        }
-                        .await;
+    ///
+    /// The measurements are batched by the Alumet pipeline before reaching this output (see the plugin configuration).
+    async fn send_measurements(&mut self, measurements: &MeasurementBuffer) -> Result<(), protocol::Error> {
+        let msg = protocol::MessageBody {
+            sender: self.settings.client_name.clone(),
+            content: protocol::MessageEnum::SendMeasurements(protocol::SendMeasurements {
+                buf: serde_impl::SerdeMeasurementBuffer::Borrowed(measurements),
+            }),
+        };
+        // --- writing
+        let mut retry_state = RetryState::new(&self.settings.msg_retry);
+        let mut res = self.out_relay.write_message(&msg).await;
+        while let Err(e) = res {
+            if !retry_state.can_retry() {
+                return Err(e);
+            }
+            log::error!("Sending measurements failed: {e:?} - retrying...");
+            retry_state.after_attempt().await;
+            match retry_action(&e) {
+                RetryAction::Fail => return Err(e),
+                RetryAction::RetryOp => res = self.out_relay.write_message(&msg).await,
+                RetryAction::Reconnect => {
+                    res = async {
+                        self.out_relay = connect_to_server(
+                            &self.settings.server_address,
+                            &self.settings.client_name,
+                            &self.alumet.metrics_reader,
+                        )
+                        .await?;
+                        self.out_relay.write_message(&msg).await
                     }
+                    .await;
                 }
             }
-            // ---
-            self.buffer.clear();
-            if size_limit_reached {
-                self.buffer.merge(&mut measurements);
-            }
         }
+        // ---
         Ok(())
     }
 
@@ -215,7 +181,7 @@ impl TcpOutput {
                     }
                     measurements = self.alumet.in_measurements.0.next() => {
                         match measurements {
-                            Some(Ok(buf)) => self.send_measurements(buf).await?,
+                            Some(Ok(buf)) => self.send_measurements(&buf).await?,
                             Some(Err(StreamRecvError::Lagged(n))) => {
                                 log::warn!("{n} measurement buffers were lost because this output was too slow!");
                             }
@@ -277,7 +243,7 @@ async fn connect_to_server(
 
     // send the metric definitions (for metrics that are known at this point)
     log::debug!("Sending initial metrics...");
-    let metrics = metrics_reader.read().await;
+    let metrics = metrics_reader.snapshot();
     let to_send = metrics
         .iter()
         .map(|(id, def)| protocol::Metric::from((*id, def.to_owned())))
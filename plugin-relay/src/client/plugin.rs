@@ -1,5 +1,5 @@
 use alumet::metrics::{Metric, RawMetricId};
-use alumet::pipeline::elements::output::BoxedAsyncOutput;
+use alumet::pipeline::elements::output::{batch::BatchSettings, BoxedAsyncOutput};
 use alumet::plugin::{
     rust::{deserialize_config, serialize_config, AlumetPlugin},
     AlumetPluginStart, ConfigTable,
@@ -120,11 +120,6 @@ impl AlumetPlugin for RelayClientPlugin {
         let client_settings = output::Settings {
             client_name: config.client_name,
             server_address: config.relay_server,
-            buffer: output::BufferSettings {
-                initial_capacity: 512,
-                max_length: config.buffer_max_length,
-                timeout: config.buffer_timeout,
-            },
             msg_retry: ExponentialRetryPolicy {
                 max_retrys: config.retry.max_times,
                 initial_delay: config.retry.initial_delay,
@@ -158,7 +153,13 @@ impl AlumetPlugin for RelayClientPlugin {
 
             let output: BoxedAsyncOutput = Box::pin(tcp.send_loop());
             Ok(output)
-        });
+        })?;
+
+        // Send the measurements in batches, to reduce the number of messages.
+        let batching = BatchSettings::default()
+            .max_points(config.buffer_max_length)
+            .max_delay(config.buffer_timeout);
+        alumet.set_output_batching("tcp_client", batching);
 
         alumet.on_pre_pipeline_start(move |pre_start| {
             // register the existing metrics
@@ -169,9 +170,9 @@ impl AlumetPlugin for RelayClientPlugin {
                 .context("failed to send the initial metrics to the TCP output")?;
 
             // hook to register the late metrics
-            pre_start.add_metric_listener("late_metrics_hook", move |new_metrics| {
+            pre_start.add_metric_listener("late_metrics_hook", move |update| {
                 metrics_tx
-                    .send(new_metrics)
+                    .send(update.new_metrics)
                     .context("failed to send late metrics to the TCP output")
             })?;
             Ok(())
@@ -0,0 +1,218 @@
+//! Aggregates measurements over time windows.
+
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::{PrefixedUnit, Unit},
+};
+use anyhow::{anyhow, Context};
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use transform::{AggregationTransform, Rule, Windows};
+
+mod transform;
+
+pub struct AggregationPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for AggregationPlugin {
+    fn name() -> &'static str {
+        "aggregation"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(AggregationPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut rules = Vec::with_capacity(self.config.rules.len());
+        for rule in &self.config.rules {
+            let slide = rule.slide.unwrap_or(rule.window);
+            if rule.window.is_zero() || slide.is_zero() {
+                return Err(anyhow!("the window and slide durations must not be zero"));
+            }
+            if slide > rule.window {
+                return Err(anyhow!(
+                    "the slide ({slide:?}) must not be larger than the window ({:?})",
+                    rule.window
+                ));
+            }
+            if rule.functions.is_empty() {
+                return Err(anyhow!("no aggregation function for metrics {:?}", rule.metrics));
+            }
+
+            let mut outputs = FxHashMap::default();
+            for metric in &rule.metrics {
+                let Some((input_id, input)) = alumet.metrics().by_name(metric) else {
+                    log::warn!("Metric {metric} not found, it will not be aggregated.");
+                    continue;
+                };
+                let unit = input.unit.clone();
+                let mut ids = Vec::with_capacity(rule.functions.len());
+                for function in &rule.functions {
+                    // "p99.9" gives "p99_9" in the metric name
+                    let function_name = function.to_string().replace('.', "_");
+                    let name = format!("{metric}_{function_name}{}", rule.output_suffix);
+                    let (value_type, unit) = match function {
+                        Function::Count => (WrappedMeasurementType::U64, PrefixedUnit::from(Unit::Unity)),
+                        _ => (WrappedMeasurementType::F64, unit.clone()),
+                    };
+                    let description = format!("{function} of {metric} over windows of {:?}", rule.window);
+                    let id = alumet
+                        .create_metric_untyped(&name, value_type, unit, &description)
+                        .with_context(|| format!("could not create metric {name}"))?;
+                    ids.push(id);
+                }
+                outputs.insert(input_id, ids);
+            }
+            rules.push(Rule {
+                windows: Windows::new(rule.window, slide, rule.allowed_lateness),
+                functions: rule.functions.clone(),
+                outputs,
+                late_points: rule.late_points,
+                keep_input: rule.keep_input,
+            });
+        }
+        alumet.add_transform("transform", Box::new(AggregationTransform::new(rules)))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Names of the metrics to aggregate.
+    metrics: Vec<String>,
+    /// Length of the windows.
+    #[serde(with = "humantime_serde")]
+    window: Duration,
+    /// Time between the start of two consecutive windows.
+    /// Defaults to `window`, which gives tumbling (non-overlapping) windows.
+    #[serde(default, with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    slide: Option<Duration>,
+    /// Aggregation functions to apply on each window.
+    functions: Vec<Function>,
+    /// How long to wait for late points before closing a window.
+    #[serde(default, with = "humantime_serde")]
+    allowed_lateness: Duration,
+    /// What to do with the points that arrive after the closing of their window.
+    #[serde(default)]
+    late_points: LatePolicy,
+    /// Appended to the name of the new metrics, after the name of the function.
+    #[serde(default)]
+    output_suffix: String,
+    /// Keep the measurements of the aggregated metrics in addition to the aggregates.
+    #[serde(default)]
+    keep_input: bool,
+}
+
+/// Aggregation function.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) enum Function {
+    Mean,
+    Min,
+    Max,
+    Sum,
+    /// Value of the latest point of the window.
+    Last,
+    /// Number of points in the window.
+    Count,
+    /// Percentile between 0 and 100, with linear interpolation.
+    Percentile(f64),
+}
+
+/// What to do with a point whose windows are closed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LatePolicy {
+    /// Discard the point.
+    #[default]
+    Drop,
+    /// Forward the point as is, without aggregating it.
+    Forward,
+}
+
+impl FromStr for Function {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let res = match s {
+            "mean" => Function::Mean,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "sum" => Function::Sum,
+            "last" => Function::Last,
+            "count" => Function::Count,
+            _ => {
+                let p = s
+                    .strip_prefix('p')
+                    .and_then(|p| p.parse::<f64>().ok())
+                    .filter(|p| (0.0..=100.0).contains(p))
+                    .with_context(|| {
+                        format!("invalid aggregation function '{s}', expected mean, min, max, sum, last, count or pNN (percentile, e.g. p95)")
+                    })?;
+                Function::Percentile(p)
+            }
+        };
+        Ok(res)
+    }
+}
+
+impl TryFrom<String> for Function {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Function> for String {
+    fn from(value: Function) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Function::Mean => f.write_str("mean"),
+            Function::Min => f.write_str("min"),
+            Function::Max => f.write_str("max"),
+            Function::Sum => f.write_str("sum"),
+            Function::Last => f.write_str("last"),
+            Function::Count => f.write_str("count"),
+            Function::Percentile(p) => write!(f, "p{p}"),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{Function, LatePolicy};
+use crate::series::{derived_point, value_as_f64, SeriesKey};
+
+/// Windows aligned on the Unix epoch: the window `k` covers `[k*slide, k*slide + length)`.
+///
+/// Times are expressed in nanoseconds since the Unix epoch.
+pub struct Windows {
+    length: u128,
+    slide: u128,
+    allowed_lateness: u128,
+}
+
+impl Windows {
+    pub fn new(length: Duration, slide: Duration, allowed_lateness: Duration) -> Self {
+        Self {
+            length: length.as_nanos(),
+            slide: slide.as_nanos(),
+            allowed_lateness: allowed_lateness.as_nanos(),
+        }
+    }
+
+    /// Returns the range of the windows that contain the time `t`, as window indices.
+    fn containing(&self, t: u128) -> std::ops::RangeInclusive<u128> {
+        let last = t / self.slide;
+        let first = if t < self.length {
+            0
+        } else {
+            (t - self.length) / self.slide + 1
+        };
+        first..=last
+    }
+
+    fn start(&self, k: u128) -> u128 {
+        k * self.slide
+    }
+
+    fn end(&self, k: u128) -> u128 {
+        k * self.slide + self.length
+    }
+
+    /// Returns the index of the first window that must stay open, given the latest time seen in the series.
+    ///
+    /// The windows before it can be closed: they ended (more than `allowed_lateness`) before `latest`.
+    fn first_open(&self, latest: u128) -> u128 {
+        match latest.checked_sub(self.length + self.allowed_lateness) {
+            Some(t) => t / self.slide + 1,
+            None => 0,
+        }
+    }
+}
+
+/// Aggregation rule, with the ids of the metrics.
+pub struct Rule {
+    pub windows: Windows,
+    pub functions: Vec<Function>,
+    /// Input metric -> output metric of each function (same order as `functions`).
+    pub outputs: FxHashMap<RawMetricId, Vec<RawMetricId>>,
+    pub late_points: LatePolicy,
+    pub keep_input: bool,
+}
+
+/// Aggregates the values of the points of the selected metrics, per series, over time windows.
+///
+/// A window is closed, and its aggregates are emitted, when a point of the same series arrives
+/// after the end of the window (plus the allowed lateness).
+/// The timestamp of the aggregates is the end of the window.
+/// If a series stops, its last window is never closed.
+pub struct AggregationTransform {
+    rules: Vec<Rule>,
+    /// Open windows of each series, for each rule.
+    series: FxHashMap<(usize, SeriesKey), SeriesWindows>,
+}
+
+struct SeriesWindows {
+    /// A point of the series, used to create the aggregated points.
+    template: MeasurementPoint,
+    /// Latest time seen in the series.
+    latest: u128,
+    /// Windows that are closed have an index strictly lower than this.
+    first_open: u128,
+    /// Window index -> (time, value) of the points in the window
+    open: BTreeMap<u128, Vec<(u128, f64)>>,
+}
+
+impl AggregationTransform {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            series: FxHashMap::default(),
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let input = std::mem::take(measurements);
+        let mut updated = FxHashSet::default();
+        for point in input {
+            if point.is_stale_marker() {
+                measurements.push(point);
+                continue;
+            }
+            let mut keep = true;
+            for (r, rule) in self.rules.iter().enumerate() {
+                if !rule.outputs.contains_key(&point.metric) {
+                    continue;
+                }
+                let t = time_nanos(point.timestamp);
+                let key = (r, SeriesKey::of(&point));
+                let state = self.series.entry(key.clone()).or_insert_with(|| SeriesWindows {
+                    template: point.clone(),
+                    latest: t,
+                    first_open: rule.windows.first_open(t),
+                    open: BTreeMap::new(),
+                });
+
+                let value = value_as_f64(&point.value);
+                let mut added = false;
+                for k in rule.windows.containing(t) {
+                    if k >= state.first_open {
+                        state.open.entry(k).or_default().push((t, value));
+                        added = true;
+                    }
+                }
+                state.latest = state.latest.max(t);
+                updated.insert(key);
+
+                keep &= rule.keep_input || (!added && rule.late_points == LatePolicy::Forward);
+                if !added {
+                    log::debug!(
+                        "Late point for metric {:?}, its windows have already been closed.",
+                        point.metric
+                    );
+                }
+            }
+            if keep {
+                measurements.push(point);
+            }
+        }
+
+        // Close the windows that have ended, in order.
+        for key in updated {
+            let rule = &self.rules[key.0];
+            let state = self.series.get_mut(&key).unwrap();
+            state.first_open = state.first_open.max(rule.windows.first_open(state.latest));
+            let still_open = state.open.split_off(&state.first_open);
+            let closed = std::mem::replace(&mut state.open, still_open);
+            for (k, points) in closed {
+                emit_window(rule, state, k, points, measurements);
+            }
+        }
+    }
+}
+
+/// Pushes the aggregates of the window `k` to the buffer.
+fn emit_window(
+    rule: &Rule,
+    state: &SeriesWindows,
+    k: u128,
+    mut points: Vec<(u128, f64)>,
+    measurements: &mut MeasurementBuffer,
+) {
+    let outputs = &rule.outputs[&state.template.metric];
+    let end = SystemTime::UNIX_EPOCH + Duration::from_nanos(rule.windows.end(k) as u64);
+    log::trace!(
+        "Closing window [{}, {}) of metric {:?}",
+        rule.windows.start(k),
+        rule.windows.end(k),
+        state.template.metric
+    );
+
+    let mut values: Vec<f64> = points.iter().map(|(_, v)| *v).collect();
+    values.sort_unstable_by(f64::total_cmp);
+    points.sort_by_key(|(t, _)| *t);
+
+    for (function, output) in rule.functions.iter().zip(outputs) {
+        let value = match function {
+            Function::Count => WrappedMeasurementValue::U64(values.len() as u64),
+            Function::Mean => WrappedMeasurementValue::F64(values.iter().sum::<f64>() / values.len() as f64),
+            Function::Min => WrappedMeasurementValue::F64(values[0]),
+            Function::Max => WrappedMeasurementValue::F64(values[values.len() - 1]),
+            Function::Sum => WrappedMeasurementValue::F64(values.iter().sum()),
+            Function::Last => WrappedMeasurementValue::F64(points[points.len() - 1].1),
+            Function::Percentile(p) => WrappedMeasurementValue::F64(percentile(&values, *p)),
+        };
+        let mut point = derived_point(&state.template, *output, value);
+        point.timestamp = Timestamp::from(end);
+        measurements.push(point);
+    }
+}
+
+/// Computes the percentile `p` (between 0 and 100) of sorted values, with linear interpolation.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    let fraction = rank - low as f64;
+    sorted[low] + (sorted[high] - sorted[low]) * fraction
+}
+
+fn time_nanos(t: Timestamp) -> u128 {
+    SystemTime::from(t)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+impl Transform for AggregationTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashMap;
+
+    use super::{AggregationTransform, Rule, Windows};
+    use crate::aggregation::{Function, LatePolicy};
+
+    fn input_metric() -> RawMetricId {
+        RawMetricId::from_u64(0)
+    }
+
+    fn point(t_ms: u64, value: f64) -> MeasurementPoint {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_millis(t_ms);
+        MeasurementPoint::new_untyped(
+            Timestamp::from(t),
+            input_metric(),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(value),
+        )
+    }
+
+    fn transform(
+        window_ms: u64,
+        slide_ms: u64,
+        functions: Vec<Function>,
+        late_points: LatePolicy,
+    ) -> AggregationTransform {
+        let outputs = (1..=functions.len() as u64).map(RawMetricId::from_u64).collect();
+        let mut rule_outputs = FxHashMap::default();
+        rule_outputs.insert(input_metric(), outputs);
+        AggregationTransform::new(vec![Rule {
+            windows: Windows::new(
+                Duration::from_millis(window_ms),
+                Duration::from_millis(slide_ms),
+                Duration::ZERO,
+            ),
+            functions,
+            outputs: rule_outputs,
+            late_points,
+            keep_input: false,
+        }])
+    }
+
+    fn run(t: &mut AggregationTransform, points: Vec<MeasurementPoint>) -> Vec<(u64, u64, WrappedMeasurementValue)> {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf);
+        buf.iter()
+            .map(|p| {
+                let t = SystemTime::from(p.timestamp)
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap();
+                (p.metric.as_u64(), t.as_millis() as u64, p.value.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tumbling_windows() {
+        use WrappedMeasurementValue::{F64, U64};
+        let functions = vec![
+            Function::Mean,
+            Function::Min,
+            Function::Max,
+            Function::Sum,
+            Function::Last,
+            Function::Count,
+            Function::Percentile(50.0),
+        ];
+        let mut t = transform(1000, 1000, functions, LatePolicy::Drop);
+        let res = run(&mut t, vec![point(100, 4.0), point(500, 1.0), point(900, 7.0)]);
+        assert_eq!(res, vec![]);
+
+        // the window [0, 1000) is closed by a point of the next window
+        let res = run(&mut t, vec![point(1200, 10.0)]);
+        assert_eq!(
+            res,
+            vec![
+                (1, 1000, F64(4.0)),
+                (2, 1000, F64(1.0)),
+                (3, 1000, F64(7.0)),
+                (4, 1000, F64(12.0)),
+                (5, 1000, F64(7.0)),
+                (6, 1000, U64(3)),
+                (7, 1000, F64(4.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sliding_windows() {
+        use WrappedMeasurementValue::F64;
+        let mut t = transform(2000, 1000, vec![Function::Sum], LatePolicy::Drop);
+        assert_eq!(run(&mut t, vec![point(500, 1.0), point(1500, 2.0)]), vec![]);
+        // [0, 2000) is closed
+        assert_eq!(run(&mut t, vec![point(2500, 3.0)]), vec![(1, 2000, F64(3.0))]);
+        // [1000, 3000) is closed
+        assert_eq!(run(&mut t, vec![point(3500, 4.0)]), vec![(1, 3000, F64(5.0))]);
+    }
+
+    #[test]
+    fn late_points() {
+        use WrappedMeasurementValue::F64;
+        let mut t = transform(1000, 1000, vec![Function::Sum], LatePolicy::Drop);
+        run(&mut t, vec![point(100, 1.0), point(1100, 1.0)]);
+        assert_eq!(run(&mut t, vec![point(900, 5.0)]), vec![]);
+
+        let mut t = transform(1000, 1000, vec![Function::Sum], LatePolicy::Forward);
+        run(&mut t, vec![point(100, 1.0), point(1100, 1.0)]);
+        assert_eq!(run(&mut t, vec![point(900, 5.0)]), vec![(0, 900, F64(5.0))]);
+
+        // a late point in a window that has not been closed yet is aggregated
+        assert_eq!(
+            run(&mut t, vec![point(1050, 2.0), point(2000, 0.0)]),
+            vec![(1, 2000, F64(3.0))]
+        );
+    }
+
+    #[test]
+    fn parse_functions() {
+        assert_eq!("mean".parse::<Function>().unwrap(), Function::Mean);
+        assert_eq!("p99.9".parse::<Function>().unwrap(), Function::Percentile(99.9));
+        assert!("p101".parse::<Function>().is_err());
+        assert!("median".parse::<Function>().is_err());
+        assert_eq!(Function::Percentile(95.0).to_string(), "p95");
+    }
+}
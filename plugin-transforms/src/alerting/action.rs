@@ -0,0 +1,172 @@
+//! Actions triggered by the alerts.
+
+use std::{
+    process::Command,
+    sync::mpsc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::transform::Notification;
+
+/// An action to run when an alert starts firing, or is resolved.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ActionConfig {
+    /// Writes a log message.
+    Log,
+    /// Sends a JSON description of the alert to a webhook, with an HTTP POST request.
+    Http { url: String },
+    /// Runs a command. The alert is described by environment variables `ALUMET_ALERT_*`.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// Runs the actions of the alerts in a dedicated thread, in order not to block the pipeline.
+pub struct ActionRunner {
+    /// Actions of each rule.
+    actions: Vec<Vec<ActionConfig>>,
+    http: Option<reqwest::blocking::Client>,
+}
+
+impl ActionRunner {
+    /// Starts the thread that runs the actions, and returns the channel to send the notifications to.
+    ///
+    /// The thread stops when the sender is dropped.
+    pub fn start(actions: Vec<Vec<ActionConfig>>) -> anyhow::Result<mpsc::Sender<Notification>> {
+        let (tx, rx) = mpsc::channel::<Notification>();
+        std::thread::Builder::new()
+            .name(String::from("alert-actions"))
+            .spawn(move || {
+                let mut runner = ActionRunner { actions, http: None };
+                for notification in rx {
+                    runner.run(&notification);
+                }
+            })
+            .context("failed to spawn the thread of the alert actions")?;
+        Ok(tx)
+    }
+
+    fn run(&mut self, notification: &Notification) {
+        for action in &self.actions[notification.rule_index] {
+            let res = match action {
+                ActionConfig::Log => {
+                    log_alert(notification);
+                    Ok(())
+                }
+                ActionConfig::Http { url } => {
+                    http_client(&mut self.http).and_then(|client| post_alert(client, url, notification))
+                }
+                ActionConfig::Command { program, args } => run_command(program, args, notification),
+            };
+            if let Err(e) = res {
+                log::error!("Action of alert {} failed: {e:#}", notification.rule);
+            }
+        }
+    }
+}
+
+/// Returns the HTTP client, which is created on first use.
+fn http_client(http: &mut Option<reqwest::blocking::Client>) -> anyhow::Result<&reqwest::blocking::Client> {
+    match http {
+        Some(client) => Ok(client),
+        None => {
+            let client = reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .context("failed to create the HTTP client")?;
+            Ok(http.insert(client))
+        }
+    }
+}
+
+fn log_alert(n: &Notification) {
+    if n.firing {
+        log::warn!(
+            "Alert {} is firing: {} = {} on {}/{}, consumer {}/{}",
+            n.rule,
+            n.metric,
+            n.value,
+            n.resource.kind(),
+            n.resource.id_display(),
+            n.consumer.kind(),
+            n.consumer.id_display()
+        );
+    } else {
+        log::info!(
+            "Alert {} is resolved: {} = {} on {}/{}, consumer {}/{}",
+            n.rule,
+            n.metric,
+            n.value,
+            n.resource.kind(),
+            n.resource.id_display(),
+            n.consumer.kind(),
+            n.consumer.id_display()
+        );
+    }
+}
+
+fn state_name(n: &Notification) -> &'static str {
+    if n.firing {
+        "firing"
+    } else {
+        "resolved"
+    }
+}
+
+fn post_alert(client: &reqwest::blocking::Client, url: &str, n: &Notification) -> anyhow::Result<()> {
+    let timestamp = n.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    let attributes: serde_json::Map<String, serde_json::Value> = n
+        .attributes
+        .iter()
+        .map(|(k, v)| (k.clone(), serde_json::Value::from(v.as_str())))
+        .collect();
+    let body = serde_json::json!({
+        "alert": n.rule,
+        "state": state_name(n),
+        "metric": n.metric,
+        "value": n.value,
+        "timestamp": timestamp,
+        "resource_kind": n.resource.kind(),
+        "resource_id": n.resource.id_string(),
+        "consumer_kind": n.consumer.kind(),
+        "consumer_id": n.consumer.id_string(),
+        "attributes": attributes,
+    });
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .and_then(|res| res.error_for_status())
+        .with_context(|| format!("HTTP request to {url} failed"))?;
+    Ok(())
+}
+
+fn run_command(program: &str, args: &[String], n: &Notification) -> anyhow::Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .env("ALUMET_ALERT_NAME", &n.rule)
+        .env("ALUMET_ALERT_STATE", state_name(n))
+        .env("ALUMET_ALERT_METRIC", &n.metric)
+        .env("ALUMET_ALERT_VALUE", n.value.to_string())
+        .env(
+            "ALUMET_ALERT_RESOURCE",
+            format!("{}/{}", n.resource.kind(), n.resource.id_display()),
+        )
+        .env(
+            "ALUMET_ALERT_CONSUMER",
+            format!("{}/{}", n.consumer.kind(), n.consumer.id_display()),
+        )
+        .status()
+        .with_context(|| format!("failed to run {program}"))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{program} exited with {status}"));
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Raises alerts when measurements cross thresholds, and runs actions.
+
+use std::time::Duration;
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use action::{ActionConfig, ActionRunner};
+use transform::{AlertRule, AlertingTransform};
+
+mod action;
+mod transform;
+
+pub struct AlertingPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for AlertingPlugin {
+    fn name() -> &'static str {
+        "alerting"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(AlertingPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut rules = Vec::new();
+        let mut actions = Vec::new();
+        for rule in &self.config.rules {
+            if rule.above.is_none() && rule.below.is_none() && rule.max_rate.is_none() {
+                return Err(anyhow!(
+                    "alert rule {} has no condition: set above, below or max_rate",
+                    rule.name
+                ));
+            }
+            let Some((metric, _)) = alumet.metrics().by_name(&rule.metric) else {
+                log::warn!("Metric {} not found, the alert {} is disabled.", rule.metric, rule.name);
+                continue;
+            };
+            rules.push(AlertRule {
+                name: rule.name.clone(),
+                metric,
+                metric_name: rule.metric.clone(),
+                above: rule.above,
+                below: rule.below,
+                max_rate: rule.max_rate,
+                hysteresis: rule.hysteresis,
+                duration: rule.duration,
+                repeat_interval: rule.repeat_interval,
+            });
+            actions.push(rule.actions.clone());
+        }
+
+        let state_metric = alumet
+            .create_metric_untyped(
+                &self.config.state_metric,
+                WrappedMeasurementType::U64,
+                Unit::Unity,
+                "state of the alerts: 0 = ok, 1 = pending, 2 = firing",
+            )
+            .with_context(|| format!("could not create metric {}", self.config.state_metric))?;
+        let actions = ActionRunner::start(actions)?;
+        let transform = AlertingTransform::new(rules, state_metric, actions);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Name of the metric that reports the state of the alerts.
+    state_metric: String,
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Name of the alert.
+    name: String,
+    /// Name of the metric to watch.
+    metric: String,
+    /// The alert fires when the value is above this threshold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    above: Option<f64>,
+    /// The alert fires when the value is below this threshold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    below: Option<f64>,
+    /// The alert fires when the absolute rate of change (per second) is above this threshold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_rate: Option<f64>,
+    /// Margin that the value must cross, in the other direction, for the alert to be resolved.
+    #[serde(default)]
+    hysteresis: f64,
+    /// How long the condition must hold before the alert fires.
+    #[serde(rename = "for", with = "humantime_serde", default)]
+    duration: Duration,
+    /// If set, the actions are run again at this interval while the alert is firing.
+    #[serde(with = "humantime_serde", default, skip_serializing_if = "Option::is_none")]
+    repeat_interval: Option<Duration>,
+    actions: Vec<ActionConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            state_metric: String::from("alert_state"),
+            rules: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    sync::mpsc,
+    time::{Duration, SystemTime},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+    resources::{Resource, ResourceConsumer},
+};
+use fxhash::FxHashMap;
+
+use crate::series::{derived_point, value_as_f64, SeriesKey};
+
+/// The series are forgotten when they have not been measured for this duration.
+const SERIES_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A rule that is evaluated on each point of a metric.
+pub struct AlertRule {
+    pub name: String,
+    pub metric: RawMetricId,
+    /// Name of the metric, for the notifications.
+    pub metric_name: String,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    /// Maximum absolute rate of change, per second.
+    pub max_rate: Option<f64>,
+    /// Margin that the value must cross, in the other direction, for a firing alert to be resolved.
+    pub hysteresis: f64,
+    /// How long the condition must hold before the alert fires.
+    pub duration: Duration,
+    /// If set, the actions are run again at this interval while the alert is firing.
+    pub repeat_interval: Option<Duration>,
+}
+
+/// State of an alert, for one series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AlertState {
+    Ok,
+    /// The condition holds since the given time, but not for long enough.
+    Pending {
+        since: SystemTime,
+    },
+    /// The alert fires. The actions were last run at `notified`.
+    Firing {
+        notified: SystemTime,
+    },
+}
+
+impl AlertState {
+    /// Value of the state metric.
+    fn code(&self) -> u64 {
+        match self {
+            AlertState::Ok => 0,
+            AlertState::Pending { .. } => 1,
+            AlertState::Firing { .. } => 2,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SeriesState {
+    state: Option<AlertState>,
+    /// Previous (timestamp, value), to compute the rate of change.
+    previous: Option<(SystemTime, f64)>,
+}
+
+/// Sent to the actions when an alert starts firing, or is resolved.
+pub struct Notification {
+    pub rule_index: usize,
+    pub rule: String,
+    /// `true` if the alert fires, `false` if it is resolved.
+    pub firing: bool,
+    pub metric: String,
+    pub value: f64,
+    pub timestamp: SystemTime,
+    pub resource: Resource,
+    pub consumer: ResourceConsumer,
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Evaluates alert rules on the measurements.
+///
+/// For each series of the rule's metric, the alert goes through the states ok, pending and firing.
+/// The actions are only run when the alert starts firing and when it is resolved, not on every point,
+/// unless a repeat interval is configured.
+/// The state of each alert is emitted as a point of the state metric: 0 = ok, 1 = pending, 2 = firing.
+pub struct AlertingTransform {
+    rules: Vec<AlertRule>,
+    /// Metric -> indices of the rules
+    rules_by_metric: FxHashMap<RawMetricId, Vec<usize>>,
+    state_metric: RawMetricId,
+    series: FxHashMap<(usize, SeriesKey), SeriesState>,
+    actions: mpsc::Sender<Notification>,
+}
+
+impl AlertingTransform {
+    pub fn new(rules: Vec<AlertRule>, state_metric: RawMetricId, actions: mpsc::Sender<Notification>) -> Self {
+        let mut rules_by_metric: FxHashMap<RawMetricId, Vec<usize>> = FxHashMap::default();
+        for (i, rule) in rules.iter().enumerate() {
+            rules_by_metric.entry(rule.metric).or_default().push(i);
+        }
+        Self {
+            rules,
+            rules_by_metric,
+            state_metric,
+            series: FxHashMap::default(),
+            actions,
+        }
+    }
+
+    /// Evaluates the rules, adds the state points to the buffer, and returns the notifications.
+    fn process(&mut self, measurements: &mut MeasurementBuffer) -> Vec<Notification> {
+        let mut states = Vec::new();
+        let mut notifications = Vec::new();
+        let mut latest = None;
+        for point in measurements.iter() {
+            let Some(rules) = self
+                .rules_by_metric
+                .get(&point.metric)
+                .filter(|_| !point.is_stale_marker())
+            else {
+                continue;
+            };
+            latest = latest.max(Some(SystemTime::from(point.timestamp)));
+            for &i in rules {
+                let rule = &self.rules[i];
+                let series = self.series.entry((i, SeriesKey::of(point))).or_default();
+                let (state, notify) = evaluate(rule, series, point);
+                let state_point = derived_point(point, self.state_metric, WrappedMeasurementValue::U64(state.code()))
+                    .with_attr("alert", rule.name.clone());
+                states.push(state_point);
+                if let Some(firing) = notify {
+                    notifications.push(Notification {
+                        rule_index: i,
+                        rule: rule.name.clone(),
+                        firing,
+                        metric: rule.metric_name.clone(),
+                        value: value_as_f64(&point.value),
+                        timestamp: SystemTime::from(point.timestamp),
+                        resource: point.resource.clone(),
+                        consumer: point.consumer.clone(),
+                        attributes: point.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect(),
+                    });
+                }
+            }
+        }
+        for point in states {
+            measurements.push(point);
+        }
+        if let Some(latest) = latest {
+            self.clean(latest);
+        }
+        notifications
+    }
+
+    /// Forgets the series that have not been measured for a while.
+    ///
+    /// The alerts of these series are not resolved, because their last state is unknown.
+    fn clean(&mut self, now: SystemTime) {
+        self.series.retain(|_, series| {
+            series
+                .previous
+                .is_some_and(|(t, _)| now.duration_since(t).unwrap_or_default() <= SERIES_TIMEOUT)
+        });
+    }
+}
+
+/// Updates the state of the series with a new point, and returns the new state
+/// and whether the actions must be run (`Some(true)`: firing, `Some(false)`: resolved).
+fn evaluate(rule: &AlertRule, series: &mut SeriesState, point: &MeasurementPoint) -> (AlertState, Option<bool>) {
+    let time = SystemTime::from(point.timestamp);
+    let value = value_as_f64(&point.value);
+    let rate = match series.previous.replace((time, value)) {
+        Some((t, v)) => match time.duration_since(t) {
+            Ok(elapsed) if !elapsed.is_zero() => Some((value - v).abs() / elapsed.as_secs_f64()),
+            _ => None,
+        },
+        None => None,
+    };
+
+    let previous_state = series.state.unwrap_or(AlertState::Ok);
+    let firing = matches!(previous_state, AlertState::Firing { .. });
+    // When the alert fires, the thresholds are moved by the hysteresis, so that it does not flap.
+    let margin = if firing { rule.hysteresis } else { 0.0 };
+    let violated = rule.above.is_some_and(|max| value > max - margin)
+        || rule.below.is_some_and(|min| value < min + margin)
+        || rule.max_rate.is_some_and(|max| rate.is_some_and(|r| r > max - margin));
+
+    let (state, notify) = match (previous_state, violated) {
+        (AlertState::Firing { .. }, false) => (AlertState::Ok, Some(false)),
+        (AlertState::Firing { notified }, true) => match rule.repeat_interval {
+            Some(repeat) if time.duration_since(notified).is_ok_and(|d| d >= repeat) => {
+                (AlertState::Firing { notified: time }, Some(true))
+            }
+            _ => (previous_state, None),
+        },
+        (_, false) => (AlertState::Ok, None),
+        (AlertState::Ok, true) if !rule.duration.is_zero() => (AlertState::Pending { since: time }, None),
+        (AlertState::Pending { since }, true) if time.duration_since(since).unwrap_or_default() < rule.duration => {
+            (previous_state, None)
+        }
+        (_, true) => (AlertState::Firing { notified: time }, Some(true)),
+    };
+    series.state = Some(state);
+    (state, notify)
+}
+
+impl Transform for AlertingTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        for notification in self.process(measurements) {
+            if self.actions.send(notification).is_err() {
+                log::error!("The thread of the alert actions has stopped, the actions cannot run.");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc,
+        time::{Duration, SystemTime},
+    };
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{AlertRule, AlertingTransform};
+
+    fn point(secs: u64, value: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(value),
+        )
+    }
+
+    fn rule() -> AlertRule {
+        AlertRule {
+            name: String::from("power_budget"),
+            metric: RawMetricId::from_u64(0),
+            metric_name: String::from("power"),
+            above: Some(100.0),
+            below: None,
+            max_rate: None,
+            hysteresis: 10.0,
+            duration: Duration::ZERO,
+            repeat_interval: None,
+        }
+    }
+
+    /// Runs the transform on the points, and returns the states and the notifications.
+    fn run(t: &mut AlertingTransform, points: Vec<MeasurementPoint>) -> (Vec<u64>, Vec<bool>) {
+        let mut buf = MeasurementBuffer::from(points);
+        let notifications = t.process(&mut buf);
+        let states = buf
+            .iter()
+            .filter(|p| p.metric == RawMetricId::from_u64(1))
+            .map(|p| match p.value {
+                WrappedMeasurementValue::U64(s) => s,
+                WrappedMeasurementValue::F64(_) => panic!("the state should be an integer"),
+            })
+            .collect();
+        (states, notifications.iter().map(|n| n.firing).collect())
+    }
+
+    #[test]
+    fn threshold_with_hysteresis() {
+        let (tx, _rx) = mpsc::channel();
+        let mut t = AlertingTransform::new(vec![rule()], RawMetricId::from_u64(1), tx);
+        let points = vec![
+            point(1, 50.0),
+            point(2, 120.0),
+            point(3, 130.0),
+            point(4, 95.0),
+            point(5, 85.0),
+            point(6, 110.0),
+        ];
+        let (states, notifications) = run(&mut t, points);
+        // No duplicate notification while firing, and 95 is within the hysteresis.
+        assert_eq!(states, vec![0, 2, 2, 2, 0, 2]);
+        assert_eq!(notifications, vec![true, false, true]);
+    }
+
+    #[test]
+    fn duration_and_repeat() {
+        let (tx, _rx) = mpsc::channel();
+        let rule = AlertRule {
+            duration: Duration::from_secs(2),
+            repeat_interval: Some(Duration::from_secs(3)),
+            ..rule()
+        };
+        let mut t = AlertingTransform::new(vec![rule], RawMetricId::from_u64(1), tx);
+        let points = (1..=7).map(|s| point(s, 150.0)).collect();
+        let (states, notifications) = run(&mut t, points);
+        assert_eq!(states, vec![1, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(notifications, vec![true, true]);
+    }
+
+    #[test]
+    fn rate_of_change() {
+        let (tx, _rx) = mpsc::channel();
+        let rule = AlertRule {
+            above: None,
+            max_rate: Some(20.0),
+            hysteresis: 0.0,
+            ..rule()
+        };
+        let mut t = AlertingTransform::new(vec![rule], RawMetricId::from_u64(1), tx);
+        let points = vec![point(0, 10.0), point(2, 40.0), point(4, 100.0), point(6, 90.0)];
+        let (states, notifications) = run(&mut t, points);
+        assert_eq!(states, vec![0, 0, 2, 0]);
+        assert_eq!(notifications, vec![true, false]);
+    }
+
+    #[test]
+    fn forget_old_series() {
+        let (tx, _rx) = mpsc::channel();
+        let mut t = AlertingTransform::new(vec![rule()], RawMetricId::from_u64(1), tx);
+        let mut other = point(1, 150.0);
+        other.consumer = ResourceConsumer::Process { pid: 1 };
+        run(&mut t, vec![point(1, 150.0), other]);
+        assert_eq!(t.series.len(), 2);
+
+        // Only the first series is still measured.
+        let (states, notifications) = run(&mut t, vec![point(1000, 150.0)]);
+        assert_eq!(t.series.len(), 1);
+        assert_eq!(states, vec![2]);
+        assert!(notifications.is_empty());
+    }
+}
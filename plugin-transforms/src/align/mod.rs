@@ -0,0 +1,111 @@
+//! Resamples series onto a common time grid.
+
+use std::time::Duration;
+
+use alumet::plugin::{
+    rust::{deserialize_config, serialize_config, AlumetPlugin},
+    AlumetPluginStart, AlumetPreStart, ConfigTable,
+};
+use anyhow::anyhow;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use transform::{AlignSpec, AlignTransform};
+
+mod transform;
+
+pub struct TimeAlignmentPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for TimeAlignmentPlugin {
+    fn name() -> &'static str {
+        "time-alignment"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        if config.period.is_zero() {
+            return Err(anyhow!("the period of the time grid must not be zero"));
+        }
+        Ok(Box::new(TimeAlignmentPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut specs = FxHashMap::default();
+        for m in &self.config.metrics {
+            let Some((id, metric)) = alumet.metrics().by_name(&m.metric) else {
+                log::warn!("Metric {} not found, it will not be aligned.", m.metric);
+                continue;
+            };
+            let spec = AlignSpec {
+                method: m.method,
+                value_type: metric.value_type.clone(),
+            };
+            specs.insert(id, spec);
+        }
+        let transform = AlignTransform::new(specs, self.config.period, self.config.max_gap);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// How to compute the values on the grid.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    /// The value at a tick is the value of the previous point (for gauges).
+    Step,
+    /// The value at a tick is interpolated linearly between the surrounding points (for gauges).
+    Linear,
+    /// Each point is a delta since the previous point, such as an energy, and is split proportionally
+    /// across the grid intervals.
+    Delta,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Period of the time grid. The ticks are the multiples of the period since the Unix epoch.
+    #[serde(with = "humantime_serde")]
+    period: Duration,
+    /// Maximum time between two consecutive points of a series.
+    ///
+    /// If the time between two points is larger, nothing is interpolated between them.
+    #[serde(with = "humantime_serde")]
+    max_gap: Duration,
+    metrics: Vec<MetricConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetricConfig {
+    metric: String,
+    method: Method,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(1),
+            max_gap: Duration::from_secs(10),
+            metrics: Vec::new(),
+        }
+    }
+}
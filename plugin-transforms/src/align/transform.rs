@@ -0,0 +1,315 @@
+use std::time::{Duration, SystemTime};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+
+use super::Method;
+use crate::series::{derived_point, value_as_f64, SeriesKey};
+
+/// How to resample a metric.
+pub struct AlignSpec {
+    pub method: Method,
+    /// Type of the metric: the resampled values have the same type as the original ones.
+    pub value_type: WrappedMeasurementType,
+}
+
+/// State of a series.
+struct SeriesState {
+    /// Previous point, in nanoseconds since the Unix epoch.
+    last_time: u128,
+    last_value: WrappedMeasurementValue,
+    /// End of the current grid interval.
+    next_tick: u128,
+    /// Delta accumulated in the current grid interval.
+    accumulated: f64,
+    /// Whether the current grid interval is fully covered by the points of the series.
+    complete: bool,
+    /// Rounding error of the previous integer delta, carried over to the next one.
+    remainder: f64,
+}
+
+/// Resamples series onto a common time grid: the multiples of `period` since the Unix epoch.
+///
+/// Gauges (step or linear interpolation) are evaluated at each tick of the grid.
+/// Deltas, such as the energy consumed since the previous point, are split proportionally
+/// across the grid intervals, and each interval `[tick - period, tick)` is emitted at `tick`.
+///
+/// The resampled points replace the original points, with the same metric.
+pub struct AlignTransform {
+    specs: FxHashMap<RawMetricId, AlignSpec>,
+    /// Grid period, in nanoseconds.
+    period: u128,
+    /// If two consecutive points of a series are farther apart, nothing is interpolated between them.
+    max_gap: u128,
+    series: FxHashMap<SeriesKey, SeriesState>,
+}
+
+impl AlignTransform {
+    pub fn new(specs: FxHashMap<RawMetricId, AlignSpec>, period: Duration, max_gap: Duration) -> Self {
+        Self {
+            specs,
+            period: period.as_nanos(),
+            max_gap: max_gap.as_nanos(),
+            series: FxHashMap::default(),
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let input = std::mem::take(measurements);
+        let mut latest = None;
+        for point in input {
+            let Some(spec) = self.specs.get(&point.metric).filter(|_| !point.is_stale_marker()) else {
+                measurements.push(point);
+                continue;
+            };
+            let t = time_nanos(point.timestamp);
+            latest = latest.max(Some(t));
+            let period = self.period;
+            let first_tick = t.div_ceil(period) * period;
+            let new_state = || SeriesState {
+                last_time: t,
+                last_value: point.value.clone(),
+                // For deltas, the first point only gives the start of the next interval.
+                next_tick: if spec.method == Method::Delta && t == first_tick {
+                    t + period
+                } else {
+                    first_tick
+                },
+                accumulated: 0.0,
+                complete: t == first_tick,
+                remainder: 0.0,
+            };
+
+            let key = SeriesKey::of(&point);
+            let Some(state) = self.series.get_mut(&key) else {
+                let state = new_state();
+                if spec.method != Method::Delta && t == first_tick {
+                    measurements.push(point);
+                }
+                self.series.insert(key, state);
+                continue;
+            };
+            if t <= state.last_time || t - state.last_time > self.max_gap {
+                log::debug!(
+                    "Gap or restart in series of metric {:?}, the alignment is restarted.",
+                    point.metric
+                );
+                let state = new_state();
+                if spec.method != Method::Delta && t == first_tick {
+                    measurements.push(point);
+                }
+                self.series.insert(key, state);
+                continue;
+            }
+
+            let (t0, v0) = (state.last_time, value_as_f64(&state.last_value));
+            let v1 = value_as_f64(&point.value);
+            match spec.method {
+                Method::Step | Method::Linear => {
+                    while state.next_tick <= t {
+                        let tick = state.next_tick;
+                        let value = if tick == t {
+                            point.value.clone()
+                        } else if spec.method == Method::Step {
+                            state.last_value.clone()
+                        } else {
+                            let x = v0 + (v1 - v0) * (tick - t0) as f64 / (t - t0) as f64;
+                            convert(x, &spec.value_type, &mut state.remainder, false)
+                        };
+                        measurements.push(at_tick(&point, tick, value));
+                        state.next_tick += period;
+                    }
+                }
+                Method::Delta => {
+                    // share of the delta between two times
+                    let share = |from: u128, to: u128| v1 * (to - from) as f64 / (t - t0) as f64;
+                    let mut cursor = t0;
+                    while state.next_tick <= t {
+                        let tick = state.next_tick;
+                        state.accumulated += share(cursor, tick);
+                        if state.complete {
+                            let value = convert(state.accumulated, &spec.value_type, &mut state.remainder, true);
+                            measurements.push(at_tick(&point, tick, value));
+                        }
+                        state.accumulated = 0.0;
+                        state.complete = true;
+                        cursor = tick;
+                        state.next_tick += period;
+                    }
+                    state.accumulated += share(cursor, t);
+                }
+            }
+            state.last_time = t;
+            state.last_value = point.value;
+        }
+        if let Some(latest) = latest {
+            self.clean(latest);
+        }
+    }
+
+    /// Forgets the series whose last point is older than the maximum gap:
+    /// their alignment would be restarted anyway.
+    fn clean(&mut self, now: u128) {
+        let max_gap = self.max_gap;
+        self.series
+            .retain(|_, state| now.saturating_sub(state.last_time) <= max_gap);
+    }
+}
+
+/// Creates a point at the given tick, with the series of `point`.
+fn at_tick(point: &MeasurementPoint, tick: u128, value: WrappedMeasurementValue) -> MeasurementPoint {
+    let mut p = derived_point(point, point.metric, value);
+    p.timestamp = Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_nanos(tick as u64));
+    p
+}
+
+/// Converts a float into a value of the given type.
+///
+/// For integer deltas, the rounding error is carried over to the next value, so that the sum is preserved.
+fn convert(x: f64, value_type: &WrappedMeasurementType, remainder: &mut f64, carry: bool) -> WrappedMeasurementValue {
+    match value_type {
+        WrappedMeasurementType::F64 => WrappedMeasurementValue::F64(x),
+        WrappedMeasurementType::U64 if carry => {
+            let total = (x + *remainder).max(0.0);
+            let rounded = total.round();
+            *remainder = total - rounded;
+            WrappedMeasurementValue::U64(rounded as u64)
+        }
+        WrappedMeasurementType::U64 => WrappedMeasurementValue::U64(x.max(0.0).round() as u64),
+    }
+}
+
+fn time_nanos(t: Timestamp) -> u128 {
+    SystemTime::from(t)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+impl Transform for AlignTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{
+            MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+            STALE_MARKER_ATTRIBUTE,
+        },
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashMap;
+
+    use super::{AlignSpec, AlignTransform};
+    use crate::align::Method;
+
+    fn point(t_ms: u64, value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_millis(t_ms)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            value,
+        )
+    }
+
+    fn transform(method: Method, value_type: WrappedMeasurementType) -> AlignTransform {
+        let specs = FxHashMap::from_iter([(RawMetricId::from_u64(0), AlignSpec { method, value_type })]);
+        AlignTransform::new(specs, Duration::from_secs(1), Duration::from_secs(5))
+    }
+
+    fn run(t: &mut AlignTransform, points: Vec<(u64, WrappedMeasurementValue)>) -> Vec<(u64, WrappedMeasurementValue)> {
+        let mut buf: MeasurementBuffer = points.into_iter().map(|(t, v)| point(t, v)).collect();
+        t.process(&mut buf);
+        buf.iter()
+            .map(|p| {
+                let t = SystemTime::from(p.timestamp)
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap();
+                (t.as_millis() as u64, p.value.clone())
+            })
+            .collect()
+    }
+
+    use WrappedMeasurementValue::{F64, U64};
+
+    #[test]
+    fn linear_and_step() {
+        let points = vec![(500, F64(0.0)), (1500, F64(10.0)), (3500, F64(50.0))];
+        let mut t = transform(Method::Linear, WrappedMeasurementType::F64);
+        assert_eq!(
+            run(&mut t, points.clone()),
+            vec![(1000, F64(5.0)), (2000, F64(20.0)), (3000, F64(40.0))]
+        );
+        let mut t = transform(Method::Step, WrappedMeasurementType::F64);
+        assert_eq!(
+            run(&mut t, points),
+            vec![(1000, F64(0.0)), (2000, F64(10.0)), (3000, F64(10.0))]
+        );
+    }
+
+    #[test]
+    fn split_deltas() {
+        // 10 Hz source: each point is the energy consumed in the last 100 ms.
+        let mut t = transform(Method::Delta, WrappedMeasurementType::F64);
+        let points = (0..=25).map(|i| (i * 100, F64(1.0))).collect();
+        // The first point starts the series, then each second is complete.
+        assert_eq!(run(&mut t, points), vec![(1000, F64(10.0)), (2000, F64(10.0))]);
+
+        // A point that spans two intervals is split proportionally.
+        let mut t = transform(Method::Delta, WrappedMeasurementType::F64);
+        let res = run(&mut t, vec![(0, F64(0.0)), (500, F64(5.0)), (2500, F64(40.0))]);
+        assert_eq!(res, vec![(1000, F64(15.0)), (2000, F64(20.0))]);
+    }
+
+    #[test]
+    fn integer_deltas_preserve_sum() {
+        let mut t = transform(Method::Delta, WrappedMeasurementType::U64);
+        let res = run(&mut t, vec![(0, U64(0)), (3000, U64(10))]);
+        assert_eq!(res, vec![(1000, U64(3)), (2000, U64(4)), (3000, U64(3))]);
+    }
+
+    #[test]
+    fn gap_restarts_series() {
+        let mut t = transform(Method::Linear, WrappedMeasurementType::F64);
+        assert_eq!(run(&mut t, vec![(500, F64(0.0)), (10_500, F64(10.0))]), vec![]);
+        assert_eq!(run(&mut t, vec![(11_500, F64(20.0))]), vec![(11_000, F64(15.0))]);
+    }
+
+    #[test]
+    fn forget_old_series() {
+        let mut t = transform(Method::Step, WrappedMeasurementType::F64);
+        let mut other = point(0, F64(1.0));
+        other.consumer = ResourceConsumer::Process { pid: 1 };
+        let mut buf = MeasurementBuffer::from(vec![point(0, F64(1.0)), other]);
+        t.process(&mut buf);
+        assert_eq!(t.series.len(), 2);
+
+        // Only the first series is still measured, the other one is older than the maximum gap.
+        run(&mut t, vec![(4000, F64(1.0)), (8000, F64(1.0))]);
+        assert_eq!(t.series.len(), 1);
+    }
+
+    #[test]
+    fn forward_stale_markers() {
+        let mut t = transform(Method::Delta, WrappedMeasurementType::F64);
+        let marker = point(1500, F64(f64::NAN)).with_attr(STALE_MARKER_ATTRIBUTE, true);
+        let mut buf = MeasurementBuffer::from(vec![point(0, F64(0.0)), marker, point(1000, F64(4.0))]);
+        t.process(&mut buf);
+        let markers: Vec<_> = buf.iter().filter(|p| p.is_stale_marker()).collect();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(run(&mut t, vec![(2000, F64(6.0))]), vec![(2000, F64(6.0))]);
+    }
+}
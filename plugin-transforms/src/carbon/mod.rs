@@ -0,0 +1,153 @@
+//! Converts energy measurements into greenhouse gas emissions.
+
+use std::{path::PathBuf, time::Duration};
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::{anyhow, Context};
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use table::{IntensitySource, Interpolation};
+use transform::{CarbonTransform, EmissionSpec};
+
+mod table;
+mod transform;
+
+pub struct CarbonIntensityPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for CarbonIntensityPlugin {
+    fn name() -> &'static str {
+        "carbon-intensity"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(CarbonIntensityPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let source = match (self.config.intensity, &self.config.table) {
+            (Some(intensity), None) => IntensitySource::Constant(intensity),
+            (None, Some(path)) => {
+                IntensitySource::table(path.clone(), self.config.interpolation, self.config.check_interval)?
+            }
+            _ => return Err(anyhow!("exactly one of intensity and table must be set")),
+        };
+
+        let mut specs = FxHashMap::default();
+        for energy in &self.config.metrics {
+            let Some((input_id, input)) = alumet.metrics().by_name(&energy.metric) else {
+                log::warn!(
+                    "Metric {} not found, its emissions will not be computed.",
+                    energy.metric
+                );
+                continue;
+            };
+            let to_joules = input
+                .unit
+                .factor(&Unit::Joule)
+                .with_context(|| format!("metric {} is not an energy, its unit is {}", energy.metric, input.unit))?;
+            let output_name = energy
+                .output
+                .clone()
+                .unwrap_or_else(|| format!("{}_emissions", energy.metric));
+            let description = format!("greenhouse gas emissions of {}", energy.metric);
+            let output = alumet
+                .create_metric_untyped(&output_name, WrappedMeasurementType::F64, gram_co2eq(), &description)
+                .with_context(|| format!("could not create metric {output_name}"))?;
+            let spec = EmissionSpec {
+                output,
+                to_joules,
+                keep_input: energy.keep_input,
+            };
+            specs.insert(input_id, spec);
+        }
+        let transform = CarbonTransform::new(specs, source);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Grams of CO2-equivalent.
+fn gram_co2eq() -> Unit {
+    Unit::Custom {
+        unique_name: String::from("g{CO2eq}"),
+        display_name: String::from("gCO2eq"),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Constant carbon intensity, in gCO2eq/kWh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    intensity: Option<f64>,
+    /// CSV or JSON file that contains the carbon intensity over time, in gCO2eq/kWh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    table: Option<PathBuf>,
+    /// How to compute the intensity between two entries of the table.
+    #[serde(default = "default_interpolation")]
+    interpolation: Interpolation,
+    /// How often to check whether the table has changed.
+    #[serde(with = "humantime_serde", default = "default_check_interval")]
+    check_interval: Duration,
+    metrics: Vec<EnergyConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnergyConfig {
+    /// Name of the energy metric.
+    metric: String,
+    /// Name of the emissions metric to create. Defaults to `{metric}_emissions`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// Keep the energy measurements in addition to the emissions.
+    #[serde(default)]
+    keep_input: bool,
+}
+
+fn default_interpolation() -> Interpolation {
+    Interpolation::Linear
+}
+
+fn default_check_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            intensity: Some(50.0),
+            table: None,
+            interpolation: default_interpolation(),
+            check_interval: default_check_interval(),
+            metrics: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Time-indexed tables of carbon intensity.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+/// How to compute the intensity between two entries of the table.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Linear interpolation between the two entries.
+    Linear,
+    /// Value of the previous entry.
+    Previous,
+}
+
+/// Carbon intensity, in gCO2eq/kWh, sorted by time.
+#[derive(Debug, Default, PartialEq)]
+pub struct IntensityTable {
+    entries: Vec<(SystemTime, f64)>,
+}
+
+impl IntensityTable {
+    pub fn new(mut entries: Vec<(SystemTime, f64)>) -> Self {
+        entries.sort_by_key(|(t, _)| *t);
+        Self { entries }
+    }
+
+    /// Loads a table from a JSON file (extension `.json`) or from a CSV file (any other extension).
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let table = if is_json {
+            Self::parse_json(&content)
+        } else {
+            Self::parse_csv(&content)
+        };
+        let table = table.with_context(|| format!("invalid intensity table {}", path.display()))?;
+        if table.entries.is_empty() {
+            return Err(anyhow!("the intensity table {} is empty", path.display()));
+        }
+        Ok(table)
+    }
+
+    /// Parses a CSV table with two columns: timestamp and intensity.
+    ///
+    /// The separator is `,` or `;`. A header line is allowed, and lines that start with `#` are ignored.
+    pub fn parse_csv(content: &str) -> anyhow::Result<Self> {
+        let mut entries = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split([',', ';']).map(str::trim);
+            let (Some(timestamp), Some(intensity), None) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(anyhow!("line {}: expected 2 columns", i + 1));
+            };
+            let entry = parse_timestamp(timestamp).and_then(|t| {
+                let intensity = intensity
+                    .parse()
+                    .with_context(|| format!("invalid intensity '{intensity}'"))?;
+                Ok((t, intensity))
+            });
+            match entry {
+                Ok(entry) => entries.push(entry),
+                // The first line can be a header, such as "timestamp,intensity".
+                Err(_) if i == 0 && timestamp.parse::<f64>().is_err() && intensity.parse::<f64>().is_err() => continue,
+                Err(e) => return Err(e.context(format!("line {}", i + 1))),
+            }
+        }
+        Ok(Self::new(entries))
+    }
+
+    /// Parses a JSON table: an array of objects `{"timestamp": ..., "intensity": ...}`.
+    pub fn parse_json(content: &str) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum JsonTimestamp {
+            Unix(f64),
+            Text(String),
+        }
+
+        #[derive(Deserialize)]
+        struct JsonEntry {
+            timestamp: JsonTimestamp,
+            intensity: f64,
+        }
+
+        let json: Vec<JsonEntry> = serde_json::from_str(content)?;
+        let entries = json
+            .into_iter()
+            .map(|e| {
+                let t = match e.timestamp {
+                    JsonTimestamp::Unix(secs) => unix_time(secs)?,
+                    JsonTimestamp::Text(text) => parse_timestamp(&text)?,
+                };
+                Ok((t, e.intensity))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::new(entries))
+    }
+
+    /// Returns the intensity at time `t`, or `None` if the table is empty.
+    ///
+    /// Before the first entry, the intensity is the one of the first entry.
+    /// After the last entry, the intensity is the one of the last entry.
+    pub fn at(&self, t: SystemTime, interpolation: Interpolation) -> Option<f64> {
+        let next = self.entries.partition_point(|(time, _)| *time <= t);
+        if next == 0 {
+            return self.entries.first().map(|(_, v)| *v);
+        }
+        let (t0, v0) = self.entries[next - 1];
+        let Some(&(t1, v1)) = self.entries.get(next) else {
+            return Some(v0);
+        };
+        match interpolation {
+            Interpolation::Previous => Some(v0),
+            Interpolation::Linear => {
+                let span = t1.duration_since(t0).unwrap_or_default().as_secs_f64();
+                let elapsed = t.duration_since(t0).unwrap_or_default().as_secs_f64();
+                Some(v0 + (v1 - v0) * elapsed / span)
+            }
+        }
+    }
+}
+
+/// Parses a timestamp: a number of seconds since the Unix epoch, or an RFC 3339 date in UTC.
+fn parse_timestamp(s: &str) -> anyhow::Result<SystemTime> {
+    match s.parse::<f64>() {
+        Ok(secs) => unix_time(secs),
+        Err(_) => humantime::parse_rfc3339_weak(s).with_context(|| format!("invalid timestamp '{s}'")),
+    }
+}
+
+fn unix_time(secs: f64) -> anyhow::Result<SystemTime> {
+    let d = Duration::try_from_secs_f64(secs).with_context(|| format!("invalid timestamp {secs}"))?;
+    Ok(UNIX_EPOCH + d)
+}
+
+/// Where the intensity comes from.
+pub enum IntensitySource {
+    Constant(f64),
+    /// A table loaded from a file, reloaded when the file changes.
+    Table {
+        path: PathBuf,
+        table: IntensityTable,
+        interpolation: Interpolation,
+        /// Modification time of the file, when it was loaded.
+        modified: Option<SystemTime>,
+        check_interval: Duration,
+        last_check: Instant,
+    },
+}
+
+impl IntensitySource {
+    pub fn table(path: PathBuf, interpolation: Interpolation, check_interval: Duration) -> anyhow::Result<Self> {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let table = IntensityTable::load(&path)?;
+        Ok(Self::Table {
+            path,
+            table,
+            interpolation,
+            modified,
+            check_interval,
+            last_check: Instant::now(),
+        })
+    }
+
+    /// Reloads the table if its file has changed since the last check.
+    ///
+    /// If the new table is invalid, an error is logged and the previous table is kept.
+    pub fn refresh(&mut self) {
+        let IntensitySource::Table {
+            path,
+            table,
+            modified,
+            check_interval,
+            last_check,
+            ..
+        } = self
+        else {
+            return;
+        };
+        if last_check.elapsed() < *check_interval {
+            return;
+        }
+        *last_check = Instant::now();
+        let new_modified = fs::metadata(&*path).and_then(|m| m.modified()).ok();
+        if new_modified == *modified {
+            return;
+        }
+        *modified = new_modified;
+        match IntensityTable::load(path) {
+            Ok(new_table) => {
+                log::info!("Intensity table {} reloaded.", path.display());
+                *table = new_table;
+            }
+            Err(e) => log::error!("Failed to reload the intensity table, the previous one is kept: {e:#}"),
+        }
+    }
+
+    /// Returns the intensity at time `t`, in gCO2eq/kWh.
+    pub fn at(&self, t: SystemTime) -> Option<f64> {
+        match self {
+            IntensitySource::Constant(intensity) => Some(*intensity),
+            IntensitySource::Table {
+                table, interpolation, ..
+            } => table.at(t, *interpolation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{IntensitySource, IntensityTable, Interpolation};
+
+    #[test]
+    fn parse_tables() {
+        let csv = "timestamp,intensity\n# comment\n1970-01-01T00:01:40Z, 50\n0;100\n";
+        let json = r#"[{"timestamp": 0, "intensity": 100}, {"timestamp": "1970-01-01T00:01:40Z", "intensity": 50}]"#;
+        let expected = IntensityTable::new(vec![(UNIX_EPOCH, 100.0), (UNIX_EPOCH + Duration::from_secs(100), 50.0)]);
+        assert_eq!(IntensityTable::parse_csv(csv).unwrap(), expected);
+        assert_eq!(IntensityTable::parse_json(json).unwrap(), expected);
+        assert!(IntensityTable::parse_csv("0,100\nyesterday,50").is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        let table = IntensityTable::new(vec![
+            (UNIX_EPOCH + Duration::from_secs(100), 100.0),
+            (UNIX_EPOCH + Duration::from_secs(200), 200.0),
+        ]);
+        let at = |secs, interpolation| table.at(UNIX_EPOCH + Duration::from_secs(secs), interpolation);
+        assert_eq!(at(0, Interpolation::Linear), Some(100.0));
+        assert_eq!(at(150, Interpolation::Linear), Some(150.0));
+        assert_eq!(at(150, Interpolation::Previous), Some(100.0));
+        assert_eq!(at(200, Interpolation::Linear), Some(200.0));
+        assert_eq!(at(300, Interpolation::Linear), Some(200.0));
+        assert_eq!(IntensityTable::default().at(UNIX_EPOCH, Interpolation::Linear), None);
+    }
+
+    #[test]
+    fn reload_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("intensity.csv");
+        fs::write(&path, "0,100\n").unwrap();
+        let mut source = IntensitySource::table(path.clone(), Interpolation::Linear, Duration::ZERO).unwrap();
+        assert_eq!(source.at(UNIX_EPOCH), Some(100.0));
+
+        // Make sure that the modification time changes.
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(&path, "0,42\n").unwrap();
+        source.refresh();
+        assert_eq!(source.at(UNIX_EPOCH), Some(42.0));
+
+        // An invalid table is ignored.
+        for invalid in ["0,oops\n", "timestamp,intensity\n"] {
+            std::thread::sleep(Duration::from_millis(20));
+            fs::write(&path, invalid).unwrap();
+            source.refresh();
+            assert_eq!(source.at(UNIX_EPOCH), Some(42.0));
+        }
+    }
+}
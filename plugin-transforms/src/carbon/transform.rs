@@ -0,0 +1,111 @@
+use std::time::SystemTime;
+
+use alumet::{
+    measurement::{MeasurementBuffer, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+
+use super::table::IntensitySource;
+use crate::series::{derived_point, value_as_f64};
+
+/// Joules in one kilowatt-hour.
+const JOULES_PER_KWH: f64 = 3.6e6;
+
+/// How to compute the emissions of an energy metric.
+pub struct EmissionSpec {
+    pub output: RawMetricId,
+    /// Factor to apply to the energy to get joules.
+    pub to_joules: f64,
+    pub keep_input: bool,
+}
+
+/// Converts energy measurements into greenhouse gas emissions, in grams of CO2-equivalent.
+///
+/// The emissions of a point are its energy multiplied by the carbon intensity at the time of the point.
+pub struct CarbonTransform {
+    /// Energy metric -> spec
+    specs: FxHashMap<RawMetricId, EmissionSpec>,
+    source: IntensitySource,
+}
+
+impl CarbonTransform {
+    pub fn new(specs: FxHashMap<RawMetricId, EmissionSpec>, source: IntensitySource) -> Self {
+        Self { specs, source }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        self.source.refresh();
+        let input = std::mem::take(measurements);
+        for point in input {
+            let Some(spec) = self.specs.get(&point.metric).filter(|_| !point.is_stale_marker()) else {
+                measurements.push(point);
+                continue;
+            };
+            if let Some(intensity) = self.source.at(SystemTime::from(point.timestamp)) {
+                let kwh = value_as_f64(&point.value) * spec.to_joules / JOULES_PER_KWH;
+                let value = WrappedMeasurementValue::F64(kwh * intensity);
+                measurements.push(derived_point(&point, spec.output, value));
+            }
+            if spec.keep_input {
+                measurements.push(point);
+            }
+        }
+    }
+}
+
+impl Transform for CarbonTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashMap;
+
+    use super::{CarbonTransform, EmissionSpec};
+    use crate::carbon::table::IntensitySource;
+
+    #[test]
+    fn energy_to_emissions() {
+        let specs = FxHashMap::from_iter([(
+            RawMetricId::from_u64(0),
+            EmissionSpec {
+                output: RawMetricId::from_u64(1),
+                to_joules: 1e-3,
+                keep_input: true,
+            },
+        )]);
+        let mut t = CarbonTransform::new(specs, IntensitySource::Constant(50.0));
+        // 7.2e6 mJ = 2 Wh, and 2 Wh at 50 gCO2eq/kWh = 0.1 g
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1)),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: 0 },
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(7.2e6),
+        );
+        let mut buf = MeasurementBuffer::from(vec![point]);
+        t.process(&mut buf);
+
+        let points: Vec<_> = buf.iter().collect();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].metric, RawMetricId::from_u64(1));
+        let WrappedMeasurementValue::F64(grams) = points[0].value else {
+            panic!("emissions should be a float");
+        };
+        assert!((grams - 0.1).abs() < 1e-12, "wrong emissions: {grams}");
+        assert_eq!(points[1].metric, RawMetricId::from_u64(0));
+    }
+}
@@ -0,0 +1,147 @@
+//! Converts energy measurements into monetary cost.
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::Context;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use tariff::{Tariff, TariffConfig};
+use transform::{CostSpec, CostTransform};
+
+mod tariff;
+mod transform;
+
+pub struct EnergyCostPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for EnergyCostPlugin {
+    fn name() -> &'static str {
+        "energy-cost"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(EnergyCostPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let tariff = Tariff::try_from(&self.config.tariff)?;
+        let timezone = tariff::parse_timezone(&self.config.timezone)?;
+        let currency = Unit::Custom {
+            unique_name: self.config.currency.clone(),
+            display_name: self.config.currency.clone(),
+        };
+
+        let mut specs = FxHashMap::default();
+        for energy in &self.config.metrics {
+            let Some((input_id, input)) = alumet.metrics().by_name(&energy.metric) else {
+                log::warn!("Metric {} not found, its cost will not be computed.", energy.metric);
+                continue;
+            };
+            let to_joules = input
+                .unit
+                .factor(&Unit::Joule)
+                .with_context(|| format!("metric {} is not an energy, its unit is {}", energy.metric, input.unit))?;
+            let output_name = energy
+                .output
+                .clone()
+                .unwrap_or_else(|| format!("{}_cost", energy.metric));
+            let description = format!("cost of {}", energy.metric);
+            let output = alumet
+                .create_metric_untyped(
+                    &output_name,
+                    WrappedMeasurementType::F64,
+                    currency.clone(),
+                    &description,
+                )
+                .with_context(|| format!("could not create metric {output_name}"))?;
+            let cumulative = if energy.cumulative {
+                let name = format!("{output_name}_total");
+                let description = format!("cumulative cost of {} per consumer", energy.metric);
+                let id = alumet
+                    .create_metric_untyped(&name, WrappedMeasurementType::F64, currency.clone(), &description)
+                    .with_context(|| format!("could not create metric {name}"))?;
+                Some(id)
+            } else {
+                None
+            };
+            let spec = CostSpec {
+                output,
+                to_joules,
+                keep_input: energy.keep_input,
+                cumulative,
+            };
+            specs.insert(input_id, spec);
+        }
+        let transform = CostTransform::new(specs, tariff, timezone, self.config.group_by.clone());
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Currency of the prices, used as the unit of the cost metrics.
+    currency: String,
+    /// Timezone of the tariff, from the IANA database (for instance `Europe/Paris`).
+    timezone: String,
+    /// Price per kWh.
+    tariff: TariffConfig,
+    /// Attributes that identify a consumer, in addition to its kind and id, for the cumulative cost.
+    #[serde(default)]
+    group_by: Vec<String>,
+    metrics: Vec<EnergyConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnergyConfig {
+    /// Name of the energy metric.
+    metric: String,
+    /// Name of the cost metric to create. Defaults to `{metric}_cost`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// Also emit the cumulative cost per consumer, with the metric `{output}_total`.
+    #[serde(default)]
+    cumulative: bool,
+    /// Keep the energy measurements in addition to the cost.
+    #[serde(default)]
+    keep_input: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            currency: String::from("EUR"),
+            timezone: String::from("UTC"),
+            tariff: TariffConfig::Flat { price: 0.25 },
+            group_by: Vec::new(),
+            metrics: Vec::new(),
+        }
+    }
+}
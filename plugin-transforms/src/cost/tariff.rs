@@ -0,0 +1,179 @@
+//! Electricity tariffs.
+
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Price of the energy, per kWh, that can depend on the time of consumption.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TariffConfig {
+    /// The same price at any time.
+    Flat { price: f64 },
+    /// A peak price during the day, and an off-peak price the rest of the time.
+    TimeOfDay {
+        peak_price: f64,
+        off_peak_price: f64,
+        /// Start of the peak hours, such as `07:00`.
+        peak_start: String,
+        /// End of the peak hours, such as `22:00`. If it is before the start, the peak hours cross midnight.
+        peak_end: String,
+    },
+    /// A price on weekdays (Monday to Friday), and another one on weekends.
+    WeekdayWeekend { weekday_price: f64, weekend_price: f64 },
+}
+
+/// A tariff, ready to be used.
+#[derive(Debug)]
+pub enum Tariff {
+    Flat(f64),
+    TimeOfDay {
+        peak: f64,
+        off_peak: f64,
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+    WeekdayWeekend {
+        weekday: f64,
+        weekend: f64,
+    },
+}
+
+impl TryFrom<&TariffConfig> for Tariff {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &TariffConfig) -> Result<Self, Self::Error> {
+        let res = match config {
+            TariffConfig::Flat { price } => Tariff::Flat(*price),
+            TariffConfig::TimeOfDay {
+                peak_price,
+                off_peak_price,
+                peak_start,
+                peak_end,
+            } => Tariff::TimeOfDay {
+                peak: *peak_price,
+                off_peak: *off_peak_price,
+                start: parse_time(peak_start)?,
+                end: parse_time(peak_end)?,
+            },
+            TariffConfig::WeekdayWeekend {
+                weekday_price,
+                weekend_price,
+            } => Tariff::WeekdayWeekend {
+                weekday: *weekday_price,
+                weekend: *weekend_price,
+            },
+        };
+        Ok(res)
+    }
+}
+
+fn parse_time(s: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S"))
+        .with_context(|| format!("invalid time of day '{s}', expected HH:MM"))
+}
+
+/// Parses the name of a timezone of the IANA database, such as `Europe/Paris` or `UTC`.
+pub fn parse_timezone(name: &str) -> anyhow::Result<Tz> {
+    name.parse().map_err(|e| anyhow!("invalid timezone '{name}': {e}"))
+}
+
+impl Tariff {
+    /// Returns the price per kWh at time `t`, in the local time of `timezone`.
+    pub fn price(&self, t: SystemTime, timezone: Tz) -> f64 {
+        let local = DateTime::<Utc>::from(t).with_timezone(&timezone);
+        match self {
+            Tariff::Flat(price) => *price,
+            Tariff::TimeOfDay {
+                peak,
+                off_peak,
+                start,
+                end,
+            } => {
+                let time = local.time();
+                let is_peak = if start <= end {
+                    *start <= time && time < *end
+                } else {
+                    // the peak hours cross midnight
+                    *start <= time || time < *end
+                };
+                if is_peak {
+                    *peak
+                } else {
+                    *off_peak
+                }
+            }
+            Tariff::WeekdayWeekend { weekday, weekend } => match local.weekday() {
+                Weekday::Sat | Weekday::Sun => *weekend,
+                _ => *weekday,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use chrono::{DateTime, Utc};
+
+    use super::{parse_timezone, Tariff, TariffConfig};
+
+    fn time(rfc3339: &str) -> SystemTime {
+        SystemTime::from(rfc3339.parse::<DateTime<Utc>>().unwrap())
+    }
+
+    #[test]
+    fn time_of_day_in_timezone() {
+        let config = TariffConfig::TimeOfDay {
+            peak_price: 0.3,
+            off_peak_price: 0.1,
+            peak_start: String::from("07:00"),
+            peak_end: String::from("22:00"),
+        };
+        let tariff = Tariff::try_from(&config).unwrap();
+        let paris = parse_timezone("Europe/Paris").unwrap();
+        let utc = parse_timezone("UTC").unwrap();
+        // 06:30 UTC is 08:30 in Paris during summer time
+        assert_eq!(tariff.price(time("2025-07-01T06:30:00Z"), utc), 0.1);
+        assert_eq!(tariff.price(time("2025-07-01T06:30:00Z"), paris), 0.3);
+        // 21:30 UTC is 22:30 in Paris during winter time
+        assert_eq!(tariff.price(time("2025-01-15T21:30:00Z"), utc), 0.3);
+        assert_eq!(tariff.price(time("2025-01-15T21:30:00Z"), paris), 0.1);
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn peak_hours_across_midnight() {
+        let config = TariffConfig::TimeOfDay {
+            peak_price: 0.3,
+            off_peak_price: 0.1,
+            peak_start: String::from("18:00"),
+            peak_end: String::from("02:00"),
+        };
+        let tariff = Tariff::try_from(&config).unwrap();
+        let utc = parse_timezone("UTC").unwrap();
+        assert_eq!(tariff.price(time("2025-01-15T23:00:00Z"), utc), 0.3);
+        assert_eq!(tariff.price(time("2025-01-15T01:59:00Z"), utc), 0.3);
+        assert_eq!(tariff.price(time("2025-01-15T02:00:00Z"), utc), 0.1);
+    }
+
+    #[test]
+    fn weekday_weekend() {
+        let tariff = Tariff::WeekdayWeekend {
+            weekday: 0.2,
+            weekend: 0.15,
+        };
+        let tokyo = parse_timezone("Asia/Tokyo").unwrap();
+        // Friday 20:00 UTC is Saturday 05:00 in Tokyo
+        assert_eq!(tariff.price(time("2025-01-17T20:00:00Z"), tokyo), 0.15);
+        assert_eq!(
+            tariff.price(time("2025-01-17T20:00:00Z"), parse_timezone("UTC").unwrap()),
+            0.2
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::SystemTime;
+
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+    resources::{Resource, ResourceConsumer},
+};
+use chrono_tz::Tz;
+use fxhash::FxHashMap;
+
+use super::tariff::Tariff;
+use crate::series::{derived_point, value_as_f64};
+
+/// Joules in one kilowatt-hour.
+const JOULES_PER_KWH: f64 = 3.6e6;
+
+/// How to compute the cost of an energy metric.
+pub struct CostSpec {
+    pub output: RawMetricId,
+    /// Factor to apply to the energy to get joules.
+    pub to_joules: f64,
+    pub keep_input: bool,
+    /// If set, the cumulative cost per consumer is emitted with this metric.
+    pub cumulative: Option<RawMetricId>,
+}
+
+/// Identifies a cumulative cost: the consumer, and the values of the `group_by` attributes.
+#[derive(PartialEq, Eq, Hash)]
+struct CumulativeKey {
+    metric: RawMetricId,
+    consumer: ResourceConsumer,
+    attributes: Vec<(String, String)>,
+}
+
+/// Converts energy measurements into monetary cost.
+///
+/// The cost of a point is its energy multiplied by the price of the tariff at the time of the point.
+/// Optionally, the cost is accumulated per consumer since the start of the agent.
+pub struct CostTransform {
+    /// Energy metric -> spec
+    specs: FxHashMap<RawMetricId, CostSpec>,
+    tariff: Tariff,
+    timezone: Tz,
+    /// Attributes that identify a consumer in addition to the `ResourceConsumer`, for the cumulative cost.
+    group_by: Vec<String>,
+    totals: FxHashMap<CumulativeKey, f64>,
+}
+
+impl CostTransform {
+    pub fn new(specs: FxHashMap<RawMetricId, CostSpec>, tariff: Tariff, timezone: Tz, group_by: Vec<String>) -> Self {
+        Self {
+            specs,
+            tariff,
+            timezone,
+            group_by,
+            totals: FxHashMap::default(),
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let input = std::mem::take(measurements);
+        for point in input {
+            let Some(spec) = self.specs.get(&point.metric).filter(|_| !point.is_stale_marker()) else {
+                measurements.push(point);
+                continue;
+            };
+            let kwh = value_as_f64(&point.value) * spec.to_joules / JOULES_PER_KWH;
+            let cost = kwh * self.tariff.price(SystemTime::from(point.timestamp), self.timezone);
+            measurements.push(derived_point(&point, spec.output, WrappedMeasurementValue::F64(cost)));
+
+            if let Some(cumulative) = spec.cumulative {
+                let attributes: Vec<(String, AttributeValue)> = self
+                    .group_by
+                    .iter()
+                    .filter_map(|key| point.attributes().find(|(k, _)| k == key))
+                    .map(|(k, v)| (k.to_owned(), v.clone()))
+                    .collect();
+                let key = CumulativeKey {
+                    metric: cumulative,
+                    consumer: point.consumer.clone(),
+                    attributes: attributes.iter().map(|(k, v)| (k.clone(), v.to_string())).collect(),
+                };
+                let total = self.totals.entry(key).or_default();
+                *total += cost;
+                let total_point = MeasurementPoint::new_untyped(
+                    point.timestamp,
+                    cumulative,
+                    Resource::LocalMachine,
+                    point.consumer.clone(),
+                    WrappedMeasurementValue::F64(*total),
+                );
+                measurements.push(total_point.with_attr_vec(attributes));
+            }
+            if spec.keep_input {
+                measurements.push(point);
+            }
+        }
+    }
+}
+
+impl Transform for CostTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashMap;
+
+    use super::{CostSpec, CostTransform};
+    use crate::cost::tariff::{parse_timezone, Tariff};
+
+    fn point(socket: u32, pid: u32, kwh: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_secs(3600)),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: socket },
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::F64(kwh * 3.6e6),
+        )
+        .with_attr("team", if pid == 1 { "blue" } else { "red" })
+    }
+
+    #[test]
+    fn cost_per_series_and_consumer() {
+        let specs = FxHashMap::from_iter([(
+            RawMetricId::from_u64(0),
+            CostSpec {
+                output: RawMetricId::from_u64(1),
+                to_joules: 1.0,
+                keep_input: false,
+                cumulative: Some(RawMetricId::from_u64(2)),
+            },
+        )]);
+        let tariff = Tariff::Flat(0.25);
+        let mut t = CostTransform::new(
+            specs,
+            tariff,
+            parse_timezone("UTC").unwrap(),
+            vec![String::from("team")],
+        );
+        let mut buf = MeasurementBuffer::from(vec![point(0, 1, 2.0), point(1, 1, 4.0), point(0, 2, 1.0)]);
+        t.process(&mut buf);
+
+        let values = |metric: u64| -> Vec<(u32, f64)> {
+            buf.iter()
+                .filter(|p| p.metric == RawMetricId::from_u64(metric))
+                .map(|p| match (&p.consumer, &p.value) {
+                    (ResourceConsumer::Process { pid }, WrappedMeasurementValue::F64(v)) => (*pid, *v),
+                    _ => panic!("unexpected point {p:?}"),
+                })
+                .collect()
+        };
+        assert_eq!(values(1), vec![(1, 0.5), (1, 1.0), (2, 0.25)]);
+        assert_eq!(values(2), vec![(1, 0.5), (1, 1.5), (2, 0.25)]);
+        assert!(buf
+            .iter()
+            .filter(|p| p.metric == RawMetricId::from_u64(2))
+            .all(|p| p.attributes_keys().eq(["team"])));
+    }
+}
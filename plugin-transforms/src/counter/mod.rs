@@ -0,0 +1,134 @@
+//! Turns cumulative counters into per-interval deltas or per-second rates.
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+};
+use anyhow::Context;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+use transform::{CounterDiffTransform, CounterSpec};
+
+mod transform;
+
+pub struct CounterDiffPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for CounterDiffPlugin {
+    fn name() -> &'static str {
+        "counter-diff"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(CounterDiffPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        // The counters can be provided by any plugin: wait for all the plugins to be started
+        // before looking for them, and create the new metrics with the right unit.
+        let mut specs = FxHashMap::default();
+        for counter in &self.config.counters {
+            let Some((input_id, input)) = alumet.metrics().by_name(&counter.metric) else {
+                log::warn!(
+                    "Metric {} not found, its {} will not be computed.",
+                    counter.metric,
+                    counter.mode.as_str()
+                );
+                continue;
+            };
+            let (value_type, unit, description) = match counter.mode {
+                Mode::Delta => (
+                    input.value_type.clone(),
+                    input.unit.clone(),
+                    format!("difference between two consecutive values of {}", counter.metric),
+                ),
+                Mode::Rate => (
+                    WrappedMeasurementType::F64,
+                    units::per_second(&input.unit),
+                    format!("rate of change of {} (per second)", counter.metric),
+                ),
+            };
+            let output_name = counter
+                .output
+                .clone()
+                .unwrap_or_else(|| format!("{}_{}", counter.metric, counter.mode.as_str()));
+            let output = alumet
+                .create_metric_untyped(&output_name, value_type, unit, &description)
+                .with_context(|| format!("could not create metric {output_name}"))?;
+            let spec = CounterSpec {
+                output,
+                mode: counter.mode,
+                max_value: counter.max_value,
+                keep_input: counter.keep_input,
+            };
+            specs.insert(input_id, spec);
+        }
+        alumet.add_transform("transform", Box::new(CounterDiffTransform::new(specs)))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    counters: Vec<CounterConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CounterConfig {
+    /// Name of the counter metric.
+    metric: String,
+    /// What to compute.
+    mode: Mode,
+    /// Name of the metric to create. Defaults to `{metric}_{mode}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// Value at which the counter overflows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_value: Option<u64>,
+    /// Keep the measurements of the counter in addition to the new ones.
+    #[serde(default)]
+    keep_input: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Mode {
+    /// Difference between two consecutive values.
+    Delta,
+    /// Difference between two consecutive values, divided by the elapsed time in seconds.
+    Rate,
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Delta => "delta",
+            Mode::Rate => "rate",
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::time::{Duration, SystemTime};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+    plugin::util::{CounterDiff, CounterDiffUpdate},
+};
+use fxhash::FxHashMap;
+
+use super::Mode;
+use crate::series::{derived_point, value_as_f64, SeriesKey};
+
+/// The series are forgotten when they have not been measured for this duration.
+const SERIES_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// What to do with the points of a counter metric.
+pub struct CounterSpec {
+    /// Metric of the new points.
+    pub output: RawMetricId,
+    pub mode: Mode,
+    /// Value at which the counter overflows, if known.
+    pub max_value: Option<u64>,
+    pub keep_input: bool,
+}
+
+/// Computes the deltas or rates of the counters, per series.
+pub struct CounterDiffTransform {
+    /// Counter metric -> spec
+    specs: FxHashMap<RawMetricId, CounterSpec>,
+    /// State of each series of counter.
+    series: FxHashMap<SeriesKey, SeriesState>,
+}
+
+struct SeriesState {
+    /// Used for integer counters, to handle overflows.
+    counter: CounterDiff,
+    /// Previous value of float counters.
+    previous_f64: Option<f64>,
+    previous_time: SystemTime,
+}
+
+impl CounterDiffTransform {
+    pub fn new(specs: FxHashMap<RawMetricId, CounterSpec>) -> Self {
+        Self {
+            specs,
+            series: FxHashMap::default(),
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let input = std::mem::take(measurements);
+        let mut latest = None;
+        for point in input {
+            let Some(spec) = self.specs.get(&point.metric).filter(|_| !point.is_stale_marker()) else {
+                measurements.push(point);
+                continue;
+            };
+            latest = latest.max(Some(SystemTime::from(point.timestamp)));
+            if let Some(new_point) = Self::update(&mut self.series, spec, &point) {
+                measurements.push(new_point);
+            }
+            if spec.keep_input {
+                measurements.push(point);
+            }
+        }
+        if let Some(latest) = latest {
+            self.clean(latest);
+        }
+    }
+
+    /// Forgets the series that have not been measured for a long time, for instance
+    /// the processes that have exited.
+    fn clean(&mut self, now: SystemTime) {
+        self.series
+            .retain(|_, state| now.duration_since(state.previous_time).unwrap_or_default() <= SERIES_TIMEOUT);
+    }
+
+    /// Updates the state of the series of `point`, and returns the new point to emit (if any).
+    fn update(
+        series: &mut FxHashMap<SeriesKey, SeriesState>,
+        spec: &CounterSpec,
+        point: &MeasurementPoint,
+    ) -> Option<MeasurementPoint> {
+        let time = SystemTime::from(point.timestamp);
+        let max_value = spec.max_value.unwrap_or(u64::MAX);
+        let state = series.entry(SeriesKey::of(point)).or_insert_with(|| SeriesState {
+            counter: CounterDiff::with_max_value(max_value),
+            previous_f64: None,
+            previous_time: time,
+        });
+        let previous_time = std::mem::replace(&mut state.previous_time, time);
+
+        // Compute the difference with the previous value.
+        // A counter that decreases without a known maximum value has been reset (e.g. the source restarted):
+        // there is no meaningful difference, the new value is the new reference.
+        let diff = match point.value {
+            WrappedMeasurementValue::U64(v) if v > max_value => {
+                log::warn!("Counter value {v} is greater than the maximum value {max_value}, the counter is reset.");
+                state.counter = CounterDiff::with_max_value(max_value);
+                None
+            }
+            WrappedMeasurementValue::U64(v) => match state.counter.update(v) {
+                CounterDiffUpdate::FirstTime => None,
+                CounterDiffUpdate::Difference(d) => Some(WrappedMeasurementValue::U64(d)),
+                CounterDiffUpdate::CorrectedDifference(d) if spec.max_value.is_some() => {
+                    Some(WrappedMeasurementValue::U64(d))
+                }
+                CounterDiffUpdate::CorrectedDifference(_) => None,
+            },
+            WrappedMeasurementValue::F64(v) => {
+                let previous = state.previous_f64.replace(v);
+                match previous {
+                    Some(prev) if v >= prev => Some(WrappedMeasurementValue::F64(v - prev)),
+                    _ => None,
+                }
+            }
+        }?;
+
+        let value = match spec.mode {
+            Mode::Delta => diff,
+            Mode::Rate => {
+                // Use the real time elapsed between the two measurements, not the configured poll interval.
+                let elapsed = time.duration_since(previous_time).ok()?.as_secs_f64();
+                if elapsed <= 0.0 {
+                    return None;
+                }
+                WrappedMeasurementValue::F64(value_as_f64(&diff) / elapsed)
+            }
+        };
+        Some(derived_point(point, spec.output, value))
+    }
+}
+
+impl Transform for CounterDiffTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashMap;
+
+    use super::{CounterDiffTransform, CounterSpec, Mode};
+
+    fn counter() -> RawMetricId {
+        RawMetricId::from_u64(0)
+    }
+
+    fn output() -> RawMetricId {
+        RawMetricId::from_u64(1)
+    }
+
+    fn point(t_ms: u64, pid: u32, value: WrappedMeasurementValue) -> MeasurementPoint {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_millis(t_ms);
+        MeasurementPoint::new_untyped(
+            Timestamp::from(t),
+            counter(),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            value,
+        )
+    }
+
+    fn transform(mode: Mode, max_value: Option<u64>) -> CounterDiffTransform {
+        let mut specs = FxHashMap::default();
+        specs.insert(
+            counter(),
+            CounterSpec {
+                output: output(),
+                mode,
+                max_value,
+                keep_input: false,
+            },
+        );
+        CounterDiffTransform::new(specs)
+    }
+
+    fn run(t: &mut CounterDiffTransform, points: Vec<MeasurementPoint>) -> Vec<WrappedMeasurementValue> {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf);
+        buf.iter()
+            .inspect(|p| assert_eq!(p.metric, output()))
+            .map(|p| p.value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn delta_per_series() {
+        use WrappedMeasurementValue::U64;
+        let mut t = transform(Mode::Delta, None);
+        assert_eq!(run(&mut t, vec![point(0, 1, U64(10)), point(0, 2, U64(100))]), vec![]);
+        assert_eq!(
+            run(&mut t, vec![point(1000, 1, U64(15)), point(1000, 2, U64(130))]),
+            vec![U64(5), U64(30)]
+        );
+        // reset of the counter: no delta, the next one is computed from the new value
+        assert_eq!(run(&mut t, vec![point(2000, 1, U64(3))]), vec![]);
+        assert_eq!(run(&mut t, vec![point(3000, 1, U64(4))]), vec![U64(1)]);
+    }
+
+    #[test]
+    fn delta_with_overflow() {
+        use WrappedMeasurementValue::U64;
+        let mut t = transform(Mode::Delta, Some(100));
+        assert_eq!(run(&mut t, vec![point(0, 1, U64(90))]), vec![]);
+        assert_eq!(run(&mut t, vec![point(1000, 1, U64(5))]), vec![U64(15)]);
+    }
+
+    #[test]
+    fn rate_uses_elapsed_time() {
+        use WrappedMeasurementValue::{F64, U64};
+        let mut t = transform(Mode::Rate, None);
+        assert_eq!(run(&mut t, vec![point(0, 1, U64(0))]), vec![]);
+        assert_eq!(run(&mut t, vec![point(500, 1, U64(10))]), vec![F64(20.0)]);
+        assert_eq!(run(&mut t, vec![point(2500, 1, U64(30))]), vec![F64(10.0)]);
+
+        let mut t = transform(Mode::Rate, None);
+        assert_eq!(run(&mut t, vec![point(0, 1, F64(1.0))]), vec![]);
+        assert_eq!(run(&mut t, vec![point(250, 1, F64(2.0))]), vec![F64(4.0)]);
+    }
+
+    #[test]
+    fn forget_old_series() {
+        use WrappedMeasurementValue::U64;
+        let mut t = transform(Mode::Delta, None);
+        run(&mut t, vec![point(0, 1, U64(10)), point(0, 2, U64(100))]);
+        assert_eq!(t.series.len(), 2);
+
+        // The second process has exited, only the first one is still measured.
+        assert_eq!(run(&mut t, vec![point(1000, 1, U64(15))]), vec![U64(5)]);
+        assert_eq!(t.series.len(), 2);
+        assert_eq!(run(&mut t, vec![point(700_000, 1, U64(20))]), vec![U64(5)]);
+        assert_eq!(t.series.len(), 1);
+    }
+}
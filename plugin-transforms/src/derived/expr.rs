@@ -0,0 +1,180 @@
+//! Arithmetic expressions over metrics.
+
+use std::{iter::Peekable, str::CharIndices};
+
+use anyhow::{anyhow, Context};
+
+/// An arithmetic expression, whose variables are metrics.
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// The value of an operand, identified by its index in the list of operands.
+    Operand(usize),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Expr {
+    /// Parses an expression, and returns it with the names of its operands.
+    ///
+    /// The grammar is the usual one: `+`, `-`, `*`, `/`, parentheses, numbers and metric names.
+    /// A metric name starts with a letter or `_`, and contains letters, digits, `_` and `.`.
+    pub fn parse(input: &str) -> anyhow::Result<(Expr, Vec<String>)> {
+        let mut parser = Parser {
+            input,
+            chars: input.char_indices().peekable(),
+            operands: Vec::new(),
+        };
+        let expr = parser
+            .parse_sum()
+            .and_then(|expr| match parser.peek() {
+                None => Ok(expr),
+                Some(c) => Err(anyhow!("unexpected character '{c}'")),
+            })
+            .with_context(|| format!("invalid expression '{input}'"))?;
+        Ok((expr, parser.operands))
+    }
+
+    /// Evaluates the expression with the given values of the operands.
+    pub fn eval(&self, operands: &[f64]) -> f64 {
+        match self {
+            Expr::Number(x) => *x,
+            Expr::Operand(i) => operands[*i],
+            Expr::Neg(e) => -e.eval(operands),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(operands), b.eval(operands));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    operands: Vec<String>,
+}
+
+impl Parser<'_> {
+    /// Returns the next non-whitespace character, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn parse_sum(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.parse_product()?;
+        loop {
+            let op = match self.peek() {
+                Some('+') => Op::Add,
+                Some('-') => Op::Sub,
+                _ => return Ok(expr),
+            };
+            self.chars.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_product()?));
+        }
+    }
+
+    fn parse_product(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some('*') => Op::Mul,
+                Some('/') => Op::Div,
+                _ => return Ok(expr),
+            };
+            self.chars.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> anyhow::Result<Expr> {
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(Expr::Neg(Box::new(self.parse_unary()?)))
+            }
+            Some('(') => {
+                self.chars.next();
+                let expr = self.parse_sum()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(expr)
+                    }
+                    _ => Err(anyhow!("missing closing parenthesis")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let token = self.take_while(|c| c.is_ascii_alphanumeric() || c == '.');
+                let x = token.parse().with_context(|| format!("invalid number '{token}'"))?;
+                Ok(Expr::Number(x))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self
+                    .take_while(|c| c.is_alphanumeric() || c == '_' || c == '.')
+                    .to_owned();
+                let index = match self.operands.iter().position(|op| *op == name) {
+                    Some(i) => i,
+                    None => {
+                        self.operands.push(name);
+                        self.operands.len() - 1
+                    }
+                };
+                Ok(Expr::Operand(index))
+            }
+            Some(c) => Err(anyhow!("unexpected character '{c}'")),
+            None => Err(anyhow!("unexpected end of expression")),
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.input.len());
+        while self.chars.next_if(|(_, c)| f(*c)).is_some() {}
+        let end = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.input.len());
+        &self.input[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, Op};
+
+    #[test]
+    fn parse_and_eval() {
+        let (expr, operands) = Expr::parse("rapl_pkg - rapl_dram").unwrap();
+        assert_eq!(operands, vec!["rapl_pkg", "rapl_dram"]);
+        assert_eq!(
+            expr,
+            Expr::Binary(Op::Sub, Box::new(Expr::Operand(0)), Box::new(Expr::Operand(1)))
+        );
+        assert_eq!(expr.eval(&[10.0, 4.0]), 6.0);
+
+        let (expr, operands) = Expr::parse("100 * (a - b) / -a + 0.5").unwrap();
+        assert_eq!(operands, vec!["a", "b"]);
+        assert_eq!(expr.eval(&[4.0, 2.0]), -49.5);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("a +").is_err());
+        assert!(Expr::parse("(a + b").is_err());
+        assert!(Expr::parse("a b").is_err());
+        assert!(Expr::parse("1.2.3").is_err());
+        assert!(Expr::parse("a % b").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Computes new metrics from arithmetic expressions over other metrics.
+
+use std::time::Duration;
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+use expr::Expr;
+use transform::{DerivedSpec, DerivedTransform, JoinOn};
+
+mod expr;
+mod transform;
+
+pub struct DerivedMetricsPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for DerivedMetricsPlugin {
+    fn name() -> &'static str {
+        "derived-metrics"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(DerivedMetricsPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut specs = Vec::new();
+        'metrics: for derived in &self.config.metrics {
+            let (expr, operand_names) = Expr::parse(&derived.expression)?;
+            if operand_names.is_empty() {
+                return Err(anyhow!(
+                    "the expression of metric {} does not use any metric",
+                    derived.name
+                ));
+            }
+            let mut operands = Vec::with_capacity(operand_names.len());
+            for name in &operand_names {
+                let Some((id, _)) = alumet.metrics().by_name(name) else {
+                    log::warn!(
+                        "Metric {name} not found, the metric {} will not be computed.",
+                        derived.name
+                    );
+                    continue 'metrics;
+                };
+                operands.push(id);
+            }
+            let unit = units::parse_unit(&derived.unit);
+            let description = derived
+                .description
+                .clone()
+                .unwrap_or_else(|| derived.expression.clone());
+            let output = alumet
+                .create_metric_untyped(&derived.name, WrappedMeasurementType::F64, unit, &description)
+                .with_context(|| format!("could not create metric {}", derived.name))?;
+            let join_on = match &derived.join_on {
+                None => JoinOn::Series,
+                Some(dimensions) => JoinOn::Dimensions(dimensions.clone()),
+            };
+            specs.push(DerivedSpec {
+                output,
+                expr,
+                operands,
+                join_on,
+                tolerance: derived.tolerance,
+            });
+        }
+        let transform = DerivedTransform::new(specs, self.config.max_delay);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// How long a point waits for the other operands of an expression.
+    #[serde(with = "humantime_serde")]
+    max_delay: Duration,
+    metrics: Vec<DerivedConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DerivedConfig {
+    /// Name of the metric to create.
+    name: String,
+    /// Arithmetic expression over other metrics.
+    expression: String,
+    /// Unit of the new metric, for instance `W`, `mJ` or `1`.
+    unit: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Maximum difference between the timestamps of the operands.
+    #[serde(with = "humantime_serde", default = "default_tolerance")]
+    tolerance: Duration,
+    /// Dimensions that the operands must share: `resource`, `consumer`, or attribute keys.
+    /// Defaults to the whole series: resource, consumer and all the attributes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    join_on: Option<Vec<String>>,
+}
+
+fn default_tolerance() -> Duration {
+    Duration::from_millis(500)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_delay: Duration::from_secs(10),
+            metrics: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+
+use super::expr::Expr;
+use crate::series::{derived_point, value_as_f64};
+
+/// How to compute a derived metric.
+pub struct DerivedSpec {
+    pub output: RawMetricId,
+    pub expr: Expr,
+    /// The metrics used in the expression. The first one is the primary operand:
+    /// the derived points have its timestamp, resource, consumer and attributes.
+    pub operands: Vec<RawMetricId>,
+    pub join_on: JoinOn,
+    /// Maximum difference between the timestamps of the operands.
+    pub tolerance: Duration,
+}
+
+/// The dimensions that must be equal for two points to be joined.
+pub enum JoinOn {
+    /// Resource, consumer and all the attributes.
+    Series,
+    /// Some dimensions: `resource`, `consumer`, or the key of an attribute.
+    Dimensions(Vec<String>),
+}
+
+impl JoinOn {
+    fn key(&self, point: &MeasurementPoint) -> Vec<String> {
+        let attribute = |key: &str| {
+            point
+                .attributes()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+                .unwrap_or_default()
+        };
+        match self {
+            JoinOn::Series => {
+                let mut key = vec![
+                    format!("{}:{}", point.resource.kind(), point.resource.id_display()),
+                    format!("{}:{}", point.consumer.kind(), point.consumer.id_display()),
+                ];
+                let mut attributes: Vec<String> = point.attributes().map(|(k, v)| format!("{k}={v}")).collect();
+                attributes.sort_unstable();
+                key.extend(attributes);
+                key
+            }
+            JoinOn::Dimensions(dimensions) => dimensions
+                .iter()
+                .map(|d| match d.as_str() {
+                    "resource" => format!("{}:{}", point.resource.kind(), point.resource.id_display()),
+                    "consumer" => format!("{}:{}", point.consumer.kind(), point.consumer.id_display()),
+                    key => attribute(key),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The recent points of the operands, for one join key.
+struct JoinState {
+    /// Points of the primary operand that wait for the other operands.
+    pending: VecDeque<MeasurementPoint>,
+    /// Recent (timestamp, value) of each operand. The first one, the primary operand, is not used.
+    history: Vec<VecDeque<(SystemTime, f64)>>,
+    /// Most recent timestamp seen for this key.
+    latest: SystemTime,
+}
+
+/// A derived metric, with the state of its joins.
+struct Derived {
+    spec: DerivedSpec,
+    states: FxHashMap<Vec<String>, JoinState>,
+}
+
+/// Computes new metrics from arithmetic expressions over other metrics.
+///
+/// The operands of an expression are joined by timestamp: a point of the first operand (the primary one)
+/// is combined with the points of the other operands that have the same join key, and whose timestamp
+/// is within the tolerance. The closest points are chosen. When an operand is missing, no point is emitted.
+///
+/// The operands can come from different sources, and therefore from different buffers.
+/// The points of the primary operand wait at most `max_delay` for the other operands.
+pub struct DerivedTransform {
+    derived: Vec<Derived>,
+    /// Metric -> (index of the derived metric, index of the operand)
+    uses: FxHashMap<RawMetricId, Vec<(usize, usize)>>,
+    max_delay: Duration,
+}
+
+impl DerivedTransform {
+    pub fn new(specs: Vec<DerivedSpec>, max_delay: Duration) -> Self {
+        let mut uses: FxHashMap<RawMetricId, Vec<(usize, usize)>> = FxHashMap::default();
+        for (d, spec) in specs.iter().enumerate() {
+            for (i, metric) in spec.operands.iter().enumerate() {
+                uses.entry(*metric).or_default().push((d, i));
+            }
+        }
+        let derived = specs
+            .into_iter()
+            .map(|spec| Derived {
+                spec,
+                states: FxHashMap::default(),
+            })
+            .collect();
+        Self {
+            derived,
+            uses,
+            max_delay,
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let mut output = Vec::new();
+        let mut latest = None;
+        for point in measurements.iter() {
+            let Some(uses) = self.uses.get(&point.metric).filter(|_| !point.is_stale_marker()) else {
+                continue;
+            };
+            latest = latest.max(Some(SystemTime::from(point.timestamp)));
+            for (d, i) in uses {
+                self.derived[*d].push(point, *i, self.max_delay, &mut output);
+            }
+        }
+        if let Some(latest) = latest {
+            for derived in &mut self.derived {
+                derived.clean(latest, self.max_delay);
+            }
+        }
+        for point in output {
+            measurements.push(point);
+        }
+    }
+}
+
+impl Derived {
+    /// Adds a point of the operand `operand`, and emits the derived points that can be computed.
+    fn push(
+        &mut self,
+        point: &MeasurementPoint,
+        operand: usize,
+        max_delay: Duration,
+        output: &mut Vec<MeasurementPoint>,
+    ) {
+        let spec = &self.spec;
+        let time = SystemTime::from(point.timestamp);
+        let key = spec.join_on.key(point);
+        let state = self.states.entry(key.clone()).or_insert_with(|| JoinState {
+            pending: VecDeque::new(),
+            history: vec![VecDeque::new(); spec.operands.len()],
+            latest: time,
+        });
+        state.latest = state.latest.max(time);
+        if operand == 0 {
+            state.pending.push_back(point.clone());
+        } else {
+            state.history[operand].push_back((time, value_as_f64(&point.value)));
+        }
+
+        // Try to compute the pending points, and forget the ones that waited too long.
+        let mut values = vec![0.0; spec.operands.len()];
+        let latest = state.latest;
+        let history = &state.history;
+        state.pending.retain(|primary| {
+            let t = SystemTime::from(primary.timestamp);
+            values[0] = value_as_f64(&primary.value);
+            let complete = (1..values.len()).all(|i| match closest(&history[i], t, spec.tolerance) {
+                Some(v) => {
+                    values[i] = v;
+                    true
+                }
+                None => false,
+            });
+            if complete {
+                let value = spec.expr.eval(&values);
+                if value.is_finite() {
+                    output.push(derived_point(primary, spec.output, WrappedMeasurementValue::F64(value)));
+                }
+                false
+            } else {
+                latest.duration_since(t).unwrap_or_default() <= max_delay
+            }
+        });
+
+        // Forget the values that cannot be joined anymore.
+        let horizon = latest.checked_sub(max_delay + spec.tolerance);
+        if let Some(horizon) = horizon {
+            for values in &mut state.history {
+                while values.front().is_some_and(|(t, _)| *t < horizon) {
+                    values.pop_front();
+                }
+            }
+        }
+        if state.pending.is_empty() && state.history.iter().all(VecDeque::is_empty) {
+            self.states.remove(&key);
+        }
+    }
+
+    /// Forgets the join keys that have not been seen for a while: their points cannot be joined anymore.
+    fn clean(&mut self, now: SystemTime, max_delay: Duration) {
+        let timeout = max_delay + self.spec.tolerance;
+        self.states
+            .retain(|_, state| now.duration_since(state.latest).unwrap_or_default() <= timeout);
+    }
+}
+
+/// Returns the value whose timestamp is the closest to `t`, if the difference is within the tolerance.
+fn closest(values: &VecDeque<(SystemTime, f64)>, t: SystemTime, tolerance: Duration) -> Option<f64> {
+    let distance = |time: &SystemTime| time.duration_since(t).unwrap_or_else(|e| e.duration());
+    values
+        .iter()
+        .map(|(time, value)| (distance(time), *value))
+        .filter(|(d, _)| *d <= tolerance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, value)| value)
+}
+
+impl Transform for DerivedTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{DerivedSpec, DerivedTransform, JoinOn};
+    use crate::derived::expr::Expr;
+
+    fn metric(id: u64) -> RawMetricId {
+        RawMetricId::from_u64(id)
+    }
+
+    fn point(metric_id: u64, millis: u64, consumer: u32, value: f64) -> MeasurementPoint {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+        MeasurementPoint::new_untyped(
+            Timestamp::from(t),
+            metric(metric_id),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid: consumer },
+            WrappedMeasurementValue::F64(value),
+        )
+    }
+
+    fn ratio(join_on: JoinOn) -> DerivedTransform {
+        let (expr, _) = Expr::parse("a / b").unwrap();
+        let spec = DerivedSpec {
+            output: metric(10),
+            expr,
+            operands: vec![metric(0), metric(1)],
+            join_on,
+            tolerance: Duration::from_millis(100),
+        };
+        DerivedTransform::new(vec![spec], Duration::from_secs(1))
+    }
+
+    fn derived_values(buf: &MeasurementBuffer) -> Vec<(u32, f64)> {
+        buf.iter()
+            .filter(|p| p.metric == metric(10))
+            .map(|p| {
+                let ResourceConsumer::Process { pid } = p.consumer else {
+                    panic!("unexpected consumer {:?}", p.consumer)
+                };
+                let WrappedMeasurementValue::F64(v) = p.value else {
+                    panic!("unexpected value {:?}", p.value)
+                };
+                (pid, v)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn join_by_series() {
+        let mut t = ratio(JoinOn::Series);
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, 1000, 1, 6.0),
+            point(0, 1000, 2, 8.0),
+            point(1, 1050, 1, 3.0),
+            // too far from the primary point
+            point(1, 1200, 2, 4.0),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(derived_values(&buf), vec![(1, 2.0)]);
+        assert_eq!(buf.len(), 5);
+
+        // The operand arrives in a later buffer.
+        let mut buf = MeasurementBuffer::from(vec![point(1, 980, 2, 2.0)]);
+        t.process(&mut buf);
+        assert_eq!(derived_values(&buf), vec![(2, 4.0)]);
+    }
+
+    #[test]
+    fn join_on_dimensions() {
+        // The denominator is measured for the whole machine, and joined with every consumer.
+        let mut t = ratio(JoinOn::Dimensions(Vec::new()));
+        let mut buf = MeasurementBuffer::from(vec![
+            point(0, 1000, 1, 6.0),
+            point(0, 1000, 2, 9.0),
+            point(1, 1000, 0, 3.0),
+        ]);
+        t.process(&mut buf);
+        assert_eq!(derived_values(&buf), vec![(1, 2.0), (2, 3.0)]);
+    }
+
+    #[test]
+    fn missing_operand_expires() {
+        let mut t = ratio(JoinOn::Series);
+        let mut buf = MeasurementBuffer::from(vec![point(0, 1000, 1, 6.0), point(0, 3000, 1, 6.0)]);
+        t.process(&mut buf);
+        assert!(derived_values(&buf).is_empty());
+
+        // The first point has expired, the second one is joined.
+        let mut buf = MeasurementBuffer::from(vec![point(1, 1000, 1, 2.0), point(1, 3000, 1, 3.0)]);
+        t.process(&mut buf);
+        assert_eq!(derived_values(&buf), vec![(1, 2.0)]);
+
+        // Division by zero: no point.
+        let mut buf = MeasurementBuffer::from(vec![point(0, 4000, 1, 1.0), point(1, 4000, 1, 0.0)]);
+        t.process(&mut buf);
+        assert!(derived_values(&buf).is_empty());
+    }
+
+    #[test]
+    fn forget_idle_keys() {
+        let mut t = ratio(JoinOn::Series);
+        let mut buf = MeasurementBuffer::from(vec![point(0, 1000, 1, 6.0), point(1, 1000, 2, 3.0)]);
+        t.process(&mut buf);
+        assert_eq!(t.derived[0].states.len(), 2);
+
+        // The key of the consumer 2 is too old to be joined.
+        let mut buf = MeasurementBuffer::from(vec![point(0, 3000, 1, 6.0)]);
+        t.process(&mut buf);
+        assert_eq!(t.derived[0].states.len(), 1);
+
+        // Nothing remains to be joined.
+        let mut buf = MeasurementBuffer::from(vec![point(1, 3000, 1, 2.0)]);
+        t.process(&mut buf);
+        assert_eq!(derived_values(&buf), vec![(1, 3.0)]);
+        t.derived[0].clean(SystemTime::UNIX_EPOCH + Duration::from_secs(10), t.max_delay);
+        assert!(t.derived[0].states.is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+//! Attaches attributes to the measurements: static values, environment variables, files and host facts.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use alumet::{
+    measurement::AttributeValue,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, ConfigTable,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{host, pattern};
+use transform::EnrichmentTransform;
+
+mod transform;
+
+pub struct EnrichmentPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for EnrichmentPlugin {
+    fn name() -> &'static str {
+        "enrichment"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(EnrichmentPlugin { config }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        // The values are read once, at startup.
+        let attributes = self.config.resolve_attributes();
+        log::debug!("Attributes added to the measurements: {attributes:?}");
+
+        let metrics = self
+            .config
+            .metrics
+            .iter()
+            .map(|m| pattern::glob(m))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let transform = EnrichmentTransform::new(attributes, metrics);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Glob patterns on the names of the metrics to enrich. If empty, all the points are enriched.
+    #[serde(default)]
+    metrics: Vec<String>,
+    /// Attribute -> value
+    #[serde(default, rename = "static")]
+    static_values: BTreeMap<String, StaticValue>,
+    /// Attribute -> name of the environment variable to read
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Attribute -> path of the file to read
+    #[serde(default)]
+    files: BTreeMap<String, PathBuf>,
+    /// Facts about the host to add as attributes.
+    #[serde(default)]
+    host_facts: Vec<HostFact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StaticValue {
+    Bool(bool),
+    U64(u64),
+    F64(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum HostFact {
+    Hostname,
+    KernelVersion,
+    CpuModel,
+}
+
+impl Config {
+    /// Reads the values of the attributes.
+    ///
+    /// Missing environment variables, files and host facts are reported, but do not prevent the other attributes from being added.
+    fn resolve_attributes(&self) -> Vec<(String, AttributeValue)> {
+        let mut attributes = Vec::new();
+        for (key, value) in &self.static_values {
+            let value = match value {
+                StaticValue::Bool(b) => AttributeValue::Bool(*b),
+                StaticValue::U64(n) => AttributeValue::U64(*n),
+                StaticValue::F64(x) => AttributeValue::F64(*x),
+                StaticValue::String(s) => AttributeValue::String(s.clone()),
+            };
+            attributes.push((key.clone(), value));
+        }
+        for (key, var) in &self.env {
+            match std::env::var(var) {
+                Ok(value) => attributes.push((key.clone(), AttributeValue::String(value))),
+                Err(e) => log::warn!("Attribute {key} will not be added: cannot read environment variable {var}: {e}"),
+            }
+        }
+        for (key, path) in &self.files {
+            match std::fs::read_to_string(path) {
+                Ok(value) => attributes.push((key.clone(), AttributeValue::String(value.trim().to_owned()))),
+                Err(e) => log::warn!("Attribute {key} will not be added: cannot read file {path:?}: {e}"),
+            }
+        }
+        for fact in &self.host_facts {
+            let (key, value) = match fact {
+                HostFact::Hostname => ("hostname", host::hostname()),
+                HostFact::KernelVersion => ("kernel_version", host::kernel_version()),
+                HostFact::CpuModel => ("cpu_model", host::cpu_model()),
+            };
+            match value {
+                Ok(value) => attributes.push((key.to_owned(), AttributeValue::String(value))),
+                Err(e) => log::warn!("Attribute {key} will not be added: cannot get the host fact: {e}"),
+            }
+        }
+        attributes
+    }
+}
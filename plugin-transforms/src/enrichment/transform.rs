@@ -0,0 +1,104 @@
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+use regex::Regex;
+
+/// Adds attributes to the measurements.
+///
+/// The attributes that are already set on a point are not replaced.
+pub struct EnrichmentTransform {
+    attributes: Vec<(String, AttributeValue)>,
+    /// Patterns on the metric names. If empty, all the points are enriched.
+    metrics: Vec<Regex>,
+    /// Whether each metric must be enriched.
+    selected: FxHashMap<RawMetricId, bool>,
+}
+
+impl EnrichmentTransform {
+    pub fn new(attributes: Vec<(String, AttributeValue)>, metrics: Vec<Regex>) -> Self {
+        Self {
+            attributes,
+            metrics,
+            selected: FxHashMap::default(),
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer, metric_name: impl Fn(&RawMetricId) -> Option<String>) {
+        for point in measurements.iter_mut() {
+            if !self.metrics.is_empty() {
+                let selected = *self.selected.entry(point.metric).or_insert_with(|| {
+                    metric_name(&point.metric).is_some_and(|name| self.metrics.iter().any(|re| re.is_match(&name)))
+                });
+                if !selected {
+                    continue;
+                }
+            }
+            for (key, value) in &self.attributes {
+                if !point.attributes_keys().any(|k| k == key) {
+                    point.add_attr(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Transform for EnrichmentTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements, |id| ctx.metrics.by_id(id).map(|m| m.name.clone()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::EnrichmentTransform;
+    use crate::pattern;
+
+    fn point(metric: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(0),
+        )
+    }
+
+    fn attributes(p: &MeasurementPoint) -> Vec<(String, String)> {
+        p.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn enrich_selected_metrics() {
+        let mut t = EnrichmentTransform::new(
+            vec![
+                (String::from("cluster"), AttributeValue::Str("grid5000")),
+                (String::from("rack"), AttributeValue::U64(12)),
+            ],
+            vec![pattern::glob("rapl_*").unwrap()],
+        );
+        let names = ["rapl_consumed_energy", "cgroup_cpu_usage_total"];
+        let mut buf = MeasurementBuffer::from(vec![point(0).with_attr("rack", "override"), point(1)]);
+        t.process(&mut buf, |id| names.get(id.as_u64() as usize).map(|s| s.to_string()));
+
+        let points: Vec<_> = buf.iter().collect();
+        assert_eq!(
+            attributes(points[0]),
+            vec![
+                (String::from("rack"), String::from("override")),
+                (String::from("cluster"), String::from("grid5000")),
+            ]
+        );
+        assert!(attributes(points[1]).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! Keeps or drops measurements according to declarative rules.
+
+use std::collections::BTreeMap;
+
+use alumet::plugin::{
+    rust::{deserialize_config, serialize_config, AlumetPlugin},
+    AlumetPluginStart, ConfigTable,
+};
+use serde::{Deserialize, Serialize};
+
+use transform::{FilterTransform, Rule};
+
+mod transform;
+
+pub struct FilterPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for FilterPlugin {
+    fn name() -> &'static str {
+        "filter"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(FilterPlugin { config }))
+    }
+
+    fn start(&mut self, alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        let rules = self
+            .config
+            .rules
+            .iter()
+            .map(Rule::try_from)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let transform = FilterTransform::new(rules, self.config.default_action);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// What to do with the points that match no rule.
+    default_action: Action,
+    /// The rules are evaluated in order, the first one that matches a point decides what to do with it.
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Action {
+    Keep,
+    Drop,
+}
+
+/// A filtering rule. A point matches the rule if it satisfies all the conditions that are set.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RuleConfig {
+    pub action: Action,
+    /// Glob pattern on the metric name, e.g. `rapl_*`.
+    pub metric: Option<String>,
+    /// Regular expression that must match the whole metric name.
+    pub metric_regex: Option<String>,
+    pub resource_kind: Option<String>,
+    /// Glob pattern on the resource id.
+    pub resource_id: Option<String>,
+    pub consumer_kind: Option<String>,
+    /// Glob pattern on the consumer id.
+    pub consumer_id: Option<String>,
+    /// Keys of attributes that must be present.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub has_attributes: Vec<String>,
+    /// Attribute key -> glob pattern on the attribute value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    /// Minimum value (inclusive).
+    pub min: Option<f64>,
+    /// Maximum value (inclusive).
+    pub max: Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_action: Action::Keep,
+            rules: Vec::new(),
+        }
+    }
+}
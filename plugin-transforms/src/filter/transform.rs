@@ -0,0 +1,286 @@
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+use regex::Regex;
+
+use super::{Action, RuleConfig};
+use crate::{pattern, series::value_as_f64};
+
+/// A compiled filtering rule.
+pub struct Rule {
+    action: Action,
+    metric: Option<Regex>,
+    resource_kind: Option<String>,
+    resource_id: Option<Regex>,
+    consumer_kind: Option<String>,
+    consumer_id: Option<Regex>,
+    has_attributes: Vec<String>,
+    attributes: Vec<(String, Regex)>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl TryFrom<&RuleConfig> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &RuleConfig) -> Result<Self, Self::Error> {
+        let metric = match (&config.metric, &config.metric_regex) {
+            (Some(_), Some(_)) => return Err(anyhow::anyhow!("metric and metric_regex cannot be used together")),
+            (Some(glob), None) => Some(pattern::glob(glob)?),
+            (None, Some(re)) => Some(pattern::anchored_regex(re)?),
+            (None, None) => None,
+        };
+        let attributes = config
+            .attributes
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), pattern::glob(value)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            action: config.action,
+            metric,
+            resource_kind: config.resource_kind.clone(),
+            resource_id: config.resource_id.as_deref().map(pattern::glob).transpose()?,
+            consumer_kind: config.consumer_kind.clone(),
+            consumer_id: config.consumer_id.as_deref().map(pattern::glob).transpose()?,
+            has_attributes: config.has_attributes.clone(),
+            attributes,
+            min: config.min,
+            max: config.max,
+        })
+    }
+}
+
+impl Rule {
+    /// Checks the conditions that do not depend on the metric name.
+    fn matches_point(&self, point: &MeasurementPoint) -> bool {
+        if self
+            .resource_kind
+            .as_deref()
+            .is_some_and(|k| k != point.resource.kind())
+        {
+            return false;
+        }
+        if let Some(re) = &self.resource_id {
+            if !re.is_match(&point.resource.id_display().to_string()) {
+                return false;
+            }
+        }
+        if self
+            .consumer_kind
+            .as_deref()
+            .is_some_and(|k| k != point.consumer.kind())
+        {
+            return false;
+        }
+        if let Some(re) = &self.consumer_id {
+            if !re.is_match(&point.consumer.id_display().to_string()) {
+                return false;
+            }
+        }
+        for key in &self.has_attributes {
+            if !point.attributes_keys().any(|k| k == key) {
+                return false;
+            }
+        }
+        for (key, re) in &self.attributes {
+            let matching = point.attributes().any(|(k, v)| k == key && re.is_match(&v.to_string()));
+            if !matching {
+                return false;
+            }
+        }
+        if self.min.is_some() || self.max.is_some() {
+            let value = value_as_f64(&point.value);
+            if self.min.is_some_and(|min| value < min) || self.max.is_some_and(|max| value > max) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Keeps or drops the points according to a list of rules.
+///
+/// The metric names are resolved at runtime, with the registry of the [`TransformContext`],
+/// which allows to filter metrics that are registered while the pipeline is running.
+pub struct FilterTransform {
+    rules: Vec<Rule>,
+    default_action: Action,
+    /// For each metric, whether each rule matches its name.
+    name_matches: FxHashMap<RawMetricId, Vec<bool>>,
+}
+
+impl FilterTransform {
+    pub fn new(rules: Vec<Rule>, default_action: Action) -> Self {
+        Self {
+            rules,
+            default_action,
+            name_matches: FxHashMap::default(),
+        }
+    }
+
+    /// Filters the measurements. `metric_name` returns the name of a metric, given its id.
+    fn process(&mut self, measurements: &mut MeasurementBuffer, metric_name: impl Fn(&RawMetricId) -> Option<String>) {
+        let input = std::mem::take(measurements);
+        for point in input {
+            let name_matches = self.name_matches.entry(point.metric).or_insert_with(|| {
+                let name = metric_name(&point.metric);
+                self.rules
+                    .iter()
+                    .map(|rule| match (&rule.metric, &name) {
+                        (None, _) => true,
+                        (Some(re), Some(name)) => re.is_match(name),
+                        (Some(_), None) => false,
+                    })
+                    .collect()
+            });
+            let action = self
+                .rules
+                .iter()
+                .zip(name_matches.iter())
+                .find(|(rule, name_matches)| **name_matches && rule.matches_point(&point))
+                .map(|(rule, _)| rule.action)
+                .unwrap_or(self.default_action);
+            if action == Action::Keep {
+                measurements.push(point);
+            }
+        }
+    }
+}
+
+impl Transform for FilterTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements, |id| ctx.metrics.by_id(id).map(|m| m.name.clone()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+
+    use super::{FilterTransform, Rule};
+    use crate::filter::Config;
+
+    const METRICS: [&str; 3] = [
+        "rapl_consumed_energy",
+        "cgroup_cpu_usage_total",
+        "perf_hardware_INSTRUCTIONS",
+    ];
+
+    fn transform(config: &str) -> FilterTransform {
+        let config: Config = toml::from_str(config).unwrap();
+        let rules = config.rules.iter().map(|r| Rule::try_from(r).unwrap()).collect();
+        FilterTransform::new(rules, config.default_action)
+    }
+
+    fn point(metric: u64, resource: Resource, consumer: ResourceConsumer, value: f64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(metric),
+            resource,
+            consumer,
+            WrappedMeasurementValue::F64(value),
+        )
+    }
+
+    fn run(t: &mut FilterTransform, points: Vec<MeasurementPoint>) -> Vec<MeasurementPoint> {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf, |id| METRICS.get(id.as_u64() as usize).map(|s| s.to_string()));
+        buf.into_iter().collect()
+    }
+
+    #[test]
+    fn keep_by_name_and_resource() {
+        let mut t = transform(
+            r#"
+            default_action = "drop"
+
+            [[rules]]
+            action = "keep"
+            metric = "rapl_*"
+            resource_kind = "cpu_package"
+            resource_id = "0"
+
+            [[rules]]
+            action = "keep"
+            metric_regex = "cgroup_.*_total"
+            consumer_kind = "cgroup"
+            consumer_id = "kubepods/*"
+            "#,
+        );
+        let res = run(
+            &mut t,
+            vec![
+                point(0, Resource::CpuPackage { id: 0 }, ResourceConsumer::LocalMachine, 1.0),
+                point(0, Resource::CpuPackage { id: 1 }, ResourceConsumer::LocalMachine, 2.0),
+                point(0, Resource::Dram { pkg_id: 0 }, ResourceConsumer::LocalMachine, 3.0),
+                point(
+                    1,
+                    Resource::LocalMachine,
+                    ResourceConsumer::ControlGroup {
+                        path: "kubepods/pod1".into(),
+                    },
+                    4.0,
+                ),
+                point(
+                    1,
+                    Resource::LocalMachine,
+                    ResourceConsumer::ControlGroup { path: "system".into() },
+                    5.0,
+                ),
+                point(2, Resource::LocalMachine, ResourceConsumer::LocalMachine, 6.0),
+                // unknown metric
+                point(42, Resource::CpuPackage { id: 0 }, ResourceConsumer::LocalMachine, 7.0),
+            ],
+        );
+        let values: Vec<_> = res.iter().map(|p| p.value.clone()).collect();
+        assert_eq!(
+            values,
+            vec![WrappedMeasurementValue::F64(1.0), WrappedMeasurementValue::F64(4.0)]
+        );
+    }
+
+    #[test]
+    fn drop_by_attributes_and_range() {
+        let mut t = transform(
+            r#"
+            default_action = "keep"
+
+            [[rules]]
+            action = "drop"
+            attributes = { domain = "dram" }
+
+            [[rules]]
+            action = "drop"
+            has_attributes = ["debug"]
+
+            [[rules]]
+            action = "drop"
+            metric = "rapl_*"
+            min = 1000.0
+            "#,
+        );
+        let p = || point(0, Resource::LocalMachine, ResourceConsumer::LocalMachine, 1.0);
+        let res = run(
+            &mut t,
+            vec![
+                p().with_attr("domain", "package"),
+                p().with_attr("domain", "dram"),
+                p().with_attr("debug", true),
+                point(0, Resource::LocalMachine, ResourceConsumer::LocalMachine, 5000.0),
+                point(1, Resource::LocalMachine, ResourceConsumer::LocalMachine, 5000.0),
+            ],
+        );
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].attributes().next().unwrap().1.to_string(), "package");
+        assert_eq!(res[1].metric, RawMetricId::from_u64(1));
+    }
+}
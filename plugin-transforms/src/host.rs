@@ -0,0 +1,43 @@
+//! Facts about the host.
+
+use std::{fs, io};
+
+/// Returns the name of the host.
+pub fn hostname() -> io::Result<String> {
+    read_trimmed("/proc/sys/kernel/hostname")
+}
+
+/// Returns the version of the kernel, e.g. `6.8.0-45-generic`.
+pub fn kernel_version() -> io::Result<String> {
+    read_trimmed("/proc/sys/kernel/osrelease")
+}
+
+/// Returns the model of the first CPU, as reported by `/proc/cpuinfo`.
+pub fn cpu_model() -> io::Result<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo")?;
+    parse_cpu_model(&cpuinfo).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no model name in /proc/cpuinfo"))
+}
+
+fn parse_cpu_model(cpuinfo: &str) -> Option<String> {
+    cpuinfo.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "model name").then(|| value.trim().to_owned())
+    })
+}
+
+fn read_trimmed(path: &str) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn cpu_model() {
+        let cpuinfo = "processor\t: 0\nvendor_id\t: GenuineIntel\nmodel name\t: Intel(R) Xeon(R) Gold 5220 CPU @ 2.20GHz\nflags\t: fpu\n";
+        assert_eq!(
+            super::parse_cpu_model(cpuinfo).as_deref(),
+            Some("Intel(R) Xeon(R) Gold 5220 CPU @ 2.20GHz")
+        );
+        assert_eq!(super::parse_cpu_model("processor\t: 0\n"), None);
+    }
+}
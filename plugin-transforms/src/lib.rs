@@ -0,0 +1,39 @@
+//! Generic transform steps, each provided by a plugin.
+//!
+//! The transforms are configured in the TOML configuration of the agent.
+//! See the README of this crate for the list of plugins and their configuration.
+//!
+//! The staleness markers (see [`alumet::measurement::STALE_MARKER_ATTRIBUTE`]) do not carry a measurement:
+//! the transforms that compute new values forward them unchanged, without using their value.
+
+mod aggregation;
+mod alerting;
+mod align;
+mod carbon;
+mod cost;
+mod counter;
+mod derived;
+mod enrichment;
+mod filter;
+mod host;
+mod pattern;
+mod power;
+mod relabel;
+mod reorder;
+mod series;
+mod staleness;
+mod units;
+
+pub use aggregation::AggregationPlugin;
+pub use alerting::AlertingPlugin;
+pub use align::TimeAlignmentPlugin;
+pub use carbon::CarbonIntensityPlugin;
+pub use cost::EnergyCostPlugin;
+pub use counter::CounterDiffPlugin;
+pub use derived::DerivedMetricsPlugin;
+pub use enrichment::EnrichmentPlugin;
+pub use filter::FilterPlugin;
+pub use power::EnergyToPowerPlugin;
+pub use relabel::RelabelPlugin;
+pub use reorder::ReorderPlugin;
+pub use staleness::StalenessPlugin;
@@ -0,0 +1,50 @@
+//! Patterns that match names and values.
+
+use anyhow::Context;
+use regex::Regex;
+
+/// Compiles a glob pattern into a regular expression that matches the whole string.
+///
+/// `*` matches any sequence of characters (including an empty one), `?` matches exactly one character.
+pub fn glob(pattern: &str) -> anyhow::Result<Regex> {
+    let mut re = String::with_capacity(pattern.len() + 8);
+    re.push('^');
+    for c in pattern.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).with_context(|| format!("invalid glob pattern '{pattern}'"))
+}
+
+/// Compiles a regular expression that must match the whole string, like in Prometheus.
+pub fn anchored_regex(pattern: &str) -> anyhow::Result<Regex> {
+    Regex::new(&format!("^(?:{pattern})$")).with_context(|| format!("invalid regular expression '{pattern}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn glob() {
+        let g = super::glob("rapl_*").unwrap();
+        assert!(g.is_match("rapl_consumed_energy"));
+        assert!(g.is_match("rapl_"));
+        assert!(!g.is_match("xrapl_consumed_energy"));
+
+        let g = super::glob("cpu?.total").unwrap();
+        assert!(g.is_match("cpu1.total"));
+        assert!(!g.is_match("cpu12.total"));
+        assert!(!g.is_match("cpu1xtotal"));
+    }
+
+    #[test]
+    fn anchored_regex() {
+        let re = super::anchored_regex("rapl_.*|perf_.*").unwrap();
+        assert!(re.is_match("rapl_consumed_energy"));
+        assert!(re.is_match("perf_instructions"));
+        assert!(!re.is_match("my_rapl_consumed_energy"));
+    }
+}
@@ -0,0 +1,115 @@
+//! Computes the average power from energy measurements.
+
+use std::time::Duration;
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::Context;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use transform::{EnergySpec, PowerTransform};
+
+mod transform;
+
+pub struct EnergyToPowerPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for EnergyToPowerPlugin {
+    fn name() -> &'static str {
+        "energy-to-power"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(EnergyToPowerPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut specs = FxHashMap::default();
+        for energy in &self.config.metrics {
+            let Some((input_id, input)) = alumet.metrics().by_name(&energy.metric) else {
+                log::warn!("Metric {} not found, its power will not be computed.", energy.metric);
+                continue;
+            };
+            let to_joules = input
+                .unit
+                .factor(&Unit::Joule)
+                .with_context(|| format!("metric {} is not an energy, its unit is {}", energy.metric, input.unit))?;
+            let output_name = energy
+                .output
+                .clone()
+                .unwrap_or_else(|| format!("{}_power", energy.metric));
+            let description = format!("average power computed from {}", energy.metric);
+            let output = alumet
+                .create_metric_untyped(&output_name, WrappedMeasurementType::F64, Unit::Watt, &description)
+                .with_context(|| format!("could not create metric {output_name}"))?;
+            let spec = EnergySpec {
+                output,
+                to_joules,
+                keep_input: energy.keep_input,
+            };
+            specs.insert(input_id, spec);
+        }
+        let transform = PowerTransform::new(specs, self.config.max_gap);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Maximum time between two consecutive points of a series.
+    ///
+    /// If the time between two points is larger, the series is considered to have been interrupted,
+    /// and the energy of the second point is not turned into power.
+    #[serde(with = "humantime_serde")]
+    max_gap: Duration,
+    metrics: Vec<EnergyConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnergyConfig {
+    /// Name of the energy metric.
+    metric: String,
+    /// Name of the power metric to create. Defaults to `{metric}_power`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// Keep the energy measurements in addition to the power.
+    #[serde(default)]
+    keep_input: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_gap: Duration::from_secs(10),
+            metrics: Vec::new(),
+        }
+    }
+}
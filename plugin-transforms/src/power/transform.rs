@@ -0,0 +1,218 @@
+use std::time::{Duration, SystemTime};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+
+use crate::series::{derived_point, value_as_f64, SeriesKey};
+
+/// How to compute the power of an energy metric.
+pub struct EnergySpec {
+    pub output: RawMetricId,
+    /// Factor to apply to the energy to get joules.
+    pub to_joules: f64,
+    pub keep_input: bool,
+}
+
+/// Computes the average power from energy measurements.
+///
+/// Each energy point is the energy consumed since the previous point of the same series.
+/// The average power is therefore the energy divided by the time elapsed since the previous point.
+///
+/// The power cannot be computed in the following cases, which (re)start the series:
+/// - first point of the series: the duration of the interval is unknown;
+/// - gap: the previous point is older than `max_gap`, the source has probably been paused or restarted;
+/// - restart: the timestamp is not after the previous one, for instance because the source has been recreated.
+pub struct PowerTransform {
+    /// Energy metric -> spec
+    specs: FxHashMap<RawMetricId, EnergySpec>,
+    max_gap: Duration,
+    /// Timestamp of the last point of each series.
+    last_seen: FxHashMap<SeriesKey, SystemTime>,
+}
+
+impl PowerTransform {
+    pub fn new(specs: FxHashMap<RawMetricId, EnergySpec>, max_gap: Duration) -> Self {
+        Self {
+            specs,
+            max_gap,
+            last_seen: FxHashMap::default(),
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer) {
+        let input = std::mem::take(measurements);
+        let mut latest = None;
+        for point in input {
+            let Some(spec) = self.specs.get(&point.metric).filter(|_| !point.is_stale_marker()) else {
+                measurements.push(point);
+                continue;
+            };
+            latest = latest.max(Some(SystemTime::from(point.timestamp)));
+            if let Some(power) = Self::power(&mut self.last_seen, self.max_gap, spec, &point) {
+                let value = WrappedMeasurementValue::F64(power);
+                measurements.push(derived_point(&point, spec.output, value));
+            }
+            if spec.keep_input {
+                measurements.push(point);
+            }
+        }
+        if let Some(latest) = latest {
+            self.clean(latest);
+        }
+    }
+
+    /// Forgets the series whose last point is older than `max_gap`: their next point would restart them anyway.
+    fn clean(&mut self, now: SystemTime) {
+        let max_gap = self.max_gap;
+        self.last_seen
+            .retain(|_, last| now.duration_since(*last).unwrap_or_default() <= max_gap);
+    }
+
+    /// Updates the series of `point` and returns the average power in watts, if it can be computed.
+    fn power(
+        last_seen: &mut FxHashMap<SeriesKey, SystemTime>,
+        max_gap: Duration,
+        spec: &EnergySpec,
+        point: &MeasurementPoint,
+    ) -> Option<f64> {
+        let time = SystemTime::from(point.timestamp);
+        let previous = last_seen.insert(SeriesKey::of(point), time)?;
+        let elapsed = match time.duration_since(previous) {
+            Ok(elapsed) if elapsed.is_zero() => {
+                log::debug!(
+                    "Duplicate timestamp in energy series {:?}, the series is restarted.",
+                    point.metric
+                );
+                return None;
+            }
+            Ok(elapsed) if elapsed > max_gap => {
+                log::debug!(
+                    "Gap of {elapsed:?} in energy series {:?}, the series is restarted.",
+                    point.metric
+                );
+                return None;
+            }
+            Ok(elapsed) => elapsed,
+            Err(_) => {
+                log::debug!(
+                    "Time went backwards in energy series {:?}, the series is restarted.",
+                    point.metric
+                );
+                return None;
+            }
+        };
+        let joules = value_as_f64(&point.value) * spec.to_joules;
+        Some(joules / elapsed.as_secs_f64())
+    }
+}
+
+impl Transform for PowerTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashMap;
+
+    use super::{EnergySpec, PowerTransform};
+
+    fn energy_metric() -> RawMetricId {
+        RawMetricId::from_u64(0)
+    }
+
+    fn point(t_ms: u64, socket: u32, joules: f64) -> MeasurementPoint {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_millis(t_ms);
+        MeasurementPoint::new_untyped(
+            Timestamp::from(t),
+            energy_metric(),
+            Resource::CpuPackage { id: socket },
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::F64(joules),
+        )
+    }
+
+    fn transform(to_joules: f64) -> PowerTransform {
+        let mut specs = FxHashMap::default();
+        specs.insert(
+            energy_metric(),
+            EnergySpec {
+                output: RawMetricId::from_u64(1),
+                to_joules,
+                keep_input: false,
+            },
+        );
+        PowerTransform::new(specs, Duration::from_secs(5))
+    }
+
+    fn run(t: &mut PowerTransform, points: Vec<MeasurementPoint>) -> Vec<f64> {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf);
+        buf.iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::F64(x) => x,
+                WrappedMeasurementValue::U64(_) => panic!("power should be a float"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sub_second_intervals() {
+        let mut t = transform(1.0);
+        // first sample: no power
+        assert!(run(&mut t, vec![point(0, 0, 1.0), point(0, 1, 1.0)]).is_empty());
+        assert_eq!(
+            run(&mut t, vec![point(100, 0, 2.0), point(100, 1, 3.0)]),
+            vec![20.0, 30.0]
+        );
+        assert_eq!(run(&mut t, vec![point(350, 0, 5.0)]), vec![20.0]);
+    }
+
+    #[test]
+    fn unit_conversion() {
+        let mut t = transform(1e-3); // millijoules
+        run(&mut t, vec![point(0, 0, 0.0)]);
+        assert_eq!(run(&mut t, vec![point(1000, 0, 2000.0)]), vec![2.0]);
+    }
+
+    #[test]
+    fn gaps_and_restarts() {
+        let mut t = transform(1.0);
+        run(&mut t, vec![point(0, 0, 1.0)]);
+        // gap larger than max_gap
+        assert!(run(&mut t, vec![point(10_000, 0, 50.0)]).is_empty());
+        assert_eq!(run(&mut t, vec![point(11_000, 0, 5.0)]), vec![5.0]);
+        // time went backwards
+        assert!(run(&mut t, vec![point(500, 0, 5.0)]).is_empty());
+        assert_eq!(run(&mut t, vec![point(1000, 0, 5.0)]), vec![10.0]);
+        // duplicate timestamp
+        assert!(run(&mut t, vec![point(1000, 0, 5.0)]).is_empty());
+    }
+
+    #[test]
+    fn forget_old_series() {
+        let mut t = transform(1.0);
+        run(&mut t, vec![point(0, 0, 1.0), point(0, 1, 1.0)]);
+        assert_eq!(t.last_seen.len(), 2);
+
+        // Only the first socket is still measured.
+        assert_eq!(run(&mut t, vec![point(4000, 0, 4.0)]), vec![1.0]);
+        assert_eq!(t.last_seen.len(), 2);
+        assert_eq!(run(&mut t, vec![point(8000, 0, 4.0)]), vec![1.0]);
+        assert_eq!(t.last_seen.len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+//! Renames metrics and rewrites attributes, in the spirit of Prometheus `relabel_configs`.
+
+use alumet::plugin::{
+    rust::{deserialize_config, serialize_config, AlumetPlugin},
+    AlumetPluginStart, AlumetPreStart, ConfigTable,
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::pattern;
+use transform::{AttributeRule, MetricRule, RelabelTransform};
+
+mod transform;
+
+pub struct RelabelPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for RelabelPlugin {
+    fn name() -> &'static str {
+        "relabel"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(RelabelPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        // Compile the rules.
+        let mut metric_rules = Vec::new();
+        let mut attribute_rules = Vec::new();
+        for rule in &self.config.rules {
+            match rule {
+                RuleConfig::RenameMetric { regex, replacement } => metric_rules.push(MetricRule {
+                    regex: pattern::anchored_regex(regex)?,
+                    replacement: replacement.clone(),
+                }),
+                rule => attribute_rules.push(AttributeRule::try_from(rule)?),
+            }
+        }
+
+        // Register the new names of the metrics that exist now.
+        // The transform resolves the names at runtime, which also renames the metrics that are
+        // registered later, provided that their new name exists.
+        if !metric_rules.is_empty() {
+            let existing: Vec<_> = alumet.metrics().iter().map(|(_, metric)| metric.clone()).collect();
+            for metric in existing {
+                let Some(name) = transform::rename_metric(&metric_rules, &metric.name) else {
+                    continue;
+                };
+                match alumet.metrics().by_name(&name) {
+                    // Two metrics can be renamed to the same name, if they are compatible.
+                    Some((_, new_metric))
+                        if new_metric.value_type == metric.value_type && new_metric.unit == metric.unit => {}
+                    Some(_) => {
+                        return Err(anyhow!(
+                            "cannot rename metric {} to {name}: a metric with this name but a different type or unit already exists",
+                            metric.name
+                        ))
+                    }
+                    None => {
+                        alumet
+                            .create_metric_untyped(&name, metric.value_type, metric.unit, &metric.description)
+                            .with_context(|| format!("could not create metric {name}"))?;
+                    }
+                };
+                log::debug!("Metric {} is renamed to {name}", metric.name);
+            }
+        }
+
+        let transform = RelabelTransform::new(metric_rules, attribute_rules);
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// The rules are applied in order.
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum RuleConfig {
+    /// Renames the metrics whose name matches `regex`.
+    RenameMetric { regex: String, replacement: String },
+    /// Renames an attribute key.
+    RenameAttribute { key: String, new_key: String },
+    /// Removes the attributes whose key matches `regex`.
+    DropAttribute { regex: String },
+    /// Copies the value of an attribute to another key.
+    CopyAttribute { key: String, new_key: String },
+    /// Builds a new attribute from the values of existing attributes.
+    ///
+    /// The values of `source_attributes` are joined with `separator`.
+    /// If the result matches `regex`, the attribute `target` is set to `replacement`,
+    /// in which the capture groups of the regex can be referenced (`$1`, `${name}`).
+    Replace {
+        source_attributes: Vec<String>,
+        #[serde(default = "default_separator")]
+        separator: String,
+        #[serde(default = "default_regex")]
+        regex: String,
+        target: String,
+        #[serde(default = "default_replacement")]
+        replacement: String,
+    },
+}
+
+fn default_separator() -> String {
+    String::from(";")
+}
+
+fn default_regex() -> String {
+    String::from("(.*)")
+}
+
+fn default_replacement() -> String {
+    String::from("$1")
+}
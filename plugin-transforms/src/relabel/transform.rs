@@ -0,0 +1,371 @@
+use alumet::{
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint},
+    metrics::{Metric, RawMetricId},
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::FxHashMap;
+use regex::Regex;
+
+use super::RuleConfig;
+use crate::pattern;
+
+/// A compiled rule that renames metrics.
+pub struct MetricRule {
+    pub regex: Regex,
+    pub replacement: String,
+}
+
+/// Applies the rules to the name of a metric, in order.
+///
+/// Returns the new name, or `None` if the metric is not renamed.
+pub fn rename_metric(rules: &[MetricRule], name: &str) -> Option<String> {
+    let mut new_name = name.to_owned();
+    for rule in rules {
+        new_name = rule.regex.replace(&new_name, rule.replacement.as_str()).into_owned();
+    }
+    (new_name != name).then_some(new_name)
+}
+
+/// A compiled rule that rewrites the attributes of a point.
+pub enum AttributeRule {
+    Rename {
+        key: String,
+        new_key: String,
+    },
+    Drop {
+        regex: Regex,
+    },
+    Copy {
+        key: String,
+        new_key: String,
+    },
+    Replace {
+        source_attributes: Vec<String>,
+        separator: String,
+        regex: Regex,
+        target: String,
+        replacement: String,
+    },
+}
+
+impl TryFrom<&RuleConfig> for AttributeRule {
+    type Error = anyhow::Error;
+
+    fn try_from(rule: &RuleConfig) -> Result<Self, Self::Error> {
+        let res = match rule {
+            RuleConfig::RenameMetric { .. } => unreachable!("metric rules are compiled to MetricRule"),
+            RuleConfig::RenameAttribute { key, new_key } => AttributeRule::Rename {
+                key: key.clone(),
+                new_key: new_key.clone(),
+            },
+            RuleConfig::DropAttribute { regex } => AttributeRule::Drop {
+                regex: pattern::anchored_regex(regex)?,
+            },
+            RuleConfig::CopyAttribute { key, new_key } => AttributeRule::Copy {
+                key: key.clone(),
+                new_key: new_key.clone(),
+            },
+            RuleConfig::Replace {
+                source_attributes,
+                separator,
+                regex,
+                target,
+                replacement,
+            } => AttributeRule::Replace {
+                source_attributes: source_attributes.clone(),
+                separator: separator.clone(),
+                regex: pattern::anchored_regex(regex)?,
+                target: target.clone(),
+                replacement: replacement.clone(),
+            },
+        };
+        Ok(res)
+    }
+}
+
+impl AttributeRule {
+    fn apply(&self, point: &mut MeasurementPoint) {
+        match self {
+            AttributeRule::Rename { key, new_key } => {
+                if let Some(value) = point.remove_attr(key) {
+                    set_attr(point, new_key, value);
+                }
+            }
+            AttributeRule::Drop { regex } => {
+                let dropped: Vec<String> = point
+                    .attributes_keys()
+                    .filter(|k| regex.is_match(k))
+                    .map(String::from)
+                    .collect();
+                for key in dropped {
+                    point.remove_attr(&key);
+                }
+            }
+            AttributeRule::Copy { key, new_key } => {
+                if let Some(value) = get_attr(point, key) {
+                    set_attr(point, new_key, value);
+                }
+            }
+            AttributeRule::Replace {
+                source_attributes,
+                separator,
+                regex,
+                target,
+                replacement,
+            } => {
+                // Like in Prometheus, a missing attribute is treated as an empty value.
+                let source = source_attributes
+                    .iter()
+                    .map(|key| get_attr(point, key).map(|v| v.to_string()).unwrap_or_default())
+                    .collect::<Vec<_>>()
+                    .join(separator);
+                if let Some(captures) = regex.captures(&source) {
+                    let mut value = String::new();
+                    captures.expand(replacement, &mut value);
+                    if value.is_empty() {
+                        point.remove_attr(target);
+                    } else {
+                        set_attr(point, target, AttributeValue::String(value));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn get_attr(point: &MeasurementPoint, key: &str) -> Option<AttributeValue> {
+    point.attributes().find(|(k, _)| *k == key).map(|(_, v)| v.clone())
+}
+
+/// Sets an attribute, replacing the previous value if any.
+fn set_attr(point: &mut MeasurementPoint, key: &str, value: AttributeValue) {
+    point.remove_attr(key);
+    point.add_attr(key.to_owned(), value);
+}
+
+/// Renames the metrics and rewrites the attributes of the measurements.
+///
+/// The metric names are resolved at runtime, with the registry of the [`TransformContext`],
+/// which allows to rename metrics that are registered while the pipeline is running.
+pub struct RelabelTransform {
+    /// Rules to apply to the metric names, in order.
+    metric_rules: Vec<MetricRule>,
+    /// For each metric, the id of its new name, or `None` if it is not renamed.
+    renamed_metrics: FxHashMap<RawMetricId, Option<RawMetricId>>,
+    /// Rules to apply to the attributes, in order.
+    attribute_rules: Vec<AttributeRule>,
+}
+
+impl RelabelTransform {
+    pub fn new(metric_rules: Vec<MetricRule>, attribute_rules: Vec<AttributeRule>) -> Self {
+        Self {
+            metric_rules,
+            renamed_metrics: FxHashMap::default(),
+            attribute_rules,
+        }
+    }
+
+    /// Relabels the measurements. `metric_by_id` and `metric_by_name` look up the metrics in the registry.
+    fn process<'m>(
+        &mut self,
+        measurements: &mut MeasurementBuffer,
+        metric_by_id: impl Fn(&RawMetricId) -> Option<&'m Metric>,
+        metric_by_name: impl Fn(&str) -> Option<(RawMetricId, &'m Metric)>,
+    ) {
+        for point in measurements.iter_mut() {
+            if !self.metric_rules.is_empty() {
+                let new_id = self.renamed_metrics.entry(point.metric).or_insert_with(|| {
+                    let metric = metric_by_id(&point.metric)?;
+                    let name = rename_metric(&self.metric_rules, &metric.name)?;
+                    match metric_by_name(&name) {
+                        Some((new_id, new_metric))
+                            if new_metric.value_type == metric.value_type && new_metric.unit == metric.unit =>
+                        {
+                            Some(new_id)
+                        }
+                        Some(_) => {
+                            log::warn!(
+                                "Metric {} will not be renamed to {name}: a metric with this name but a different type or unit already exists",
+                                metric.name
+                            );
+                            None
+                        }
+                        None => {
+                            log::warn!(
+                                "Metric {} will not be renamed to {name}: no metric with this name is registered",
+                                metric.name
+                            );
+                            None
+                        }
+                    }
+                });
+                if let Some(new_id) = new_id {
+                    point.metric = *new_id;
+                }
+            }
+            for rule in &self.attribute_rules {
+                rule.apply(point);
+            }
+        }
+    }
+}
+
+impl Transform for RelabelTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(
+            measurements,
+            |id| ctx.metrics.by_id(id),
+            |name| ctx.metrics.by_name(name),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::{
+        measurement::{
+            MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+        },
+        metrics::{Metric, RawMetricId},
+        resources::{Resource, ResourceConsumer},
+        units::Unit,
+    };
+
+    use super::{AttributeRule, MetricRule, RelabelTransform};
+    use crate::{pattern, relabel::RuleConfig};
+
+    fn point(metric: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(metric),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(0),
+        )
+    }
+
+    fn metric(name: &str) -> Metric {
+        Metric {
+            name: name.to_owned(),
+            description: String::new(),
+            value_type: WrappedMeasurementType::U64,
+            unit: Unit::Watt.into(),
+        }
+    }
+
+    /// Applies the attribute rules, without any metric in the registry.
+    fn process_attributes(mut t: RelabelTransform, buf: &mut MeasurementBuffer) {
+        t.process(buf, |_| None, |_| None);
+    }
+
+    fn attributes(p: &MeasurementPoint) -> Vec<(String, String)> {
+        p.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect()
+    }
+
+    fn rules(toml: &str) -> Vec<AttributeRule> {
+        #[derive(serde::Deserialize)]
+        struct Rules {
+            rules: Vec<RuleConfig>,
+        }
+        let rules: Rules = toml::from_str(toml).unwrap();
+        rules
+            .rules
+            .iter()
+            .map(|r| AttributeRule::try_from(r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rename_metric() {
+        let metric_rules = vec![MetricRule {
+            regex: pattern::anchored_regex("rapl_(.*)").unwrap(),
+            replacement: String::from("cpu_$1"),
+        }];
+        let mut t = RelabelTransform::new(metric_rules, Vec::new());
+        // rapl_energy (3) is registered "late", and cpu_power (2) has no source metric
+        let metrics: Vec<Metric> = ["rapl_power", "perf_cycles", "cpu_power", "rapl_energy"]
+            .iter()
+            .map(|name| metric(name))
+            .collect();
+        let mut buf = MeasurementBuffer::from(vec![point(0), point(1), point(3)]);
+        t.process(
+            &mut buf,
+            |id| metrics.get(id.as_u64() as usize),
+            |name| {
+                let i = metrics.iter().position(|m| m.name == name)?;
+                Some((RawMetricId::from_u64(i as u64), &metrics[i]))
+            },
+        );
+
+        // rapl_energy is not renamed, because cpu_energy does not exist
+        let ids: Vec<_> = buf.iter().map(|p| p.metric.as_u64()).collect();
+        assert_eq!(ids, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn rewrite_attributes() {
+        let rules = rules(
+            r#"
+            [[rules]]
+            action = "rename_attribute"
+            key = "pod_name"
+            new_key = "pod"
+
+            [[rules]]
+            action = "copy_attribute"
+            key = "pod"
+            new_key = "workload"
+
+            [[rules]]
+            action = "drop_attribute"
+            regex = "tmp_.*"
+
+            [[rules]]
+            action = "replace"
+            source_attributes = ["namespace", "pod"]
+            separator = "/"
+            regex = "(.+)/(.+)-[0-9]+"
+            target = "app"
+            replacement = "$1.$2"
+            "#,
+        );
+        let t = RelabelTransform::new(Vec::new(), rules);
+        let mut buf = MeasurementBuffer::from(vec![point(0)
+            .with_attr("namespace", "prod")
+            .with_attr("pod_name", "api-42")
+            .with_attr("tmp_a", "x")
+            .with_attr("tmp_b", 1)]);
+        process_attributes(t, &mut buf);
+
+        let p = buf.iter().next().unwrap();
+        assert_eq!(
+            attributes(p),
+            vec![
+                (String::from("namespace"), String::from("prod")),
+                (String::from("pod"), String::from("api-42")),
+                (String::from("workload"), String::from("api-42")),
+                (String::from("app"), String::from("prod.api")),
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_without_match() {
+        let rules = rules(
+            r#"
+            [[rules]]
+            action = "replace"
+            source_attributes = ["pod"]
+            regex = "api-.*"
+            target = "tier"
+            replacement = "backend"
+            "#,
+        );
+        let t = RelabelTransform::new(Vec::new(), rules);
+        let mut buf = MeasurementBuffer::from(vec![point(0).with_attr("pod", "web-1"), point(0)]);
+        process_attributes(t, &mut buf);
+        assert!(buf.iter().all(|p| !p.attributes_keys().any(|k| k == "tier")));
+    }
+}
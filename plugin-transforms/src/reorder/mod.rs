@@ -0,0 +1,137 @@
+//! Reorders out-of-order points and drops duplicates.
+
+use std::time::Duration;
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::Context;
+use fxhash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+use transform::{ReorderTransform, StatsMetrics};
+
+mod transform;
+
+pub struct ReorderPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for ReorderPlugin {
+    fn name() -> &'static str {
+        "reorder"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(ReorderPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut metrics = FxHashSet::default();
+        for name in &self.config.metrics {
+            match alumet.metrics().by_name(name) {
+                Some((id, _)) => {
+                    metrics.insert(id);
+                }
+                None => log::warn!("Metric {name} not found, its points will not be reordered."),
+            }
+        }
+        if metrics.is_empty() {
+            // nothing to reorder, don't report statistics either
+            return Ok(());
+        }
+        let stats = match self.config.report_interval {
+            Some(interval) => {
+                let mut create = |name: &str, description: &str| {
+                    alumet
+                        .create_metric_untyped(name, WrappedMeasurementType::U64, Unit::Unity, description)
+                        .with_context(|| format!("could not create metric {name}"))
+                };
+                Some(StatsMetrics {
+                    late: create(
+                        "reorder_late_points",
+                        "number of points that arrived after the watermark",
+                    )?,
+                    duplicates: create(
+                        "reorder_duplicate_points",
+                        "number of duplicate points that were dropped",
+                    )?,
+                    interval,
+                })
+            }
+            None => None,
+        };
+        let transform = ReorderTransform::new(
+            metrics,
+            self.config.watermark_delay,
+            self.config.dedup_window,
+            self.config.late_points,
+            stats,
+        );
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Metrics to reorder. The points of the other metrics are forwarded immediately.
+    #[serde(default)]
+    metrics: Vec<String>,
+    /// How long to wait for the out-of-order points: the watermark of a series is its largest timestamp, minus this delay.
+    #[serde(with = "humantime_serde")]
+    watermark_delay: Duration,
+    /// How long to remember the released points, to drop the duplicates that arrive after the watermark.
+    #[serde(with = "humantime_serde")]
+    dedup_window: Duration,
+    /// What to do with the points that arrive after the watermark of their series.
+    late_points: LatePolicy,
+    /// How often to report the number of late and duplicate points. If not set, they are not reported.
+    #[serde(with = "humantime_serde", default, skip_serializing_if = "Option::is_none")]
+    report_interval: Option<Duration>,
+}
+
+/// What to do with the points that arrive after the watermark.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatePolicy {
+    /// Drop the late points.
+    Drop,
+    /// Forward the late points as they are, out of order.
+    Forward,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            metrics: Vec::new(),
+            watermark_delay: Duration::from_secs(2),
+            dedup_window: Duration::from_secs(60),
+            late_points: LatePolicy::Forward,
+            report_interval: Some(Duration::from_secs(10)),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+    resources::{Resource, ResourceConsumer},
+};
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::LatePolicy;
+use crate::series::SeriesKey;
+
+/// Metrics that report the number of points that have been handled specially.
+pub struct StatsMetrics {
+    pub late: RawMetricId,
+    pub duplicates: RawMetricId,
+    pub interval: Duration,
+}
+
+#[derive(Default)]
+struct SeriesState {
+    /// Points that wait for the watermark, by timestamp in nanoseconds.
+    pending: BTreeMap<u128, Vec<MeasurementPoint>>,
+    /// Values of the points that have been released recently, by timestamp, to detect duplicates.
+    released: BTreeMap<u128, Vec<WrappedMeasurementValue>>,
+    /// Largest timestamp of the series.
+    max_time: u128,
+    /// Every point of the series at or before this time has been released.
+    watermark: u128,
+}
+
+/// Reorders the points of each series by timestamp and drops exact duplicates.
+///
+/// Each series has its own watermark: the largest timestamp of the series, minus the configured delay.
+/// Points are held back until the watermark of their series passes their timestamp, then released in order.
+/// A point that arrives when the watermark has already passed its timestamp is late: it cannot
+/// be put in order anymore.
+/// Because the watermarks are independent, a source that flushes its measurements less often
+/// than the others does not make its points late.
+///
+/// A series that has not been measured for `dedup_window` (compared to the most recent point of all
+/// the series) is forgotten, and its pending points are released.
+///
+/// Two points are exact duplicates if they belong to the same series and have the same timestamp and value.
+///
+/// Only the points of the selected metrics are held back, the others are forwarded immediately.
+pub struct ReorderTransform {
+    /// Metrics to reorder.
+    metrics: FxHashSet<RawMetricId>,
+    /// Watermark delay, in nanoseconds.
+    delay: u128,
+    /// How long the released points are remembered to detect duplicates, in nanoseconds.
+    dedup_window: u128,
+    late_policy: LatePolicy,
+    /// Largest timestamp of all the series.
+    max_time: u128,
+    series: FxHashMap<SeriesKey, SeriesState>,
+    stats: Option<StatsMetrics>,
+    late_count: u64,
+    duplicate_count: u64,
+    last_report: Option<SystemTime>,
+}
+
+impl ReorderTransform {
+    pub fn new(
+        metrics: FxHashSet<RawMetricId>,
+        delay: Duration,
+        dedup_window: Duration,
+        late_policy: LatePolicy,
+        stats: Option<StatsMetrics>,
+    ) -> Self {
+        Self {
+            metrics,
+            delay: delay.as_nanos(),
+            dedup_window: dedup_window.as_nanos(),
+            late_policy,
+            max_time: 0,
+            series: FxHashMap::default(),
+            stats,
+            late_count: 0,
+            duplicate_count: 0,
+            last_report: None,
+        }
+    }
+
+    fn process(&mut self, measurements: &mut MeasurementBuffer, now: SystemTime) {
+        let input = std::mem::take(measurements);
+        for point in input {
+            if point.is_stale_marker() || !self.metrics.contains(&point.metric) {
+                // a marker has no value to deduplicate, and comes after the last point of its series
+                measurements.push(point);
+                continue;
+            }
+            let t = time_nanos(point.timestamp);
+            let state = self.series.entry(SeriesKey::of(&point)).or_default();
+            let is_duplicate = state
+                .pending
+                .get(&t)
+                .is_some_and(|points| points.iter().any(|p| p.value == point.value))
+                || state
+                    .released
+                    .get(&t)
+                    .is_some_and(|values| values.contains(&point.value));
+            if is_duplicate {
+                self.duplicate_count += 1;
+            } else if t <= state.watermark {
+                self.late_count += 1;
+                log::debug!(
+                    "Late point of metric {:?}: {:?} is before the watermark.",
+                    point.metric,
+                    point.timestamp
+                );
+                if self.late_policy == LatePolicy::Forward {
+                    measurements.push(point);
+                }
+            } else {
+                state.max_time = state.max_time.max(t);
+                self.max_time = self.max_time.max(t);
+                state.pending.entry(t).or_default().push(point);
+            }
+        }
+
+        // release the points that are before the new watermark of their series, in order
+        let (delay, dedup_window) = (self.delay, self.dedup_window);
+        let idle_before = self.max_time.saturating_sub(delay + dedup_window);
+        let mut released = Vec::new();
+        self.series.retain(|_, state| {
+            let idle = state.max_time < idle_before;
+            state.watermark = if idle {
+                state.max_time
+            } else {
+                state.watermark.max(state.max_time.saturating_sub(delay))
+            };
+            let waiting = state.pending.split_off(&(state.watermark + 1));
+            for (t, points) in std::mem::replace(&mut state.pending, waiting) {
+                state
+                    .released
+                    .insert(t, points.iter().map(|p| p.value.clone()).collect());
+                released.extend(points.into_iter().map(|p| (t, p)));
+            }
+            state.released = state.released.split_off(&state.watermark.saturating_sub(dedup_window));
+            !idle
+        });
+        released.sort_by_key(|(t, _)| *t);
+        for (_, point) in released {
+            measurements.push(point);
+        }
+
+        self.report(measurements, now);
+    }
+
+    /// Emits the number of late and duplicate points since the start, if it is time to do so.
+    fn report(&mut self, measurements: &mut MeasurementBuffer, now: SystemTime) {
+        let Some(stats) = &self.stats else {
+            return;
+        };
+        if self
+            .last_report
+            .is_some_and(|last| now.duration_since(last).unwrap_or_default() < stats.interval)
+        {
+            return;
+        }
+        self.last_report = Some(now);
+        for (metric, count) in [(stats.late, self.late_count), (stats.duplicates, self.duplicate_count)] {
+            measurements.push(MeasurementPoint::new_untyped(
+                Timestamp::from(now),
+                metric,
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(count),
+            ));
+        }
+    }
+}
+
+fn time_nanos(t: Timestamp) -> u128 {
+    SystemTime::from(t)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+impl Transform for ReorderTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, _ctx: &TransformContext) -> Result<(), TransformError> {
+        self.process(measurements, SystemTime::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashSet;
+
+    use super::{ReorderTransform, StatsMetrics};
+    use crate::reorder::LatePolicy;
+
+    fn point(t_s: u64, socket: u32, value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(SystemTime::UNIX_EPOCH + Duration::from_secs(t_s)),
+            RawMetricId::from_u64(0),
+            Resource::CpuPackage { id: socket },
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(value),
+        )
+    }
+
+    fn transform(late_policy: LatePolicy) -> ReorderTransform {
+        let stats = StatsMetrics {
+            late: RawMetricId::from_u64(1),
+            duplicates: RawMetricId::from_u64(2),
+            interval: Duration::from_secs(10),
+        };
+        ReorderTransform::new(
+            FxHashSet::from_iter([RawMetricId::from_u64(0)]),
+            Duration::from_secs(2),
+            Duration::from_secs(60),
+            late_policy,
+            Some(stats),
+        )
+    }
+
+    /// Pairs of (time, value) or (metric, value).
+    type Pairs = Vec<(u64, u64)>;
+
+    /// Runs the transform and returns the (time, value) of the data points, and the reported stats.
+    fn run(t: &mut ReorderTransform, points: Vec<MeasurementPoint>, now: u64) -> (Pairs, Pairs) {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf, SystemTime::UNIX_EPOCH + Duration::from_secs(now));
+        let (mut data, mut stats) = (Vec::new(), Vec::new());
+        for p in buf.iter() {
+            let time = SystemTime::from(p.timestamp)
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let WrappedMeasurementValue::U64(v) = p.value else {
+                panic!("unexpected value {:?}", p.value);
+            };
+            if p.metric == RawMetricId::from_u64(0) {
+                data.push((time, v));
+            } else {
+                stats.push((p.metric.as_u64(), v));
+            }
+        }
+        (data, stats)
+    }
+
+    #[test]
+    fn reorder_with_watermark() {
+        let mut t = transform(LatePolicy::Drop);
+        let (data, stats) = run(&mut t, vec![point(11, 0, 2), point(10, 0, 1), point(13, 1, 3)], 100);
+        // the watermarks are 11 - 2 = 9 for the socket 0, and 13 - 2 = 11 for the socket 1
+        assert!(data.is_empty());
+        assert_eq!(stats, vec![(1, 0), (2, 0)]);
+
+        let (data, _) = run(&mut t, vec![point(15, 0, 5), point(13, 0, 4)], 101);
+        assert_eq!(data, vec![(10, 1), (11, 2), (13, 4)]);
+    }
+
+    #[test]
+    fn independent_watermarks() {
+        let mut t = transform(LatePolicy::Drop);
+        run(&mut t, vec![point(100, 0, 1)], 100);
+        // a series that is flushed less often is not late
+        let (data, _) = run(&mut t, vec![point(95, 1, 2), point(99, 1, 3)], 101);
+        assert_eq!(data, vec![(95, 2)]);
+        assert_eq!(t.late_count, 0);
+    }
+
+    #[test]
+    fn release_idle_series() {
+        let mut t = transform(LatePolicy::Drop);
+        run(&mut t, vec![point(10, 0, 1)], 100);
+        let (data, _) = run(&mut t, vec![point(100, 1, 2)], 101);
+        assert_eq!(data, vec![(10, 1)]);
+        assert_eq!(t.series.len(), 1);
+    }
+
+    #[test]
+    fn drop_duplicates_and_count_late_points() {
+        let mut t = transform(LatePolicy::Drop);
+        let (data, _) = run(&mut t, vec![point(10, 0, 1), point(10, 0, 1), point(10, 1, 1)], 100);
+        assert!(data.is_empty());
+        let (data, _) = run(&mut t, vec![point(13, 0, 3), point(10, 0, 1)], 101);
+        assert_eq!(data, vec![(10, 1)]);
+
+        // duplicate of a released point, and a late point
+        let (data, stats) = run(&mut t, vec![point(10, 0, 1), point(9, 0, 7)], 110);
+        assert!(data.is_empty());
+        assert_eq!(stats, vec![(1, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn forward_late_points() {
+        let mut t = transform(LatePolicy::Forward);
+        run(&mut t, vec![point(20, 0, 1)], 100);
+        let (data, _) = run(&mut t, vec![point(5, 0, 7)], 101);
+        assert_eq!(data, vec![(5, 7)]);
+    }
+
+    #[test]
+    fn forward_other_metrics() {
+        let mut t = transform(LatePolicy::Drop);
+        let mut other = point(5, 0, 7);
+        other.metric = RawMetricId::from_u64(3);
+        let (data, stats) = run(&mut t, vec![point(10, 0, 1), other], 100);
+        assert!(data.is_empty());
+        assert_eq!(stats, vec![(3, 7), (1, 0), (2, 0)]);
+        assert_eq!(t.series.len(), 1);
+    }
+}
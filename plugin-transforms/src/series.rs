@@ -0,0 +1,60 @@
+//! Identification of the time series that flow through the pipeline.
+
+use alumet::{
+    measurement::{MeasurementPoint, WrappedMeasurementValue},
+    metrics::RawMetricId,
+    resources::{Resource, ResourceConsumer},
+};
+
+/// Identifies a time series: a metric, measured on a resource, for a consumer, with some attributes.
+///
+/// Two points that have the same key belong to the same series.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SeriesKey {
+    pub metric: RawMetricId,
+    pub resource: Resource,
+    pub consumer: ResourceConsumer,
+    /// Attributes as (key, value) strings, sorted by key.
+    attributes: Vec<(String, String)>,
+}
+
+impl SeriesKey {
+    /// Returns the key of the series that the point belongs to.
+    pub fn of(point: &MeasurementPoint) -> Self {
+        let mut attributes: Vec<(String, String)> =
+            point.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect();
+        attributes.sort_unstable();
+        Self {
+            metric: point.metric,
+            resource: point.resource.clone(),
+            consumer: point.consumer.clone(),
+            attributes,
+        }
+    }
+}
+
+/// Creates a new point for the metric `metric`, with the same timestamp, resource,
+/// consumer and attributes as `point`.
+pub fn derived_point(
+    point: &MeasurementPoint,
+    metric: RawMetricId,
+    value: WrappedMeasurementValue,
+) -> MeasurementPoint {
+    let attributes = point.attributes().map(|(k, v)| (k.to_owned(), v.clone())).collect();
+    MeasurementPoint::new_untyped(
+        point.timestamp,
+        metric,
+        point.resource.clone(),
+        point.consumer.clone(),
+        value,
+    )
+    .with_attr_vec(attributes)
+}
+
+/// Returns the value of the measurement as a float.
+pub fn value_as_f64(value: &WrappedMeasurementValue) -> f64 {
+    match value {
+        WrappedMeasurementValue::F64(x) => *x,
+        WrappedMeasurementValue::U64(x) => *x as f64,
+    }
+}
@@ -0,0 +1,126 @@
+//! Detects the series that stop producing measurements.
+
+use std::time::Duration;
+
+use alumet::{
+    measurement::WrappedMeasurementType,
+    plugin::{
+        rust::{deserialize_config, serialize_config, AlumetPlugin},
+        AlumetPluginStart, AlumetPreStart, ConfigTable,
+    },
+    units::Unit,
+};
+use anyhow::{anyhow, Context};
+use fxhash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+use transform::{StalenessSettings, StalenessTransform};
+
+mod transform;
+
+pub struct StalenessPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for StalenessPlugin {
+    fn name() -> &'static str {
+        "staleness"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        if config.factor < 1.0 {
+            return Err(anyhow!("the factor must be at least 1, got {}", config.factor));
+        }
+        Ok(Box::new(StalenessPlugin { config }))
+    }
+
+    fn start(&mut self, _alumet: &mut AlumetPluginStart) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pre_pipeline_start(&mut self, alumet: &mut AlumetPreStart) -> anyhow::Result<()> {
+        let mut metrics = FxHashSet::default();
+        for name in &self.config.metrics {
+            match alumet.metrics().by_name(name) {
+                Some((id, _)) => {
+                    metrics.insert(id);
+                }
+                None => log::warn!("Metric {name} not found, its staleness will not be detected."),
+            }
+        }
+        if !self.config.metrics.is_empty() && metrics.is_empty() {
+            log::warn!("None of the configured metrics exist, the staleness of every metric will be detected.");
+        }
+        let gap_metric = match &self.config.gap_metric {
+            Some(name) => {
+                let id = alumet
+                    .create_metric_untyped(
+                        name,
+                        WrappedMeasurementType::F64,
+                        Unit::Second,
+                        "duration of the gaps in the series, reported when they come back",
+                    )
+                    .with_context(|| format!("could not create metric {name}"))?;
+                Some(id)
+            }
+            None => None,
+        };
+        let transform = StalenessTransform::new(StalenessSettings {
+            metrics,
+            factor: self.config.factor,
+            min_timeout: self.config.min_timeout,
+            forget_after: self.config.forget_after,
+            markers: self.config.markers,
+            gap_metric,
+        });
+        alumet.add_transform("transform", Box::new(transform))?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Metrics to watch. If empty, every metric is watched.
+    #[serde(default)]
+    metrics: Vec<String>,
+    /// A series is stale after `factor` times its usual cadence without any point.
+    factor: f64,
+    /// Minimum time without any point before a series is stale.
+    #[serde(with = "humantime_serde")]
+    min_timeout: Duration,
+    /// A stale series is forgotten after this time.
+    #[serde(with = "humantime_serde")]
+    forget_after: Duration,
+    /// Emit a staleness marker when a series goes quiet.
+    markers: bool,
+    /// Name of the metric that reports the duration of the gaps. If not set, the gaps are not reported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gap_metric: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            metrics: Vec::new(),
+            factor: 3.0,
+            min_timeout: Duration::from_secs(1),
+            forget_after: Duration::from_secs(600),
+            markers: false,
+            gap_metric: Some(String::from("series_gap_duration")),
+        }
+    }
+}
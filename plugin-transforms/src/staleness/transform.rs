@@ -0,0 +1,249 @@
+use std::time::{Duration, SystemTime};
+
+use alumet::{
+    measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue, STALE_MARKER_ATTRIBUTE},
+    metrics::RawMetricId,
+    pipeline::elements::{error::TransformError, transform::TransformContext},
+    pipeline::Transform,
+};
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::series::{derived_point, SeriesKey};
+
+/// Weight of the last interval in the estimation of the cadence.
+const CADENCE_SMOOTHING: f64 = 0.25;
+
+/// Parameters of the staleness detection.
+pub struct StalenessSettings {
+    /// Metrics to watch. If empty, every metric is watched.
+    pub metrics: FxHashSet<RawMetricId>,
+    /// A series is stale after `factor` times its cadence without any point.
+    pub factor: f64,
+    /// Minimum time without any point before a series is stale.
+    pub min_timeout: Duration,
+    /// A stale series is forgotten after this time.
+    pub forget_after: Duration,
+    /// Whether to emit staleness markers.
+    pub markers: bool,
+    /// Metric that reports the duration of the gaps, when the series come back.
+    pub gap_metric: Option<RawMetricId>,
+}
+
+struct SeriesState {
+    /// Last point of the series, used as a template for the markers.
+    last: MeasurementPoint,
+    /// Estimated time between two points, if at least two points have been seen.
+    cadence: Option<Duration>,
+    /// Whether a staleness marker has been emitted since the last point.
+    stale: bool,
+}
+
+/// Detects the series that go quiet.
+///
+/// The cadence of each series is learned from the time between its points.
+/// When a series does not produce any point for `factor` times its cadence, it is considered stale:
+/// a staleness marker is emitted in the series, with the attribute [`STALE_MARKER_ATTRIBUTE`].
+/// When the series comes back, the duration of the gap is reported with the gap metric.
+pub struct StalenessTransform {
+    settings: StalenessSettings,
+    series: FxHashMap<SeriesKey, SeriesState>,
+}
+
+impl StalenessTransform {
+    pub fn new(settings: StalenessSettings) -> Self {
+        Self {
+            settings,
+            series: FxHashMap::default(),
+        }
+    }
+
+    fn process(
+        &mut self,
+        measurements: &mut MeasurementBuffer,
+        now: SystemTime,
+        metric_name: impl Fn(RawMetricId) -> String,
+    ) {
+        let mut gaps = Vec::new();
+        for point in measurements.iter() {
+            if point.is_stale_marker()
+                || (!self.settings.metrics.is_empty() && !self.settings.metrics.contains(&point.metric))
+            {
+                continue;
+            }
+            let key = SeriesKey::of(point);
+            let Some(state) = self.series.get_mut(&key) else {
+                let state = SeriesState {
+                    last: point.clone(),
+                    cadence: None,
+                    stale: false,
+                };
+                self.series.insert(key, state);
+                continue;
+            };
+            let t = SystemTime::from(point.timestamp);
+            let Ok(interval) = t.duration_since(SystemTime::from(state.last.timestamp)) else {
+                // older point, it does not say anything about the current cadence
+                continue;
+            };
+            let timeout = state.cadence.map(|c| timeout(&self.settings, c));
+            let is_gap = state.stale || timeout.is_some_and(|timeout| interval > timeout);
+            if is_gap {
+                if let Some(gap_metric) = self.settings.gap_metric {
+                    let gap = derived_point(point, gap_metric, WrappedMeasurementValue::F64(interval.as_secs_f64()))
+                        .with_attr("metric", metric_name(point.metric));
+                    gaps.push(gap);
+                }
+            } else if !interval.is_zero() {
+                // the gaps are not part of the cadence
+                state.cadence = Some(match state.cadence {
+                    Some(c) => c.mul_f64(1.0 - CADENCE_SMOOTHING) + interval.mul_f64(CADENCE_SMOOTHING),
+                    None => interval,
+                });
+            }
+            state.last = point.clone();
+            state.stale = false;
+        }
+        for gap in gaps {
+            measurements.push(gap);
+        }
+
+        // look for the series that have gone quiet
+        let mut markers = Vec::new();
+        self.series.retain(|_, state| {
+            let Some(cadence) = state.cadence else {
+                return true;
+            };
+            let quiet = now
+                .duration_since(SystemTime::from(state.last.timestamp))
+                .unwrap_or_default();
+            if state.stale {
+                return quiet <= self.settings.forget_after;
+            }
+            if quiet > timeout(&self.settings, cadence) {
+                state.stale = true;
+                if self.settings.markers {
+                    markers.push(marker(&state.last, now));
+                }
+            }
+            true
+        });
+        for m in markers {
+            measurements.push(m);
+        }
+    }
+}
+
+/// Returns how long a series with this cadence can go without any point before being stale.
+fn timeout(settings: &StalenessSettings, cadence: Duration) -> Duration {
+    cadence.mul_f64(settings.factor).max(settings.min_timeout)
+}
+
+/// Creates a staleness marker in the series of `last`.
+fn marker(last: &MeasurementPoint, now: SystemTime) -> MeasurementPoint {
+    let value = match last.value {
+        WrappedMeasurementValue::F64(_) => WrappedMeasurementValue::F64(f64::NAN),
+        WrappedMeasurementValue::U64(_) => WrappedMeasurementValue::U64(0),
+    };
+    let mut marker = derived_point(last, last.metric, value).with_attr(STALE_MARKER_ATTRIBUTE, true);
+    marker.timestamp = Timestamp::from(now);
+    marker
+}
+
+impl Transform for StalenessTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer, ctx: &TransformContext) -> Result<(), TransformError> {
+        let metric_name = |id: RawMetricId| ctx.metrics.by_id(&id).map(|m| m.name.clone()).unwrap_or_default();
+        self.process(measurements, SystemTime::now(), metric_name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use alumet::{
+        measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+        metrics::RawMetricId,
+        resources::{Resource, ResourceConsumer},
+    };
+    use fxhash::FxHashSet;
+
+    use super::{StalenessSettings, StalenessTransform};
+
+    fn time(t_s: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(t_s)
+    }
+
+    fn point(t_s: u64, pid: u32) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::from(time(t_s)),
+            RawMetricId::from_u64(0),
+            Resource::LocalMachine,
+            ResourceConsumer::Process { pid },
+            WrappedMeasurementValue::F64(1.0),
+        )
+    }
+
+    fn transform() -> StalenessTransform {
+        StalenessTransform::new(StalenessSettings {
+            metrics: FxHashSet::default(),
+            factor: 3.0,
+            min_timeout: Duration::from_secs(1),
+            forget_after: Duration::from_secs(60),
+            markers: true,
+            gap_metric: Some(RawMetricId::from_u64(1)),
+        })
+    }
+
+    fn run(t: &mut StalenessTransform, points: Vec<MeasurementPoint>, now: u64) -> MeasurementBuffer {
+        let mut buf = MeasurementBuffer::from(points);
+        t.process(&mut buf, time(now), |id| format!("metric_{}", id.as_u64()));
+        buf
+    }
+
+    fn markers(buf: &MeasurementBuffer) -> Vec<(u32, SystemTime)> {
+        buf.iter()
+            .filter(|p| p.is_stale_marker())
+            .map(|p| match (&p.consumer, &p.value) {
+                (ResourceConsumer::Process { pid }, WrappedMeasurementValue::F64(v)) if v.is_nan() => {
+                    (*pid, SystemTime::from(p.timestamp))
+                }
+                _ => panic!("unexpected marker {p:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn marker_when_series_goes_quiet() {
+        let mut t = transform();
+        let buf = run(&mut t, vec![point(10, 1), point(10, 2), point(12, 1), point(12, 2)], 12);
+        assert!(markers(&buf).is_empty());
+
+        // process 2 stops, its cadence is 2s: it is stale after 6s
+        let buf = run(&mut t, vec![point(14, 1), point(16, 1)], 18);
+        assert!(markers(&buf).is_empty());
+        let buf = run(&mut t, vec![point(18, 1)], 19);
+        assert_eq!(markers(&buf), vec![(2, time(19))]);
+
+        // only one marker per gap
+        let buf = run(&mut t, vec![point(20, 1)], 20);
+        assert!(markers(&buf).is_empty());
+    }
+
+    #[test]
+    fn report_gap_when_series_comes_back() {
+        let mut t = transform();
+        run(&mut t, vec![point(10, 1), point(11, 1)], 11);
+        let buf = run(&mut t, vec![point(30, 1)], 30);
+        let gaps: Vec<_> = buf.iter().filter(|p| p.metric == RawMetricId::from_u64(1)).collect();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].value, WrappedMeasurementValue::F64(19.0));
+        assert!(gaps[0]
+            .attributes()
+            .any(|(k, v)| k == "metric" && matches!(v, AttributeValue::String(name) if name == "metric_0")));
+
+        // the cadence has not been changed by the gap
+        let buf = run(&mut t, vec![point(31, 1)], 35);
+        assert_eq!(markers(&buf), vec![(1, time(35))]);
+    }
+}
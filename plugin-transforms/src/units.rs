@@ -0,0 +1,83 @@
+//! Derivation of units.
+
+use alumet::units::{PrefixedUnit, Unit, UnitPrefix};
+
+/// Returns the unit of the rate of change (per second) of a quantity measured in `unit`.
+///
+/// For instance, the rate of an energy in joules is a power in watts.
+pub fn per_second(unit: &PrefixedUnit) -> PrefixedUnit {
+    match unit.base_unit {
+        Unit::Joule => PrefixedUnit {
+            base_unit: Unit::Watt,
+            prefix: unit.prefix.clone(),
+        },
+        Unit::Second => PrefixedUnit {
+            base_unit: Unit::Unity,
+            prefix: unit.prefix.clone(),
+        },
+        _ => PrefixedUnit {
+            base_unit: Unit::Custom {
+                unique_name: format!("{}/s", unit.unique_name()),
+                display_name: format!("{}/s", unit.display_name()),
+            },
+            prefix: UnitPrefix::Plain,
+        },
+    }
+}
+
+/// Parses a unit given in the configuration, such as `W`, `mJ` or `kiloW.h`.
+///
+/// The units and prefixes are named as in the Unified Code for Units of Measure (UCUM).
+/// A name that is not recognized is turned into a custom unit.
+pub fn parse_unit(name: &str) -> PrefixedUnit {
+    if let Ok(unit) = name.parse::<Unit>() {
+        return unit.into();
+    }
+    const PREFIXES: [&str; 12] = [
+        "nano", "micro", "milli", "kilo", "mega", "giga", "n", "μ", "m", "k", "M", "G",
+    ];
+    for prefix in PREFIXES {
+        if let Some(unit) = name.strip_prefix(prefix).and_then(|rest| rest.parse::<Unit>().ok()) {
+            let prefix = prefix.parse().expect("the prefixes should be valid");
+            return PrefixedUnit {
+                base_unit: unit,
+                prefix,
+            };
+        }
+    }
+    Unit::Custom {
+        unique_name: name.to_owned(),
+        display_name: name.to_owned(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use alumet::units::{PrefixedUnit, Unit};
+
+    use super::{parse_unit, per_second};
+
+    #[test]
+    fn rate_units() {
+        assert_eq!(
+            per_second(&PrefixedUnit::milli(Unit::Joule)),
+            PrefixedUnit::milli(Unit::Watt)
+        );
+        assert_eq!(
+            per_second(&PrefixedUnit::micro(Unit::Second)),
+            PrefixedUnit::micro(Unit::Unity)
+        );
+        let bytes = per_second(&Unit::Byte.into());
+        assert_eq!(bytes.unique_name(), "By/s");
+    }
+
+    #[test]
+    fn parse_units() {
+        assert_eq!(parse_unit("W"), Unit::Watt.into());
+        assert_eq!(parse_unit("1"), Unit::Unity.into());
+        assert_eq!(parse_unit("mJ"), PrefixedUnit::milli(Unit::Joule));
+        assert_eq!(parse_unit("kiloW.h"), PrefixedUnit::kilo(Unit::WattHour));
+        assert_eq!(parse_unit("gCO2eq").unique_name(), "gCO2eq");
+    }
+}